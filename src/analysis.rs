@@ -0,0 +1,201 @@
+use crate::{
+    hardware::{MB_4, MB_8},
+    instruction::{decode_raw_from_bytes, Category, OpCode},
+    Parameter, Tx8Error,
+};
+
+/// The kind of a control flow edge found by `control_flow_edges`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    Jump,
+    ConditionalJump,
+    Call,
+    /// A jump or call to a target computed at runtime, the target of such an edge is always 0
+    Indirect,
+}
+
+/// Walks the code linearly and returns a (source, target, kind) edge for every jump and call.
+/// Addresses are the ones the code has after loading. Bytes which do not decode to an instruction
+/// are skipped.
+pub fn control_flow_edges(code: &[u8]) -> Result<Vec<(u32, u32, EdgeKind)>, Tx8Error> {
+    check_size(code)?;
+    let start = MB_4 as u32;
+    let end = start + code.len() as u32;
+    let mut edges = Vec::new();
+    let mut ptr = start;
+    while ptr < end {
+        let Ok(raw) = decode_raw_from_bytes(code, (ptr - start) as usize) else {
+            ptr += 1;
+            continue;
+        };
+        let kind = match raw.op_code {
+            OpCode::Jump => Some(EdgeKind::Jump),
+            OpCode::JumpEqual
+            | OpCode::JumpNotEqual
+            | OpCode::JumpGreaterThan
+            | OpCode::JumpGreaterEqual
+            | OpCode::JumpLessThan
+            | OpCode::JumpLessEqual
+            | OpCode::Repeat => Some(EdgeKind::ConditionalJump),
+            OpCode::Call => Some(EdgeKind::Call),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            let target = if target_is_second(raw.op_code) {
+                raw.second
+            } else {
+                raw.first
+            };
+            let edge = match target {
+                Parameter::Constant8(target) => (ptr, target as u32, kind),
+                Parameter::Constant16(target) => (ptr, target as u32, kind),
+                Parameter::Constant32(target) => (ptr, target, kind),
+                _ => (ptr, 0, EdgeKind::Indirect),
+            };
+            edges.push(edge);
+        }
+        ptr += raw.len;
+    }
+    Ok(edges)
+}
+
+/// Fails with `RomTooLarge` like `Memory::load_rom` if the code doesn't fit into memory
+pub(crate) fn check_size(code: &[u8]) -> Result<(), Tx8Error> {
+    if code.len() > MB_8 {
+        return Err(Tx8Error::RomTooLarge {
+            size: code.len(),
+            max: MB_8,
+        });
+    }
+    Ok(())
+}
+
+/// Whether the target of a jump or call is its second parameter, `rep` takes its counter first
+/// and the target second
+pub(crate) fn target_is_second(op_code: OpCode) -> bool {
+    matches!(op_code, OpCode::Repeat)
+}
+
+/// Number of instructions of every category found by `instruction_summary`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InstructionSummary {
+    /// Integer arithmetic, logic and bit instructions
+    pub arithmetic: u32,
+    /// Instructions operating on floats, including conversions from and to integers
+    pub float: u32,
+    /// Jumps, calls, returns and the halts, including the conditional ones like `hlteq`
+    pub control_flow: u32,
+    pub sys_call: u32,
+    /// Everything else, e.g. loads, stack operations and integer comparisons
+    pub other: u32,
+    /// Bytes which do not decode to an instruction
+    pub invalid_bytes: u32,
+}
+
+/// Walks the code linearly like `control_flow_edges` and counts the instructions by category
+pub fn instruction_summary(code: &[u8]) -> Result<InstructionSummary, Tx8Error> {
+    check_size(code)?;
+    let start = MB_4 as u32;
+    let end = start + code.len() as u32;
+    let mut summary = InstructionSummary::default();
+    let mut ptr = start;
+    while ptr < end {
+        let Ok(raw) = decode_raw_from_bytes(code, (ptr - start) as usize) else {
+            summary.invalid_bytes += 1;
+            ptr += 1;
+            continue;
+        };
+        let counter = match raw.op_code.info().category {
+            Category::Arithmetic => &mut summary.arithmetic,
+            Category::Float => &mut summary.float,
+            Category::ControlFlow => &mut summary.control_flow,
+            Category::SysCall => &mut summary.sys_call,
+            Category::Other => &mut summary.other,
+        };
+        *counter += 1;
+        ptr += raw.len;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EXTENSION_PREFIX;
+
+    // call 0x40000d; jne 0x400000; hlt; ret; jmp a
+    const CODE: [u8; 17] = [
+        0x0c, 0x30, 0x0d, 0x00, 0x40, 0x00, 0x04, 0x30, 0x00, 0x00, 0x40, 0x00, 0x00, 0x0d, 0x02,
+        0x60, 0x00,
+    ];
+
+    #[test]
+    fn finds_the_edges_of_a_loop_and_a_call() {
+        assert_eq!(
+            control_flow_edges(&CODE).unwrap(),
+            [
+                (0x400000, 0x40000d, EdgeKind::Call),
+                (0x400006, 0x400000, EdgeKind::ConditionalJump),
+                (0x40000e, 0, EdgeKind::Indirect),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_bytes_which_are_no_instruction() {
+        let mut code = vec![0xfe];
+        code.extend(CODE);
+        let edges = control_flow_edges(&code).unwrap();
+        assert_eq!(edges.len(), 3);
+        assert_eq!(edges[0], (0x400001, 0x40000d, EdgeKind::Call));
+    }
+
+    #[test]
+    fn counts_conditional_halts_as_control_flow() {
+        // hlteq; add a 1u8; fadd a b; ld a b; sys 0u8; 0xfe
+        let code = [
+            0x98, 0x22, 0x61, 0x00, 0x01, 0x42, 0x66, 0x00, 0x01, 0x10, 0x66, 0x00, 0x01, 0x0e,
+            0x10, 0x00, 0xfe,
+        ];
+        let summary = instruction_summary(&code).unwrap();
+        assert_eq!(
+            summary,
+            InstructionSummary {
+                arithmetic: 1,
+                float: 1,
+                control_flow: 1,
+                sys_call: 1,
+                other: 1,
+                invalid_bytes: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_extended_op_codes_from_their_metadata() {
+        // xnop; xadd a 1u8; utf a; ret
+        let code = [
+            EXTENSION_PREFIX,
+            0x00,
+            0xa0,
+            0x61,
+            0x00,
+            0x01,
+            0x74,
+            0x60,
+            0x00,
+            0x0d,
+        ];
+        let summary = instruction_summary(&code).unwrap();
+        assert_eq!(
+            summary,
+            InstructionSummary {
+                arithmetic: 1,
+                float: 1,
+                control_flow: 1,
+                other: 1,
+                ..InstructionSummary::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    cell::RefCell,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{parse_rom, Effect, Execution, Tx8Error};
+
+/// The outcome of a program run by `run_conformance`
+#[derive(Clone, Debug)]
+pub struct RunResult {
+    /// Everything the program printed, including the diagnostics of the `test_*` system calls
+    pub output: Vec<u8>,
+    /// Why the program stopped if it did not halt, including errors parsing the rom
+    pub error: Option<Tx8Error>,
+    pub instruction_count: u64,
+}
+
+/// A rom whose output differs from its expected output
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub rom: PathBuf,
+    /// Offset of the first differing byte, the length of the shorter output if one is a prefix of
+    /// the other
+    pub offset: usize,
+    pub error: Option<Tx8Error>,
+}
+
+// collects the output while the execution owns the writer
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(pub(crate) Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs a rom file until it halts or fails and collects its output. The random number generator
+/// always starts with the same seed, so the result only depends on the rom and the input.
+pub fn run_conformance(rom: &[u8], input: &[u8]) -> RunResult {
+    let output = SharedBuffer::default();
+    let (error, instruction_count) = match run(rom, input, output.clone()) {
+        Ok(count) => (None, count),
+        Err((error, count)) => (Some(error), count),
+    };
+    let output = output.0.take();
+    RunResult {
+        output,
+        error,
+        instruction_count,
+    }
+}
+
+fn run(rom: &[u8], input: &[u8], output: SharedBuffer) -> Result<u64, (Tx8Error, u64)> {
+    let rom = parse_rom(rom).map_err(|error| (error, 0))?;
+    let mut execution =
+        Execution::new_with_rom_and_input(&rom, input.to_vec()).map_err(|error| (error, 0))?;
+    execution.set_output(Box::new(output.clone()));
+    execution.set_error_output(Box::new(output));
+    loop {
+        match execution.next_step() {
+            Ok(Effect::Halted(_)) => return Ok(execution.instruction_count()),
+            Ok(_) => (),
+            Err(error) => return Err((error, execution.instruction_count())),
+        }
+    }
+}
+
+/// Runs every `.txr` rom in `dir` which has an `.expected` file next to it and compares the
+/// output. Input is read from an `.input` file if there is one. A rom passes if its output matches
+/// and it halted without an error.
+pub fn run_conformance_dir(dir: &Path) -> io::Result<Vec<Mismatch>> {
+    let mut roms = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    roms.retain(|path| path.extension().is_some_and(|ext| ext == "txr"));
+    roms.sort();
+
+    let mut mismatches = Vec::new();
+    for rom in roms {
+        let expected_path = rom.with_extension("expected");
+        if !expected_path.exists() {
+            continue;
+        }
+        let expected = std::fs::read(expected_path)?;
+        let input_path = rom.with_extension("input");
+        let input = if input_path.exists() {
+            std::fs::read(input_path)?
+        } else {
+            Vec::new()
+        };
+        let result = run_conformance(&std::fs::read(&rom)?, &input);
+        let offset = first_difference(&result.output, &expected);
+        if offset.is_some() || result.error.is_some() {
+            mismatches.push(Mismatch {
+                rom,
+                offset: offset.unwrap_or(expected.len()),
+                error: result.error,
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(offset) => Some(offset),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{execution::hash, RomBuilder};
+
+    // push val; sys print_u32; hlt
+    fn printing_rom(val: u32) -> Vec<u8> {
+        let mut code = vec![0x1d, 0x30];
+        code.extend(val.to_le_bytes());
+        code.extend([0x0e, 0x30]);
+        code.extend(hash("print_u32").to_le_bytes());
+        code.push(0x00);
+        RomBuilder::new(&code).build().unwrap()
+    }
+
+    #[test]
+    fn collects_the_output_of_a_run() {
+        let result = run_conformance(&printing_rom(42), b"");
+        assert_eq!(result.output, b"42");
+        assert_eq!(result.error, None);
+        assert_eq!(result.instruction_count, 3);
+        assert_eq!(
+            run_conformance(b"junk", b"").error,
+            Some(Tx8Error::ParseError)
+        );
+    }
+
+    #[test]
+    fn reports_the_first_differing_byte_of_each_rom() {
+        let dir = std::env::temp_dir().join(format!("tx8-conformance-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files: [(&str, &[u8], &[u8]); 4] = [
+            ("broken", b"junk", b""),
+            ("pass", &printing_rom(42), b"42"),
+            ("short", &printing_rom(42), b"421"),
+            ("wrong", &printing_rom(42), b"43"),
+        ];
+        for (name, rom, expected) in files {
+            std::fs::write(dir.join(format!("{name}.txr")), rom).unwrap();
+            std::fs::write(dir.join(format!("{name}.expected")), expected).unwrap();
+        }
+        // roms without expected output are skipped
+        std::fs::write(dir.join("skipped.txr"), b"junk").unwrap();
+
+        let mismatches = run_conformance_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let found: Vec<(PathBuf, usize, Option<Tx8Error>)> = mismatches
+            .into_iter()
+            .map(|mismatch| (mismatch.rom, mismatch.offset, mismatch.error))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (dir.join("broken.txr"), 0, Some(Tx8Error::ParseError)),
+                (dir.join("short.txr"), 2, None),
+                (dir.join("wrong.txr"), 1, None),
+            ]
+        );
+    }
+}
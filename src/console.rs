@@ -0,0 +1,126 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::MmioHandler;
+
+/// Address of the console device mapped by `Execution::map_console`
+pub const CONSOLE_ADDRESS: u32 = 0x001000;
+/// Number of bytes the console device occupies
+pub const CONSOLE_SIZE: u32 = 5;
+
+const CURSOR_X: u32 = 0;
+const CURSOR_Y: u32 = 1;
+const FOREGROUND: u32 = 2;
+const BACKGROUND: u32 = 3;
+const CHARACTER: u32 = 4;
+
+/// A text console controlled through byte registers, which turns register writes into ANSI
+/// escape sequences. The bytes are collected in `pending` until the execution writes them to its
+/// output.
+pub(crate) struct Console {
+    pending: Rc<RefCell<Vec<u8>>>,
+    x: u8,
+    y: u8,
+    foreground: u8,
+    background: u8,
+}
+
+impl Console {
+    pub(crate) fn new(pending: Rc<RefCell<Vec<u8>>>) -> Self {
+        Console {
+            pending,
+            x: 0,
+            y: 0,
+            foreground: 0,
+            background: 0,
+        }
+    }
+
+    fn write_register(&mut self, offset: u32, val: u8) {
+        let escape = match offset {
+            CURSOR_X | CURSOR_Y => {
+                match offset {
+                    CURSOR_X => self.x = val,
+                    _ => self.y = val,
+                }
+                // the terminal counts from 1
+                format!("\x1b[{};{}H", self.y as u32 + 1, self.x as u32 + 1)
+            }
+            FOREGROUND => {
+                self.foreground = val;
+                format!("\x1b[38;5;{val}m")
+            }
+            BACKGROUND => {
+                self.background = val;
+                format!("\x1b[48;5;{val}m")
+            }
+            CHARACTER => {
+                // the terminal moves the cursor along
+                self.x = self.x.wrapping_add(1);
+                self.pending.borrow_mut().push(val);
+                return;
+            }
+            _ => return,
+        };
+        self.pending
+            .borrow_mut()
+            .extend_from_slice(escape.as_bytes());
+    }
+
+    fn read_register(&self, offset: u32) -> u8 {
+        match offset {
+            CURSOR_X => self.x,
+            CURSOR_Y => self.y,
+            FOREGROUND => self.foreground,
+            BACKGROUND => self.background,
+            _ => 0,
+        }
+    }
+}
+
+impl MmioHandler for Console {
+    fn read(&mut self, addr: u32, size: u32) -> u32 {
+        let bytes: Vec<u8> = (0..size)
+            .map(|i| self.read_register(addr + i - CONSOLE_ADDRESS))
+            .collect();
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |acc, byte| acc << 8 | *byte as u32)
+    }
+
+    // wider writes set several registers at once, starting with the lowest address
+    fn write(&mut self, addr: u32, size: u32, val: u32) {
+        for (i, byte) in (0..size).zip(val.to_le_bytes()) {
+            self.write_register(addr + i - CONSOLE_ADDRESS, byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(console: &Console) -> String {
+        String::from_utf8(console.pending.take()).unwrap()
+    }
+
+    #[test]
+    fn cursor_writes_emit_the_move_escape() {
+        let mut console = Console::new(Rc::default());
+        console.write(CONSOLE_ADDRESS + CURSOR_Y, 1, 3);
+        assert_eq!(written(&console), "\x1b[4;1H");
+        console.write(CONSOLE_ADDRESS + CURSOR_X, 1, 9);
+        assert_eq!(written(&console), "\x1b[4;10H");
+        assert_eq!(console.read(CONSOLE_ADDRESS, 2), 0x0309);
+    }
+
+    #[test]
+    fn characters_advance_the_cursor() {
+        let mut console = Console::new(Rc::default());
+        console.write(CONSOLE_ADDRESS + FOREGROUND, 2, 0x0401);
+        assert_eq!(written(&console), "\x1b[38;5;1m\x1b[48;5;4m");
+        console.write(CONSOLE_ADDRESS + CHARACTER, 1, b'x' as u32);
+        assert_eq!(written(&console), "x");
+        assert_eq!(console.read(CONSOLE_ADDRESS + CURSOR_X, 1), 1);
+    }
+}
@@ -0,0 +1,59 @@
+/// Running state of a CRC-32 (IEEE 802.3, as used by zip and png) computation, for data which is
+/// not available as one slice
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32(0xffffffff)
+    }
+}
+
+impl Crc32 {
+    /// Adds the bytes to the checksum
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                // 0xedb88320 is the reversed polynomial 0x04c11db7
+                self.0 = if self.0 & 1 != 0 {
+                    (self.0 >> 1) ^ 0xedb88320
+                } else {
+                    self.0 >> 1
+                };
+            }
+        }
+    }
+
+    /// The CRC-32 of all bytes passed to `update`
+    pub fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// CRC-32 (IEEE 802.3, as used by zip and png) of the given bytes
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::default();
+    crc.update(data);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn updates_in_pieces_match_one_update() {
+        let mut crc = Crc32::default();
+        for byte in b"123456789" {
+            crc.update(&[*byte]);
+        }
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+}
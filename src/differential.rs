@@ -0,0 +1,115 @@
+use crate::{conformance::SharedBuffer, parse_rom, Effect, Execution, StateDiff, Tx8Error};
+
+/// The first step in which the two executions of `diff_run` behaved differently
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// Number of instructions both executions completed identically before diverging
+    pub step: u64,
+    pub left: StepOutcome,
+    pub right: StepOutcome,
+}
+
+/// What a single step of an execution did
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepOutcome {
+    /// The effect and the changed registers and memory, or the error the step failed with
+    pub result: Result<(Effect, StateDiff), Tx8Error>,
+    /// Bytes printed during the step, including diagnostics
+    pub output: Vec<u8>,
+}
+
+/// Runs a rom in two executions, which are set up by `left` and `right` after loading, and
+/// compares the effect, the changed state and the output of every step. Both run until they halt
+/// or fail the same way. Programs which do not halt need a budget set by both configurations.
+/// The rom failing to parse or load is reported as a divergence in step 0 with the same error on
+/// both sides.
+pub fn diff_run(
+    rom: &[u8],
+    input: &[u8],
+    left: impl FnOnce(&mut Execution),
+    right: impl FnOnce(&mut Execution),
+) -> Result<(), Box<Divergence>> {
+    let (mut left, left_output) = prepare(rom, input, left)?;
+    let (mut right, right_output) = prepare(rom, input, right)?;
+    let mut step = 0;
+    loop {
+        let left_outcome = next_outcome(&mut left, &left_output);
+        let right_outcome = next_outcome(&mut right, &right_output);
+        if left_outcome != right_outcome {
+            return Err(Box::new(Divergence {
+                step,
+                left: left_outcome,
+                right: right_outcome,
+            }));
+        }
+        match left_outcome.result {
+            Ok((Effect::Halted(_), _)) | Err(_) => return Ok(()),
+            Ok(_) => step += 1,
+        }
+    }
+}
+
+fn prepare<'a>(
+    rom: &[u8],
+    input: &[u8],
+    configure: impl FnOnce(&mut Execution<'a>),
+) -> Result<(Execution<'a>, SharedBuffer), Box<Divergence>> {
+    let failed = |error| {
+        let outcome = StepOutcome {
+            result: Err(error),
+            output: Vec::new(),
+        };
+        Box::new(Divergence {
+            step: 0,
+            left: outcome.clone(),
+            right: outcome,
+        })
+    };
+    let rom = parse_rom(rom).map_err(failed)?;
+    let mut execution = Execution::new_with_rom_and_input(&rom, input.to_vec()).map_err(failed)?;
+    let output = SharedBuffer::default();
+    execution.set_output(Box::new(output.clone()));
+    execution.set_error_output(Box::new(output.clone()));
+    configure(&mut execution);
+    Ok((execution, output))
+}
+
+fn next_outcome(execution: &mut Execution, output: &SharedBuffer) -> StepOutcome {
+    let result = execution.step_with_diff();
+    StepOutcome {
+        result,
+        output: output.0.take(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Register, RomBuilder};
+
+    #[test]
+    fn reports_the_first_diverging_step() {
+        // inc b, ld c a, hlt
+        let code = [0x20, 0x60, 0x01, 0x10, 0x66, 0x02, 0x00, 0x00];
+        let rom = RomBuilder::new(&code).build().unwrap();
+        assert!(diff_run(&rom, &[], |_| {}, |_| {}).is_ok());
+
+        let divergence = diff_run(
+            &rom,
+            &[],
+            |_| {},
+            |execution| execution.set_register(Register::A, 5),
+        )
+        .unwrap_err();
+        assert_eq!(divergence.step, 1);
+        assert!(divergence.left.result.is_ok());
+        assert_ne!(divergence.left, divergence.right);
+    }
+
+    #[test]
+    fn roms_failing_to_parse_diverge_in_the_first_step() {
+        let divergence = diff_run(b"not a rom", &[], |_| {}, |_| {}).unwrap_err();
+        assert_eq!(divergence.step, 0);
+        assert_eq!(divergence.left.result, Err(Tx8Error::ParseError));
+    }
+}
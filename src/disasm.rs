@@ -0,0 +1,203 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    ops::Range,
+};
+
+use crate::{
+    analysis::{check_size, control_flow_edges, target_is_second, EdgeKind},
+    hardware::MB_4,
+    instruction::{decode_instruction, decode_raw_from_bytes, RawInstruction},
+    Memory, Parameter, Tx8Error,
+};
+
+const RESET: &str = "\x1b[0m";
+const OP_CODE_COLOR: &str = "\x1b[1;33m";
+const REGISTER_COLOR: &str = "\x1b[36m";
+const CONSTANT_COLOR: &str = "\x1b[35m";
+const ADDRESS_COLOR: &str = "\x1b[32m";
+
+/// Decodes the instruction at `ptr` and renders it in assembly syntax, e.g. `add a 5u8`, together
+/// with its length. Constants are shown as encoded, registers and memory are not read. With
+/// `colored`, opcodes, registers, constants and addresses are wrapped in ANSI escape codes.
+pub fn disassemble_at(mem: &Memory, ptr: u32, colored: bool) -> Result<(String, u32), Tx8Error> {
+    let raw = decode_instruction(mem, ptr)?;
+    Ok((format_raw(&raw, colored, None), raw.len))
+}
+
+/// Options for the listing created by `disassemble`
+#[derive(Clone, Debug, Default)]
+pub struct DisasmOptions {
+    /// Show the encoded bytes of every instruction
+    pub raw_bytes: bool,
+    /// Name the targets of jumps and calls with constant targets `:l<address>` and use the names
+    /// as operands
+    pub labels: bool,
+    /// Only list instructions starting in this address range
+    pub range: Option<Range<u32>>,
+    pub colored: bool,
+    /// Source lines of offsets into the code like `Rom::lines`, shown as `; line 42` after the
+    /// instructions starting at these offsets
+    pub lines: Vec<(u32, u32)>,
+}
+
+/// Creates a listing of the code in assembly syntax, one instruction per line prefixed with its
+/// address after loading. The code is decoded linearly, bytes which do not decode to an
+/// instruction are listed as comments.
+pub fn disassemble(code: &[u8], options: &DisasmOptions) -> Result<String, Tx8Error> {
+    check_size(code)?;
+    let targets: HashMap<u32, u32> = if options.labels {
+        control_flow_edges(code)?
+            .into_iter()
+            .filter(|(_, _, kind)| *kind != EdgeKind::Indirect)
+            .map(|(source, target, _)| (source, target))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let labels: HashSet<u32> = targets.values().copied().collect();
+    let start = MB_4 as u32;
+    let end = start + code.len() as u32;
+    let lines: HashMap<u32, u32> = options
+        .lines
+        .iter()
+        .map(|&(offset, line)| (start + offset, line))
+        .collect();
+    let mut listing = String::new();
+    let mut ptr = start;
+    while ptr < end {
+        let offset = (ptr - start) as usize;
+        let (text, len) = match decode_raw_from_bytes(code, offset) {
+            Ok(raw) => {
+                let label = targets.get(&ptr).map(|target| format!(":l{target:x}"));
+                (format_raw(&raw, options.colored, label.as_deref()), raw.len)
+            }
+            Err(_) => (format!("; invalid byte {:#04x}", code[offset]), 1),
+        };
+        if options
+            .range
+            .as_ref()
+            .is_some_and(|range| !range.contains(&ptr))
+        {
+            ptr += len;
+            continue;
+        }
+        if labels.contains(&ptr) {
+            let _ = writeln!(listing, ":l{ptr:x}");
+        }
+        let _ = write!(listing, "{ptr:06x}  ");
+        if options.raw_bytes {
+            // instructions are at most 10 bytes long, which need 29 characters
+            let bytes: Vec<String> = code[offset..offset + len as usize]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            let _ = write!(listing, "{:<30}", bytes.join(" "));
+        }
+        let _ = write!(listing, "{text}");
+        if let Some(line) = lines.get(&ptr) {
+            let _ = write!(listing, "  ; line {line}");
+        }
+        listing.push('\n');
+        ptr += len;
+    }
+    Ok(listing)
+}
+
+fn format_raw(raw: &RawInstruction, colored: bool, target_label: Option<&str>) -> String {
+    let mut text = paint(raw.op_code.mnemonic(), OP_CODE_COLOR, colored);
+    let target = usize::from(target_is_second(raw.op_code));
+    for (index, par) in [raw.first, raw.second].into_iter().enumerate() {
+        if let Some(label) = target_label.filter(|_| index == target) {
+            text.push(' ');
+            text.push_str(&paint(label, ADDRESS_COLOR, colored));
+            continue;
+        }
+        let color = match par {
+            Parameter::Unused => continue,
+            Parameter::Constant8(_) | Parameter::Constant16(_) | Parameter::Constant32(_) => {
+                CONSTANT_COLOR
+            }
+            Parameter::AbsoluteAddress(_) | Parameter::RelativeAddress(_) => ADDRESS_COLOR,
+            Parameter::Register(_) | Parameter::RegisterAddress(_) => REGISTER_COLOR,
+        };
+        text.push(' ');
+        text.push_str(&paint(&par.describe(), color, colored));
+    }
+    text
+}
+
+fn paint(token: &str, color: &str, colored: bool) -> String {
+    if colored {
+        format!("{color}{token}{RESET}")
+    } else {
+        token.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_replace_only_the_target_of_rep() {
+        // rep c 0x400000u32; hlt
+        let code = [0x9f, 0x63, 0x02, 0x00, 0x00, 0x40, 0x00, 0x00];
+        let options = DisasmOptions {
+            labels: true,
+            ..Default::default()
+        };
+        let listing = disassemble(&code, &options).unwrap();
+        assert!(listing.contains("400000  rep c :l400000\n"), "{listing}");
+    }
+
+    #[test]
+    fn labels_replace_the_target_of_jumps() {
+        // jmp 0x400000u32
+        let code = [0x02, 0x30, 0x00, 0x00, 0x40, 0x00];
+        let options = DisasmOptions {
+            labels: true,
+            ..Default::default()
+        };
+        let listing = disassemble(&code, &options).unwrap();
+        assert_eq!(listing, ":l400000\n400000  jmp :l400000\n");
+    }
+
+    #[test]
+    fn only_colored_listings_contain_escape_sequences() {
+        // add a 5u8; jmp #400000; hlt
+        let code = [0x22, 0x61, 0x00, 0x05, 0x02, 0x40, 0x00, 0x00, 0x40, 0x00];
+        let mut options = DisasmOptions::default();
+        let plain = disassemble(&code, &options).unwrap();
+        assert!(!plain.contains('\x1b'), "{plain}");
+        options.colored = true;
+        let colored = disassemble(&code, &options).unwrap();
+        assert!(colored.contains(OP_CODE_COLOR), "{colored}");
+
+        let mem = Memory::load_rom(&code, &[]).unwrap();
+        let (text, len) = disassemble_at(&mem, MB_4 as u32, false).unwrap();
+        assert_eq!((text.as_str(), len), ("add a 5u8", 4));
+    }
+
+    #[test]
+    fn listings_annotate_the_lines_of_the_debug_table() {
+        // ld a 5u8; inc a; hlt
+        let code = [0x10, 0x61, 0x00, 0x05, 0x20, 0x60, 0x00, 0x00];
+        let bytes = crate::RomBuilder::new(&code)
+            .line(7, 4)
+            .line(0, 2)
+            .build()
+            .unwrap();
+        let rom = crate::parse_rom(&bytes).unwrap();
+        assert_eq!(rom.lines, [(0, 2), (7, 4)]);
+        let options = DisasmOptions {
+            lines: rom.lines.clone(),
+            ..Default::default()
+        };
+        let listing = disassemble(rom.code, &options).unwrap();
+        assert_eq!(
+            listing,
+            "400000  ld a 5u8  ; line 2\n400004  inc a\n400007  hlt  ; line 4\n"
+        );
+    }
+}
@@ -1,14 +1,43 @@
-use std::{array::TryFromSliceError, error::Error, fmt::Display, str::Utf8Error};
-#[derive(Clone, Copy, Debug)]
+use std::{
+    array::TryFromSliceError, error::Error, fmt::Display, num::TryFromIntError, str::Utf8Error,
+};
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Tx8Error {
     ParseError,
     InstructionError,
-    OutOfBoundsWrite,
-    InvalidRegister,
+    OutOfBoundsWrite(u32),
+    InvalidRegister(u8),
     InvalidSysCall,
     InvalidOpCode(u8),
+    /// The byte following the extension prefix selects no opcode of the extension table
+    InvalidExtendedOpCode(u8),
     DivisionByZero,
     NoInputGiven,
+    OutputError,
+    UninitializedRead,
+    SectionOverlap,
+    ArithmeticOverflow,
+    ChecksumMismatch,
+    TrailingData,
+    BudgetExhausted,
+    OutputLimitReached,
+    SelfModifyingCode,
+    InvalidRelocation(u32),
+    OffsetOutOfBounds,
+    StackOverflow,
+    StackUnderflow,
+    MissingSysCallArgument(u32),
+    ReadViolation(u32),
+    WriteViolation(u32),
+    ExecuteViolation(u32),
+    NullPointerAccess(u32),
+    /// A value which is no unicode scalar value, e.g. a surrogate, was printed as a character
+    InvalidCodepoint(u32),
+    /// The code of a rom is `size` bytes long, more than the `max` bytes which can be loaded
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
 }
 
 impl Error for Tx8Error {}
@@ -17,6 +46,21 @@ impl Display for Tx8Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Tx8Error::InvalidOpCode(op) => write!(f, "InvalidOpCode({:x})", op),
+            Tx8Error::InvalidExtendedOpCode(op) => write!(f, "InvalidExtendedOpCode({:x})", op),
+            Tx8Error::InvalidRegister(byte) => write!(f, "InvalidRegister({:x})", byte),
+            Tx8Error::OutOfBoundsWrite(addr) => write!(f, "OutOfBoundsWrite({:x})", addr),
+            Tx8Error::InvalidRelocation(offset) => write!(f, "InvalidRelocation({:x})", offset),
+            Tx8Error::MissingSysCallArgument(s) => write!(f, "MissingSysCallArgument({:x})", s),
+            Tx8Error::ReadViolation(addr) => write!(f, "ReadViolation({:x})", addr),
+            Tx8Error::WriteViolation(addr) => write!(f, "WriteViolation({:x})", addr),
+            Tx8Error::ExecuteViolation(addr) => write!(f, "ExecuteViolation({:x})", addr),
+            Tx8Error::NullPointerAccess(addr) => write!(f, "NullPointerAccess({:x})", addr),
+            Tx8Error::InvalidCodepoint(val) => write!(f, "InvalidCodepoint({:x})", val),
+            Tx8Error::RomTooLarge { size, max } => write!(
+                f,
+                "RomTooLarge(the code has {size} bytes, {} more than the maximum of {max})",
+                size.saturating_sub(*max)
+            ),
             _ => write!(f, "{:?}", self),
         }
     }
@@ -32,3 +76,47 @@ impl From<Utf8Error> for Tx8Error {
         Tx8Error::ParseError
     }
 }
+impl From<TryFromIntError> for Tx8Error {
+    fn from(_value: TryFromIntError) -> Self {
+        Tx8Error::ParseError
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_too_large_reports_both_sizes() {
+        let error = Tx8Error::RomTooLarge { size: 12, max: 10 };
+        assert_eq!(
+            error.to_string(),
+            "RomTooLarge(the code has 12 bytes, 2 more than the maximum of 10)"
+        );
+        // sizes which fit are no error, but still display
+        let error = Tx8Error::RomTooLarge { size: 3, max: 10 };
+        assert!(error.to_string().contains("0 more"));
+    }
+
+    #[test]
+    fn messages_contain_the_offending_value() {
+        for (error, message) in [
+            (Tx8Error::InvalidOpCode(0xab), "InvalidOpCode(ab)"),
+            (
+                Tx8Error::InvalidExtendedOpCode(0x7f),
+                "InvalidExtendedOpCode(7f)",
+            ),
+            (Tx8Error::InvalidRegister(0x38), "InvalidRegister(38)"),
+            (
+                Tx8Error::OutOfBoundsWrite(0xfffff0),
+                "OutOfBoundsWrite(fffff0)",
+            ),
+            (Tx8Error::ReadViolation(0xc01000), "ReadViolation(c01000)"),
+            (Tx8Error::NullPointerAccess(0x10), "NullPointerAccess(10)"),
+            (Tx8Error::InvalidCodepoint(0xd800), "InvalidCodepoint(d800)"),
+            (Tx8Error::DivisionByZero, "DivisionByZero"),
+        ] {
+            assert_eq!(error.to_string(), message);
+        }
+    }
+}
@@ -1,24 +1,149 @@
-use std::{collections::HashMap, io::Read, ops::Neg};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{vec_deque, HashMap, VecDeque},
+    fmt::Display,
+    io::Write as _,
+    ops::Neg,
+    rc::Rc,
+};
 
 use crate::{
-    hardware::{Cpu, Memory},
+    console::{Console, CONSOLE_ADDRESS, CONSOLE_SIZE},
+    crc::Crc32,
+    hardware::{format_reg, Cpu, Memory, MmioHandler, Register, MB_16, STACK_BOTTOM, STACK_TOP},
     instruction::{parse_instruction, Comparison, Instruction, Type},
-    parameter::{Size, Value, Writable, Write},
+    parameter::{Parameter, Size, Value, Writable, Write},
     random::*,
-    Tx8Error,
+    CycleCosts, DataSection, Rom, Tx8Error,
 };
 
-#[derive(Clone, Debug)]
+/// Value of `R` after comparing floats of which at least one is NaN
+const UNORDERED: i32 = 2;
+
+/// Most decimal places `print_f32_prec` prints
+const MAX_PRECISION: u32 = 100;
+
+/// Most bytes of a number `parse_int` and `parse_float` read, longer tokens are no valid number
+const MAX_NUMBER_LENGTH: usize = 64;
+
+/// The quiet NaN every NaN result is replaced with if canonicalization is enabled
+const CANONICAL_NAN: u32 = 0x7fc00000;
+
+pub type RegisterCallback = Box<dyn FnMut(Register, u32, u32)>;
+
+pub type TrapHandler = Box<dyn FnMut(TrapKind, &mut Cpu, &mut Memory) -> TrapAction>;
+
+/// The state changed by a single instruction
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// Changed registers with their old and new value
+    pub registers: Vec<(Register, u32, u32)>,
+    /// Changed memory bytes with their address, old and new value
+    pub memory: Vec<(u32, u8, u8)>,
+    /// Program counter after the step
+    pub pc: u32,
+}
+
+impl std::fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the program counter is printed last
+        for (reg, old, new) in self
+            .registers
+            .iter()
+            .filter(|(reg, _, _)| *reg != Register::P)
+        {
+            let (old, new) = (format_reg(*old, true), format_reg(*new, true));
+            writeln!(f, "{reg:?}: {old} -> {new}")?;
+        }
+        for (address, old, new) in &self.memory {
+            writeln!(f, "#{address:06x}: {old:#04x} -> {new:#04x}")?;
+        }
+        write!(f, "P: {}", format_reg(self.pc, false))
+    }
+}
+
 pub struct Execution<'a> {
     cpu: Cpu,
     memory: Memory,
     sys_call_map: HashMap<u32, &'a str>,
     rand: Rand,
     input: std::vec::IntoIter<u8>,
+    // the input as it was set, restored by `reset`
+    initial_input: Vec<u8>,
+    output: Box<dyn std::io::Write>,
+    error_output: Box<dyn std::io::Write>,
+    // bytes the console device emitted during the current instruction
+    console: Option<Rc<RefCell<Vec<u8>>>>,
+    instruction_count: u64,
+    cycle_costs: CycleCosts,
+    simulated_cycles: u64,
+    lenient_sys_calls: bool,
+    trap_overflow: bool,
+    canonical_nan: bool,
+    pause_on_sys_call: bool,
+    // the next step performs a pending system call instead of pausing again
+    sys_call_resumed: bool,
+    register_watchers: Vec<(Register, RegisterCallback)>,
+    trap_handler: Option<TrapHandler>,
+    budget: Option<u64>,
+    initial_budget: Option<u64>,
+    output_limit: Option<u64>,
+    initial_output_limit: Option<u64>,
+    // whether execution outside of the code images should stop the program
+    check_code_bounds: bool,
+    // where execution starts, also after a reset
+    entry: u32,
+    sys_call_abi: SysCallAbi,
+    // the most recently executed instructions, oldest first
+    replay_log: VecDeque<(u32, Instruction)>,
+    replay_capacity: usize,
+}
+
+impl std::fmt::Debug for Execution<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Execution")
+            .field("cpu", &self.cpu)
+            .field("rand", &self.rand)
+            .field("instruction_count", &self.instruction_count)
+            .field("simulated_cycles", &self.simulated_cycles)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<'a> Execution<'a> {
-    pub fn new_with_rom(data: &[u8]) -> Result<Self, Tx8Error> {
+    pub fn new_with_rom(rom: &Rom) -> Result<Self, Tx8Error> {
+        Self::new_with_rom_at(rom, rom.entry_point())
+    }
+
+    /// Loads several code images into one memory, each at its base address, and starts executing
+    /// at `entry`. The images must not overlap and must lie within memory, see
+    /// `Memory::load_rom_at`. Every image counts as code for the code bounds check and code
+    /// protection.
+    pub fn new_with_images(images: &[(&[u8], u32)], entry: u32) -> Result<Self, Tx8Error> {
+        let Some(((code, base), rest)) = images.split_first() else {
+            return Err(Tx8Error::ParseError);
+        };
+        let mut execution = Self::new_with_rom_at(&Rom::from_code(code), *base)?;
+        for (data, base) in rest {
+            execution.memory.load_rom_at(data, *base)?;
+        }
+        execution.cpu.p = entry;
+        execution.entry = entry;
+        Ok(execution)
+    }
+
+    /// Like `new_with_rom`, with the bytes the input system calls return. Executions never read
+    /// stdin themselves, the input is empty unless it is given here or with `set_input`.
+    pub fn new_with_rom_and_input(rom: &Rom, input: Vec<u8>) -> Result<Self, Tx8Error> {
+        let mut execution = Self::new_with_rom(rom)?;
+        execution.set_input(input);
+        Ok(execution)
+    }
+
+    /// Loads the code at `base` instead of the usual code address and starts executing there.
+    /// The addresses listed in the relocation table of the rom are adjusted accordingly.
+    pub fn new_with_rom_at(rom: &Rom, base: u32) -> Result<Self, Tx8Error> {
         let mut sys_call_map = HashMap::new();
         let sys_calls = [
             "print_u32",
@@ -26,6 +151,9 @@ impl<'a> Execution<'a> {
             "print_f32",
             "print_u8",
             "print_char",
+            "print_hex",
+            "print_bin",
+            "print_oct",
             "test_af",
             "test_au",
             "test_ai",
@@ -33,53 +161,517 @@ impl<'a> Execution<'a> {
             "test_r",
             "test_ri",
             "read_char",
+            "read_char_eof",
+            "read_line",
+            "input_available",
+            "memcpy",
+            "exit",
+            "rand_bytes",
+            "parse_int",
+            "parse_float",
+            "crc32",
+            "print_f32_prec",
+            "count_bytes",
+            "print_utf8_codepoint",
         ];
         for sys_call in sys_calls {
             sys_call_map.insert(hash(sys_call), sys_call);
         }
         let rand = Rand::new();
-        let mut input = Vec::new();
-        let mut stdin = std::io::stdin().lock();
-        stdin
-            .read_to_end(&mut input)
-            .map_err(|_| Tx8Error::NoInputGiven)?;
+        let memory = Memory::load_rom_relocated(rom.code, &rom.sections, &rom.relocations, base)?;
+        let mut cpu = Cpu::new();
+        cpu.p = base;
         Ok(Execution {
-            cpu: Cpu::new(),
-            memory: Memory::load_rom(data)?,
+            cpu,
+            memory,
             sys_call_map,
             rand,
-            input: input.into_iter(),
+            input: Vec::new().into_iter(),
+            initial_input: Vec::new(),
+            output: Box::new(std::io::sink()),
+            error_output: Box::new(std::io::sink()),
+            console: None,
+            instruction_count: 0,
+            cycle_costs: CycleCosts::default(),
+            simulated_cycles: 0,
+            lenient_sys_calls: false,
+            trap_overflow: false,
+            canonical_nan: false,
+            pause_on_sys_call: false,
+            sys_call_resumed: false,
+            register_watchers: Vec::new(),
+            trap_handler: None,
+            budget: None,
+            initial_budget: None,
+            output_limit: None,
+            initial_output_limit: None,
+            check_code_bounds: false,
+            entry: base,
+            replay_log: VecDeque::new(),
+            replay_capacity: 0,
+            sys_call_abi: SysCallAbi::default(),
         })
     }
+    /// Creates an execution from raw code without a rom file, e.g. to test a few instructions.
+    /// The code is loaded at the usual code address and every `(address, data)` pair is copied
+    /// to its address like a data section.
+    pub fn test_harness(code: &[u8], data: &[(u32, &[u8])]) -> Result<Self, Tx8Error> {
+        let mut rom = Rom::from_code(code);
+        rom.sections = data
+            .iter()
+            .map(|&(address, data)| DataSection {
+                name: "",
+                address,
+                data,
+            })
+            .collect();
+        Self::new_with_rom(&rom)
+    }
+
+    /// Sets the bytes returned by the input system calls, replacing any input not read yet
+    pub fn set_input(&mut self, input: Vec<u8>) {
+        self.input = input.clone().into_iter();
+        self.initial_input = input;
+    }
+
+    /// Sets where the print system calls write to. Output is discarded by default.
+    pub fn set_output(&mut self, output: Box<dyn std::io::Write>) {
+        self.output = output;
+    }
+
+    /// Sets where the `test_*` system calls write their diagnostics to, separately from the
+    /// output of the print system calls. Diagnostics are discarded by default and do not count
+    /// towards the output limit.
+    pub fn set_error_output(&mut self, output: Box<dyn std::io::Write>) {
+        self.error_output = output;
+    }
+
+    /// Enables tracking of uninitialized stack memory. Reading stack bytes which were not written
+    /// since the stack pointer last moved over them results in `Tx8Error::UninitializedRead`.
+    pub fn set_poison_stack(&mut self, enabled: bool) {
+        self.memory.set_stack_poisoning(enabled);
+    }
+
+    /// Selects where system calls read their arguments from and store their results
+    pub fn set_sys_call_abi(&mut self, abi: SysCallAbi) {
+        self.sys_call_abi = abi;
+    }
+
+    /// When enabled, unknown system calls print a warning and are treated as no-ops instead of
+    /// failing with `Tx8Error::InvalidSysCall`
+    pub fn set_lenient_sys_calls(&mut self, enabled: bool) {
+        self.lenient_sys_calls = enabled;
+    }
+
+    /// When enabled, signed and unsigned `add`, `sub` and `mul` fail with
+    /// `Tx8Error::ArithmeticOverflow` instead of wrapping around
+    pub fn set_trap_overflow(&mut self, enabled: bool) {
+        self.trap_overflow = enabled;
+    }
+
+    /// When enabled, float instructions store every NaN result as `0x7fc00000` instead of the
+    /// bits the host produced, so results do not depend on the payload or the sign of the NaN
+    pub fn set_canonical_nan(&mut self, enabled: bool) {
+        self.canonical_nan = enabled;
+    }
+
+    /// When enabled, `next_step` returns `Effect::SysCallPending` instead of executing a system
+    /// call, so that its arguments can be inspected. `resume_sys_call` then performs it.
+    pub fn set_pause_on_sys_call(&mut self, enabled: bool) {
+        self.pause_on_sys_call = enabled;
+    }
+
+    /// Executes the instruction at the program counter like `next_step`, but performs a system
+    /// call there instead of pausing before it
+    pub fn resume_sys_call(&mut self) -> Result<Effect, Tx8Error> {
+        self.sys_call_resumed = true;
+        self.next_step()
+    }
+
+    /// Installs a handler which decides what happens instead of failing with
+    /// `Tx8Error::DivisionByZero` or `Tx8Error::ArithmeticOverflow`. It is called before the
+    /// faulting instruction changed anything and may modify the registers and memory. Unless it
+    /// aborts, the program counter then moves past the instruction as usual and `R` is left as
+    /// the handler left it.
+    pub fn set_trap_handler(&mut self, handler: Option<TrapHandler>) {
+        self.trap_handler = handler;
+    }
+
+    /// Registers a callback which is invoked with the old and the new value after every
+    /// instruction that changed the given register
+    pub fn on_register_change(&mut self, reg: Register, callback: RegisterCallback) {
+        self.register_watchers.push((reg, callback));
+    }
+
+    /// Returns the machine to the state right after construction: registers, memory, the random
+    /// number generator, the counters, the replay log and a pending system call are restored, as
+    /// are the input, the budget and the output limit as they were last set. The configuration
+    /// and the register watchers are kept.
+    pub fn reset(&mut self) {
+        self.cpu = Cpu::new();
+        self.cpu.p = self.entry;
+        self.memory.reset();
+        self.rand = Rand::new();
+        self.input = self.initial_input.clone().into_iter();
+        if let Some(console) = &self.console {
+            console.borrow_mut().clear();
+        }
+        self.instruction_count = 0;
+        self.simulated_cycles = 0;
+        self.sys_call_resumed = false;
+        self.budget = self.initial_budget;
+        self.output_limit = self.initial_output_limit;
+        self.replay_log.clear();
+    }
+
+    /// Limits the work the program may do. Every instruction costs one unit and system calls
+    /// processing several bytes additionally cost one unit per byte. Once the budget is used up,
+    /// `next_step` fails with `Tx8Error::BudgetExhausted`. `None` removes the limit.
+    /// A system call running out of budget stops after the bytes already paid for. The
+    /// instruction does not complete, so the program counter still points to it.
+    pub fn set_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+        self.initial_budget = budget;
+    }
+
+    /// Units of the budget not used yet, `None` if there is no limit
+    pub fn remaining_budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// When enabled, the program halts with `HaltReason::RanOffEnd` as soon as the program counter
+    /// leaves the loaded code instead of executing whatever follows it, usually zero padding which
+    /// decodes as `hlt`. Programs executing code in data sections must leave this disabled.
+    pub fn set_code_bounds_check(&mut self, enabled: bool) {
+        self.check_code_bounds = enabled;
+    }
+
+    /// When enabled, an instruction writing into the loaded code fails with
+    /// `Tx8Error::SelfModifyingCode` and the write is discarded. Programs which modify their own
+    /// code intentionally must leave this disabled.
+    pub fn set_code_protection(&mut self, enabled: bool) {
+        self.memory.set_code_protection(enabled);
+    }
+
+    /// When enabled, relative addresses fail with `Tx8Error::OffsetOutOfBounds` instead of
+    /// wrapping around if the offset in the `O` register moves them outside of the memory
+    pub fn set_bounded_offsets(&mut self, enabled: bool) {
+        self.memory.set_bounded_offsets(enabled);
+    }
+
+    /// Makes accesses below `threshold` fail with `Tx8Error::NullPointerAccess`, see
+    /// `Memory::set_null_guard`. 0 disables the guard.
+    pub fn set_null_guard(&mut self, threshold: u32) {
+        self.memory.set_null_guard(threshold);
+    }
+
+    /// Limits how many bytes the print system calls may write in total. The system call exceeding
+    /// the limit writes the bytes still allowed and fails with `Tx8Error::OutputLimitReached`.
+    /// `None` removes the limit.
+    pub fn set_output_limit(&mut self, limit: Option<u64>) {
+        self.output_limit = limit;
+        self.initial_output_limit = limit;
+    }
+
+    /// Keeps the last `capacity` executed instructions for `recent_instructions`. 0 disables the
+    /// log, which is the default. A smaller capacity drops the oldest instructions.
+    pub fn set_replay_log(&mut self, capacity: usize) {
+        self.replay_capacity = capacity;
+        while self.replay_log.len() > capacity {
+            self.replay_log.pop_front();
+        }
+    }
+
+    /// The most recently executed instructions with their address, oldest first. An instruction
+    /// which failed is included.
+    pub fn recent_instructions(&self) -> vec_deque::Iter<'_, (u32, Instruction)> {
+        self.replay_log.iter()
+    }
+
+    /// Bytes which may still be written before the output limit is reached, `None` if there is
+    /// no limit
+    pub fn remaining_output(&self) -> Option<u64> {
+        self.output_limit
+    }
+
+    fn charge(&mut self, units: u64) -> Result<(), Tx8Error> {
+        if let Some(budget) = &mut self.budget {
+            *budget = budget.checked_sub(units).ok_or(Tx8Error::BudgetExhausted)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes the instruction at the program counter without executing it. The operands show the
+    /// current register and memory contents. Reads of mapped devices still reach the device.
+    pub fn peek_instruction(&self) -> Result<Instruction, Tx8Error> {
+        let parsed = parse_instruction(&self.cpu, &self.memory, self.cpu.p);
+        // peeking must not fail the next step
+        self.memory.take_uninitialized_read();
+        self.memory.take_access_violation();
+        parsed.map(|(instruction, _)| instruction)
+    }
+
+    /// Executes the instruction at the program counter. If it fails, the program counter stays on
+    /// the faulting instruction and the instruction is not counted, but the budget is charged.
+    /// Faults of the instruction itself, like a division by zero or a stack overflow, are detected
+    /// before it changes anything. Uninitialized reads and writes into the code are detected after
+    /// the instruction ran, so its writes are kept, and a print which reaches the output limit
+    /// has written the part of the output which still fit. Accesses the memory permissions forbid
+    /// fail before the instruction runs if they read an operand and afterwards otherwise, but
+    /// forbidden writes are always discarded.
     pub fn next_step(&mut self) -> Result<Effect, Tx8Error> {
+        if self.check_code_bounds && !self.memory.is_code(self.cpu.p) {
+            return Ok(Effect::Halted(HaltReason::RanOffEnd));
+        }
+        let resumed = std::mem::take(&mut self.sys_call_resumed);
+        if self.pause_on_sys_call && !resumed {
+            if let Ok(Instruction::SysCall(val)) = self.peek_instruction() {
+                return Ok(Effect::SysCallPending(val.val));
+            }
+        }
+        self.charge(1)?;
+        let old_cpu = self.cpu;
         let (instruction, len) = parse_instruction(&self.cpu, &self.memory, self.cpu.p)?;
+        if self.memory.take_uninitialized_read().is_some() {
+            return Err(Tx8Error::UninitializedRead);
+        }
+        if let Some(violation) = self.memory.take_access_violation() {
+            return Err(violation);
+        }
+        if self.replay_capacity > 0 {
+            if self.replay_log.len() == self.replay_capacity {
+                self.replay_log.pop_front();
+            }
+            self.replay_log.push_back((self.cpu.p, instruction));
+        }
 
-        let effect = self.execute_instruction(instruction, len)?;
+        // writes into the code from outside of the program are not reported
+        self.memory.take_code_write();
+        let effect = self.execute_instruction(instruction, len);
+        self.flush_console()?;
+        let effect = effect?;
+        if self.memory.take_uninitialized_read().is_some() {
+            return Err(Tx8Error::UninitializedRead);
+        }
+        if let Some(violation) = self.memory.take_access_violation() {
+            return Err(violation);
+        }
+        if self.memory.take_code_write().is_some() {
+            return Err(Tx8Error::SelfModifyingCode);
+        }
         // increase instruction pointer
         if instruction.increase_program_counter() {
             self.cpu.p += len;
         }
+        self.instruction_count += 1;
+        self.simulated_cycles += self.cycle_costs.cost(&instruction);
+        for (reg, callback) in self.register_watchers.iter_mut() {
+            let (old, new) = (old_cpu.get(*reg), self.cpu.get(*reg));
+            if old != new {
+                callback(*reg, old, new);
+            }
+        }
         Ok(effect)
     }
 
+    /// Moves the program counter past the instruction at the program counter without executing
+    /// it, e.g. to continue after it failed. Fails if the instruction cannot be decoded, as its
+    /// length is unknown then.
+    pub fn skip_faulting_instruction(&mut self) -> Result<(), Tx8Error> {
+        let (_, len) = parse_instruction(&self.cpu, &self.memory, self.cpu.p)?;
+        self.memory.take_uninitialized_read();
+        self.memory.take_access_violation();
+        self.cpu.p += len;
+        Ok(())
+    }
+
+    /// Executes up to `steps` instructions, e.g. to run a program a bit every frame. Returns early
+    /// with the effect of an instruction that halted or hit a breakpoint, otherwise with
+    /// `Effect::Yielded`.
+    pub fn run_steps(&mut self, steps: u64) -> Result<Effect, Tx8Error> {
+        for _ in 0..steps {
+            match self.next_step()? {
+                Effect::None => (),
+                effect => return Ok(effect),
+            }
+        }
+        Ok(Effect::Yielded)
+    }
+
+    /// Executes one instruction, but runs a `call` until it returns to the instruction after it.
+    /// Recursive calls to the same return address are told apart by the stack pointer. A callee
+    /// which never returns runs until it halts, fails or exhausts the budget.
+    pub fn step_over(&mut self) -> Result<Effect, Tx8Error> {
+        let (instruction, len) = parse_instruction(&self.cpu, &self.memory, self.cpu.p)?;
+        self.memory.take_uninitialized_read();
+        self.memory.take_access_violation();
+        if !matches!(instruction, Instruction::Call(_)) {
+            return self.next_step();
+        }
+        let return_address = self.cpu.p + len;
+        let stack_pointer = self.cpu.s;
+        loop {
+            let effect = self.next_step()?;
+            let returned = self.cpu.p == return_address && self.cpu.s >= stack_pointer;
+            if returned || !matches!(effect, Effect::None) {
+                return Ok(effect);
+            }
+        }
+    }
+
+    /// Executes one instruction like `next_step` and reports which registers and memory bytes it
+    /// changed
+    pub fn step_with_diff(&mut self) -> Result<(Effect, StateDiff), Tx8Error> {
+        let old_cpu = self.cpu;
+        self.memory.start_write_log();
+        let effect = self.next_step();
+        let writes = self.memory.take_write_log();
+        let effect = effect?;
+
+        let registers = Register::ALL
+            .into_iter()
+            .map(|reg| (reg, old_cpu.get(reg), self.cpu.get(reg)))
+            .filter(|(_, old, new)| old != new)
+            .collect();
+        // the first write to an address knows its value before the step, the last one the result
+        let mut memory: Vec<(u32, u8, u8)> = Vec::new();
+        for (address, old, new) in writes {
+            match memory.iter_mut().find(|(seen, _, _)| *seen == address) {
+                Some(change) => change.2 = new,
+                None => memory.push((address, old, new)),
+            }
+        }
+        memory.retain(|(_, old, new)| old != new);
+        let diff = StateDiff {
+            registers,
+            memory,
+            pc: self.cpu.p,
+        };
+        Ok((effect, diff))
+    }
+
+    /// Maps a device into memory, see `Memory::map_io`
+    pub fn map_io(
+        &mut self,
+        range: std::ops::Range<u32>,
+        handler: Box<dyn MmioHandler>,
+    ) -> Result<(), Tx8Error> {
+        self.memory.map_io(range, handler)
+    }
+
+    /// Maps the console device at `CONSOLE_ADDRESS`. Its byte registers are the cursor column
+    /// and row, the foreground and background color and a character register. Writing them
+    /// prints the ANSI escape sequence moving the cursor or setting the 256-color palette color,
+    /// or the character, to the output.
+    pub fn map_console(&mut self) -> Result<(), Tx8Error> {
+        let pending = Rc::new(RefCell::new(Vec::new()));
+        self.memory.map_io(
+            CONSOLE_ADDRESS..CONSOLE_ADDRESS + CONSOLE_SIZE,
+            Box::new(Console::new(pending.clone())),
+        )?;
+        self.console = Some(pending);
+        Ok(())
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Gives write access to the memory, e.g. to import a prepared image before running
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Address of the next instruction to execute
+    pub fn program_counter(&self) -> u32 {
+        self.cpu.p
+    }
+
+    pub fn get_register(&self, reg: Register) -> u32 {
+        self.cpu.get(reg)
+    }
+
+    /// Sets a register, e.g. to prepare the arguments of a code snippet. Setting `P` makes the
+    /// next step execute the instruction at that address.
+    pub fn set_register(&mut self, reg: Register, val: u32) {
+        self.cpu.set(reg, val);
+    }
+
+    /// Number of instructions executed so far
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Approximate number of cycles the executed instructions would have taken
+    pub fn simulated_cycles(&self) -> u64 {
+        self.simulated_cycles
+    }
+
+    pub fn set_cycle_costs(&mut self, costs: CycleCosts) {
+        self.cycle_costs = costs;
+    }
+
+    /// Serializes the register state and the instruction count as JSON
+    #[cfg(feature = "serde")]
+    pub fn state_json(&self) -> String {
+        #[derive(serde::Serialize)]
+        struct State<'a> {
+            registers: &'a Cpu,
+            instruction_count: u64,
+        }
+        let state = State {
+            registers: &self.cpu,
+            instruction_count: self.instruction_count,
+        };
+        serde_json::to_string(&state).expect("state serialization cannot fail")
+    }
+
     pub fn execute_instruction(
         &mut self,
         instr: Instruction,
         len: u32,
     ) -> Result<Effect, Tx8Error> {
         match instr {
-            Instruction::Halt => return Ok(Effect::Halted),
+            Instruction::Halt => return Ok(Effect::Halted(HaltReason::HaltOpcode)),
+            Instruction::HaltIf(comp) => {
+                // like `hlt`, the program counter stays on the instruction if it halts
+                if eval_condition(&self.cpu, comp) {
+                    return Ok(Effect::Halted(HaltReason::HaltOpcode));
+                }
+                self.cpu.p += len;
+            }
             Instruction::Nop => (),
             Instruction::Jump(value, comp) => self.jump(value.val, comp, len),
-            Instruction::CompareSigned(val, val2) => self.compare_signed(val, val2.val),
-            Instruction::CompareFloat(val, val2) => self.compare_float(val.val, val2.val),
-            Instruction::CompareUnsigned(val, val2) => self.compare_unsigned(val.val, val2.val),
+            Instruction::CompareSigned(val, val2) => self.cpu.set_r(compare_signed(val, val2.val)),
+            Instruction::CompareFloat(val, val2) => {
+                let res = compare_float(val.val, val2.val);
+                self.cpu.set_r(res);
+                self.cpu.unordered = res == UNORDERED as u32;
+            }
+            Instruction::CompareUnsigned(val, val2) => {
+                self.cpu.set_r(compare_unsigned(val.val, val2.val))
+            }
             Instruction::Call(val) => self.call(val, len),
-            Instruction::SysCall(value) => self.sys_call(value.val)?,
+            Instruction::Repeat(to, counter, target) => {
+                self.repeat(to, counter.val, target.val, len)?
+            }
+            Instruction::SysCall(value) => return self.sys_call(value.val),
             Instruction::Return => self.ret(),
-            Instruction::Load(to, val) => self.load(to, val)?,
+            Instruction::DebugBreak => return Ok(Effect::BreakpointHit),
+            Instruction::Load(to, val)
+            | Instruction::LoadAddress(to, val)
+            | Instruction::GetPc(to, val) => self.load(to, val)?,
             Instruction::Push(val) => self.push(val),
             Instruction::Pop(val) => self.pop(val)?,
+            Instruction::PushMulti(mask) => self.push_multi(mask.val)?,
+            Instruction::PopMulti(mask) => self.pop_multi(mask.val)?,
+            Instruction::Enter(size) => self.enter(size.val)?,
+            Instruction::Leave => self.leave()?,
+            Instruction::TakeR(to) => self.take_r(to)?,
+            Instruction::FetchAdd(to, old, val) => self.fetch_add(to, old, val)?,
+            Instruction::Spill(addr, len) => self.spill(addr.val, len.val)?,
+            Instruction::Reload(addr, len) => self.reload(addr.val, len.val)?,
+            Instruction::Swap(to, to2, val, val2) => self.swap(to, to2, val, val2)?,
             Instruction::Add(to, val, val2, kind) => self.add(to, val, val2, kind)?,
             Instruction::Sub(to, val, val2, kind) => self.sub(to, val, val2, kind)?,
             Instruction::Mul(to, val, val2, kind) => self.mul(to, val, val2, kind)?,
@@ -89,6 +681,11 @@ impl<'a> Execution<'a> {
             Instruction::MaxMin(to, val, val2, kind, is_max) => {
                 self.max_min(to, val, val2, kind, is_max)?
             }
+            Instruction::ISqrt(to, val) => self.isqrt(to, val)?,
+            Instruction::IncDecSaturating(to, val, is_inc) => {
+                self.inc_dec_saturating(to, val, is_inc)?
+            }
+            Instruction::AbsDiff(to, val, val2, kind) => self.abs_diff(to, val, val2, kind)?,
             Instruction::AbsSign(to, val, kind, is_abs) => self.abs_sign(to, val, kind, is_abs)?,
             Instruction::And(to, val, val2) => self.and(to, val, val2)?,
             Instruction::Or(to, val, val2) => self.or(to, val, val2)?,
@@ -104,6 +701,8 @@ impl<'a> Execution<'a> {
             Instruction::Clear(to, val, val2) => self.clear(to, val, val2)?,
             Instruction::Toggle(to, val, val2) => self.toggle(to, val, val2)?,
             Instruction::Test(val, val2) => self.test(val, val2),
+            Instruction::ByteSwap(to, val) => self.byte_swap(to, val)?,
+            Instruction::BitReverse(to, val) => self.bit_reverse(to, val)?,
             Instruction::Sin(to, val) => self.sin(to, val)?,
             Instruction::Cos(to, val) => self.cos(to, val)?,
             Instruction::Tan(to, val) => self.tan(to, val)?,
@@ -113,6 +712,11 @@ impl<'a> Execution<'a> {
             Instruction::ArcTan2(to, val, val2) => self.arctan2(to, val, val2)?,
             Instruction::Sqrt(to, val) => self.sqrt(to, val)?,
             Instruction::Pow(to, val, val2) => self.pow(to, val, val2)?,
+            Instruction::Fma(to, val, val2) => self.fma(to, val, val2)?,
+            Instruction::Floor(to, val) => self.floor(to, val)?,
+            Instruction::Ceil(to, val) => self.ceil(to, val)?,
+            Instruction::Round(to, val) => self.round(to, val)?,
+            Instruction::Trunc(to, val) => self.trunc(to, val)?,
             Instruction::Exp(to, val) => self.exp(to, val)?,
             Instruction::Log(to, val) => self.log(to, val)?,
             Instruction::Log2(to, val) => self.log2(to, val)?,
@@ -121,71 +725,323 @@ impl<'a> Execution<'a> {
             Instruction::RSeed(val) => self.rseed(val),
             Instruction::ItoF(to, val) => self.i_to_f(to, val)?,
             Instruction::FtoI(to, val) => self.f_to_i(to, val)?,
+            Instruction::FloatToFixed(to, val, frac) => self.float_to_fixed(to, val, frac)?,
+            Instruction::FixedToFloat(to, val, frac) => self.fixed_to_float(to, val, frac)?,
             Instruction::UtoF(to, val) => self.u_to_f(to, val)?,
             Instruction::FtoU(to, val) => self.f_to_f(to, val)?,
+            Instruction::Order(to, val, val2, kind) => self.order(to, val, val2, kind)?,
+            Instruction::CompareInto(to, val, val2, kind) => {
+                self.compare_into(to, val, val2, kind)?
+            }
         };
         Ok(Effect::None)
     }
 
-    fn sys_call(&mut self, val: u32) -> Result<(), Tx8Error> {
+    fn sys_call(&mut self, val: u32) -> Result<Effect, Tx8Error> {
         if let Some(&str) = self.sys_call_map.get(&val) {
             match str {
-                "print_u32" => print!("{}", self.memory.read_int(self.cpu.s)),
-                "print_i32" => print!("{}", self.memory.read_int(self.cpu.s) as i32),
-                "print_f32" => print!("{}", f32::from_bits(self.memory.read_int(self.cpu.s))),
-                "print_char" => print!("{}", self.memory.read_int(self.cpu.s) as u8 as char),
-                "print_u8" => print!("{}", self.memory.read_int(self.cpu.s) as u8),
-                "test_af" => println!("{}", f32::from_bits(self.cpu.a)),
-                "test_au" => println!("{:x}", self.cpu.a),
-                "test_ai" => println!("{}", self.cpu.a as i32),
-                "test_rf" => println!("{}", f32::from_bits(self.cpu.r)),
-                "test_r" => println!("{:x}", self.cpu.r),
-                "test_ri" => println!("{}", self.cpu.r as i32),
+                "print_u32" => self.print(self.stack_arg(0, Size::Int)?)?,
+                "print_i32" => self.print(self.stack_arg(0, Size::Int)? as i32)?,
+                "print_f32" => self.print(f32::from_bits(self.stack_arg(0, Size::Int)?))?,
+                "print_f32_prec" => {
+                    let val = f32::from_bits(self.stack_arg(0, Size::Int)?);
+                    let precision = self.sys_call_arg(1, Register::A)?.min(MAX_PRECISION);
+                    self.print(format!("{val:.*}", precision as usize))?
+                }
+                "print_char" => self.print(self.stack_arg(0, Size::Byte)? as u8 as char)?,
+                "print_utf8_codepoint" => {
+                    let codepoint = self.stack_arg(0, Size::Int)?;
+                    let char =
+                        char::from_u32(codepoint).ok_or(Tx8Error::InvalidCodepoint(codepoint))?;
+                    self.print(char)?
+                }
+                "print_u8" => self.print(self.stack_arg(0, Size::Byte)? as u8)?,
+                "print_hex" => self.print(format!("{:x}", self.stack_arg(0, Size::Int)?))?,
+                "print_bin" => self.print(format!("{:b}", self.stack_arg(0, Size::Int)?))?,
+                "print_oct" => self.print(format!("{:o}", self.stack_arg(0, Size::Int)?))?,
+                "test_af" => self.print_diagnostic(format!("{}\n", f32::from_bits(self.cpu.a)))?,
+                "test_au" => self.print_diagnostic(format!("{:x}\n", self.cpu.a))?,
+                "test_ai" => self.print_diagnostic(format!("{}\n", self.cpu.a as i32))?,
+                "test_rf" => self.print_diagnostic(format!("{}\n", f32::from_bits(self.cpu.r)))?,
+                "test_r" => self.print_diagnostic(format!("{:x}\n", self.cpu.r))?,
+                "test_ri" => self.print_diagnostic(format!("{}\n", self.cpu.r as i32))?,
                 "read_char" => {
-                    if let Some(char) = self.input.next() {
-                        self.cpu.o = char as u8 as u32;
-                    } else {
-                        return Err(Tx8Error::NoInputGiven);
-                    }
+                    let char = self.input.next().ok_or(Tx8Error::NoInputGiven)?;
+                    self.sys_call_result(0, Register::O, char as u32)?;
+                }
+                "read_char_eof" => {
+                    // 0xffffffff signals the end of the input
+                    let char = self.input.next().map_or(u32::MAX, |char| char as u32);
+                    self.sys_call_result(0, Register::O, char)?;
+                }
+                "read_line" => self.read_line()?,
+                "input_available" => {
+                    self.sys_call_result(0, Register::R, self.input.len() as u32)?
+                }
+                "memcpy" => self.memcpy()?,
+                "rand_bytes" => self.rand_bytes()?,
+                "parse_int" => self.parse_number(false)?,
+                "parse_float" => self.parse_number(true)?,
+                "crc32" => self.crc32()?,
+                "count_bytes" => self.count_bytes()?,
+                "exit" => {
+                    let code = self.stack_arg(0, Size::Int)?;
+                    return Ok(Effect::Halted(HaltReason::Exit(code)));
                 }
                 _ => return Err(Tx8Error::InvalidSysCall),
             }
-            Ok(())
+            Ok(Effect::None)
+        } else if self.lenient_sys_calls {
+            status!(warn, "unimplemented syscall {}", format_reg(val, false));
+            Ok(Effect::None)
         } else {
             Err(Tx8Error::InvalidSysCall)
         }
     }
 
+    /// Reads the argument with the given index, from its register with the legacy ABI
+    fn sys_call_arg(&self, index: u32, legacy: Register) -> Result<u32, Tx8Error> {
+        match self.sys_call_abi {
+            SysCallAbi::Legacy => Ok(self.cpu.get(legacy)),
+            SysCallAbi::Stack => self.stack_arg(index, Size::Int),
+        }
+    }
+
+    /// Reads the argument with the given index from the stack, the arguments are 4 bytes apart.
+    /// Fails if it is not on the stack, e.g. because nothing was pushed.
+    fn stack_arg(&self, index: u32, size: Size) -> Result<u32, Tx8Error> {
+        let addr = self.cpu.s.wrapping_add(index * 4);
+        let end = addr as u64 + size.bytes() as u64;
+        if self.cpu.s < STACK_BOTTOM || addr < self.cpu.s || end > STACK_TOP as u64 {
+            return Err(Tx8Error::MissingSysCallArgument(self.cpu.s));
+        }
+        Ok(match size {
+            Size::Byte => self.memory.read_byte(addr) as u32,
+            Size::Short => self.memory.read_short(addr) as u32,
+            Size::Int => self.memory.read_int(addr),
+        })
+    }
+
+    /// Stores the result with the given index of a system call, in the given register with the
+    /// legacy ABI. With the stack ABI, the first result is stored in `R` and a second one replaces
+    /// the first argument on the stack.
+    fn sys_call_result(&mut self, index: u32, legacy: Register, val: u32) -> Result<(), Tx8Error> {
+        match (self.sys_call_abi, index) {
+            (SysCallAbi::Legacy, _) => self.cpu.set(legacy, val),
+            (SysCallAbi::Stack, 0) => self.cpu.set_r(val),
+            (SysCallAbi::Stack, _) => {
+                // fails like reading the argument if its slot is not on the stack
+                self.stack_arg(index - 1, Size::Int)?;
+                let addr = self.cpu.s.wrapping_add((index - 1) * 4);
+                self.memory.write_int(addr, val);
+            }
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, val: impl Display) -> Result<(), Tx8Error> {
+        self.print_bytes(val.to_string().as_bytes())
+    }
+
+    fn print_bytes(&mut self, mut bytes: &[u8]) -> Result<(), Tx8Error> {
+        let mut truncated = false;
+        if let Some(limit) = &mut self.output_limit {
+            let allowed = (*limit).min(bytes.len() as u64);
+            truncated = allowed < bytes.len() as u64;
+            bytes = &bytes[..allowed as usize];
+            *limit -= allowed;
+        }
+        self.output
+            .write_all(bytes)
+            .map_err(|_| Tx8Error::OutputError)?;
+        if truncated {
+            return Err(Tx8Error::OutputLimitReached);
+        }
+        Ok(())
+    }
+
+    /// Writes what the console device emitted to the output
+    fn flush_console(&mut self) -> Result<(), Tx8Error> {
+        let Some(console) = &self.console else {
+            return Ok(());
+        };
+        let bytes = console.take();
+        self.print_bytes(&bytes)
+    }
+
+    fn print_diagnostic(&mut self, val: impl Display) -> Result<(), Tx8Error> {
+        self.error_output
+            .write_all(val.to_string().as_bytes())
+            .map_err(|_| Tx8Error::OutputError)
+    }
+
+    /// Reads input until a newline into the buffer at `a` of size `b`, storing the length in `r`
+    fn read_line(&mut self) -> Result<(), Tx8Error> {
+        let (to, size) = (
+            self.sys_call_arg(0, Register::A)?,
+            self.sys_call_arg(1, Register::B)?,
+        );
+        let mut len = 0;
+        while let Some(byte) = self.input.next() {
+            self.charge(1)?;
+            if byte == b'\n' {
+                break;
+            }
+            if len < size {
+                self.memory.write_byte(to.wrapping_add(len), byte);
+                len += 1;
+            }
+        }
+        self.sys_call_result(0, Register::R, len)
+    }
+
+    /// Copies `c` bytes from address `b` to address `a`, the ranges may overlap
+    fn memcpy(&mut self) -> Result<(), Tx8Error> {
+        let (to, from, len) = (
+            self.sys_call_arg(0, Register::A)?,
+            self.sys_call_arg(1, Register::B)?,
+            self.sys_call_arg(2, Register::C)?,
+        );
+        check_block(from, len, Tx8Error::ReadViolation)?;
+        check_block(to, len, Tx8Error::OutOfBoundsWrite)?;
+        for i in 0..len {
+            self.charge(1)?;
+            // copy backwards if the destination starts inside the source
+            let offset = if to > from { len - 1 - i } else { i };
+            let byte = self.memory.read_byte(from + offset);
+            self.memory.write_byte(to + offset, byte);
+        }
+        Ok(())
+    }
+
+    /// Stores the CRC-32 of the `b` bytes starting at `a` in `r`
+    fn crc32(&mut self) -> Result<(), Tx8Error> {
+        let (from, len) = (
+            self.sys_call_arg(0, Register::A)?,
+            self.sys_call_arg(1, Register::B)?,
+        );
+        check_block(from, len, Tx8Error::ReadViolation)?;
+        let mut crc = Crc32::default();
+        for i in 0..len {
+            self.charge(1)?;
+            crc.update(&[self.memory.read_byte(from + i)]);
+        }
+        self.sys_call_result(0, Register::R, crc.finish())
+    }
+
+    /// Stores the number of the `b` bytes starting at `a` which equal the lowest byte of `c` in `r`
+    fn count_bytes(&mut self) -> Result<(), Tx8Error> {
+        let (from, len, byte) = (
+            self.sys_call_arg(0, Register::A)?,
+            self.sys_call_arg(1, Register::B)?,
+            self.sys_call_arg(2, Register::C)? as u8,
+        );
+        check_block(from, len, Tx8Error::ReadViolation)?;
+        let mut count = 0;
+        for i in 0..len {
+            self.charge(1)?;
+            if self.memory.read_byte(from + i) == byte {
+                count += 1;
+            }
+        }
+        self.sys_call_result(0, Register::R, count)
+    }
+
+    /// Parses the number at `a` into `r` and stores the number of bytes consumed in `b`, 0 if
+    /// there is no valid number. Leading whitespace is skipped and the number ends at a NUL byte,
+    /// whitespace or the end of memory.
+    fn parse_number(&mut self, float: bool) -> Result<(), Tx8Error> {
+        let start = self.sys_call_arg(0, Register::A)?;
+        let end = MB_16 as u32;
+        let mut ptr = start;
+        // every byte read is charged before reading it, the end of memory ends the text
+        while ptr < end {
+            self.charge(1)?;
+            if !self.memory.read_byte(ptr).is_ascii_whitespace() {
+                break;
+            }
+            ptr += 1;
+        }
+        let mut token = Vec::new();
+        // reading one byte more than the longest number tells too long tokens apart
+        while token.len() <= MAX_NUMBER_LENGTH && ptr + (token.len() as u32) < end {
+            self.charge(1)?;
+            let byte = self.memory.read_byte(ptr + token.len() as u32);
+            if byte == 0 || byte.is_ascii_whitespace() {
+                break;
+            }
+            token.push(byte);
+        }
+        if token.len() > MAX_NUMBER_LENGTH {
+            token.clear();
+        }
+        let parsed = std::str::from_utf8(&token).ok().and_then(|token| {
+            if float {
+                token.parse::<f32>().ok().map(f32::to_bits)
+            } else {
+                token.parse::<i32>().ok().map(|val| val as u32)
+            }
+        });
+        let (val, consumed) = match parsed {
+            Some(val) => (val, ptr.wrapping_sub(start) + token.len() as u32),
+            None => (0, 0),
+        };
+        self.sys_call_result(0, Register::R, val)?;
+        self.sys_call_result(1, Register::B, consumed)
+    }
+
+    /// Fills `b` bytes starting at `a` with bytes from the random number generator
+    fn rand_bytes(&mut self) -> Result<(), Tx8Error> {
+        let (to, len) = (
+            self.sys_call_arg(0, Register::A)?,
+            self.sys_call_arg(1, Register::B)?,
+        );
+        for i in 0..len {
+            self.charge(1)?;
+            let byte = self.rand.next() as u8;
+            self.memory.write_byte(to.wrapping_add(i), byte);
+        }
+        Ok(())
+    }
+
     fn jump(&mut self, val: u32, comp: Comparison, instr_len: u32) {
-        let r = self.cpu.r as i32;
-        let cond = match comp {
-            Comparison::None => true,
-            Comparison::Equal => r == 0,
-            Comparison::NotEqual => r != 0,
-            Comparison::Greater => r > 0,
-            Comparison::GreaterEqual => r >= 0,
-            Comparison::Less => r < 0,
-            Comparison::LessEqual => r <= 0,
-        };
-        if cond {
+        if eval_condition(&self.cpu, comp) {
             self.cpu.p = val;
         } else {
             self.cpu.p += instr_len;
         }
     }
 
-    fn compare_signed(&mut self, val: Value, val2: u32) {
-        self.cpu.r = match val.size {
-            Size::Byte => i8::signum((val.val as i8) - (val2 as i8)) as u32,
-            Size::Short => i16::signum((val.val as i16) - (val2 as i16)) as u32,
-            Size::Int => i32::signum((val.val as i32) - (val2 as i32)) as u32,
+    /// Writes the comparison result like `cmp` into the destination instead of the `R` register
+    fn compare_into(
+        &mut self,
+        to: Writable,
+        val: Value,
+        val2: Value,
+        kind: Type,
+    ) -> Result<(), Tx8Error> {
+        let res = match kind {
+            Type::Signed => compare_signed(val, val2.val),
+            Type::Unsigned => compare_unsigned(val.val, val2.val),
+            Type::Float => compare_float(val.val, val2.val),
         };
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
-    fn compare_float(&mut self, val: u32, val2: u32) {
-        self.cpu.r = f32::signum(f32::from_bits(val) - f32::from_bits(val2)) as i32 as u32;
-    }
-    fn compare_unsigned(&mut self, val: u32, val2: u32) {
-        self.cpu.r = i64::signum((val as i64) - (val2 as i64)) as u32;
+
+    /// Writes the ordering of both values as 0 (less), 1 (equal), 2 (greater) or 3 (unordered)
+    fn order(&mut self, to: Writable, val: Value, val2: Value, kind: Type) -> Result<(), Tx8Error> {
+        let ordering = match kind {
+            Type::Signed => Some((val.val as i32).cmp(&(val2.val as i32))),
+            Type::Unsigned => Some(val.val.cmp(&val2.val)),
+            Type::Float => f32::from_bits(val.val).partial_cmp(&f32::from_bits(val2.val)),
+        };
+        let res = match ordering {
+            Some(Ordering::Less) => 0,
+            Some(Ordering::Equal) => 1,
+            Some(Ordering::Greater) => 2,
+            None => 3,
+        };
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn load(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
@@ -193,7 +1049,7 @@ impl<'a> Execution<'a> {
     }
 
     fn push(&mut self, val: Value) {
-        self.cpu.s -= val.size.bytes();
+        self.move_stack_pointer(self.cpu.s - val.size.bytes());
         match val.size {
             Size::Byte => self.memory.write_byte(self.cpu.s, (val.val & 0xff) as u8),
             Size::Short => self
@@ -210,21 +1066,178 @@ impl<'a> Execution<'a> {
             Size::Int => self.memory.read_int(self.cpu.s),
         };
         val.write(&mut self.memory, &mut self.cpu, value)?;
-        self.cpu.s += val.size().bytes();
+        self.move_stack_pointer(self.cpu.s + val.size().bytes());
         Ok(())
     }
 
-    fn call(&mut self, val: Value, len: u32) {
-        self.push(Value::new(self.cpu.p + len, Size::Int));
-        self.cpu.p = val.val;
+    /// Pushes the registers selected by the mask, starting with the lowest bit
+    fn push_multi(&mut self, mask: u32) -> Result<(), Tx8Error> {
+        for reg in mask_registers(mask)? {
+            let val =
+                Value::from_par(Parameter::Register(reg), &self.cpu, &self.memory, Size::Int)?;
+            self.push(val);
+        }
+        Ok(())
     }
-    fn ret(&mut self) {
-        let val = self.memory.read_int(self.cpu.s);
-        self.cpu.s += 4;
-        self.cpu.p = val;
+
+    /// Pops the registers selected by the mask in the reverse order of `push_multi`
+    fn pop_multi(&mut self, mask: u32) -> Result<(), Tx8Error> {
+        for reg in mask_registers(mask)?.into_iter().rev() {
+            self.pop(Writable::from_par(Parameter::Register(reg))?)?;
+        }
+        Ok(())
     }
 
-    fn add(
+    /// Pushes `O`, points it to the saved value and reserves `size` bytes below it. A frame must
+    /// not leave the default stack if it starts in it.
+    fn enter(&mut self, size: u32) -> Result<(), Tx8Error> {
+        let bottom = self
+            .cpu
+            .s
+            .checked_sub(4)
+            .and_then(|s| s.checked_sub(size))
+            .ok_or(Tx8Error::StackOverflow)?;
+        if (STACK_BOTTOM..=STACK_TOP).contains(&self.cpu.s) && bottom < STACK_BOTTOM {
+            return Err(Tx8Error::StackOverflow);
+        }
+        self.push(Value::new(self.cpu.o, Size::Int));
+        self.cpu.o = self.cpu.s;
+        self.move_stack_pointer(bottom);
+        Ok(())
+    }
+
+    /// Frees the frame of the last `enter` and restores `O`
+    fn leave(&mut self) -> Result<(), Tx8Error> {
+        self.move_stack_pointer(self.cpu.o);
+        self.pop(Writable::register(Register::O))
+    }
+
+    /// Moves `R` into the destination like `ld` and clears it afterwards
+    fn take_r(&mut self, to: Writable) -> Result<(), Tx8Error> {
+        let r = self.cpu.r;
+        to.write_size(&mut self.memory, &mut self.cpu, r, Size::Int)?;
+        self.cpu.set_r(0);
+        Ok(())
+    }
+
+    /// Adds `val` to the target and puts the previous value of the target into `R`. The sum wraps
+    /// around within the size of the target, unless an unsigned overflow traps like with `uadd`.
+    fn fetch_add(&mut self, to: Writable, old: Value, val: Value) -> Result<(), Tx8Error> {
+        // the size of the previous value is the size of the target, see `parse_instruction`
+        let size = old.size;
+        let mask = size.mask() as u64;
+        let sum = (old.val as u64 & mask) + (val.val as u64 & mask);
+        // the trap handler leaves `R` as it wants it
+        if self.check_overflow(to, Type::Unsigned, sum > mask, false)? {
+            return Ok(());
+        }
+        to.write_size(&mut self.memory, &mut self.cpu, sum as u32, size)?;
+        self.cpu.set_r(old.val);
+        Ok(())
+    }
+
+    /// Pushes the `len` bytes starting at `addr` as one block. The bytes keep their order, so the
+    /// first byte ends up at the new stack pointer.
+    fn spill(&mut self, addr: u32, len: u32) -> Result<(), Tx8Error> {
+        let bottom = self.cpu.s.checked_sub(len).ok_or(Tx8Error::StackOverflow)?;
+        if (STACK_BOTTOM..=STACK_TOP).contains(&self.cpu.s) && bottom < STACK_BOTTOM {
+            return Err(Tx8Error::StackOverflow);
+        }
+        // the block is read first in case it overlaps with its new place on the stack
+        let block = self.read_block(addr, len);
+        self.move_stack_pointer(bottom);
+        self.write_block(bottom, &block);
+        Ok(())
+    }
+
+    /// Pops a block of `len` bytes pushed by `spill` and stores it at `addr`
+    fn reload(&mut self, addr: u32, len: u32) -> Result<(), Tx8Error> {
+        let top = self
+            .cpu
+            .s
+            .checked_add(len)
+            .ok_or(Tx8Error::StackUnderflow)?;
+        if (STACK_BOTTOM..=STACK_TOP).contains(&self.cpu.s) && top > STACK_TOP {
+            return Err(Tx8Error::StackUnderflow);
+        }
+        let block = self.read_block(self.cpu.s, len);
+        self.move_stack_pointer(top);
+        self.write_block(addr, &block);
+        Ok(())
+    }
+
+    fn read_block(&self, addr: u32, len: u32) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.memory.read_byte(addr.wrapping_add(i)))
+            .collect()
+    }
+
+    fn write_block(&mut self, addr: u32, block: &[u8]) {
+        for (i, byte) in (0..).zip(block) {
+            self.memory.write_byte(addr.wrapping_add(i), *byte);
+        }
+    }
+
+    fn swap(
+        &mut self,
+        to: Writable,
+        to2: Writable,
+        val: Value,
+        val2: Value,
+    ) -> Result<(), Tx8Error> {
+        // only the bytes both locations have in common are exchanged
+        let size = if to.size().bytes() < to2.size().bytes() {
+            to.size()
+        } else {
+            to2.size()
+        };
+        let mask = match size {
+            Size::Byte => 0xff,
+            Size::Short => 0xffff,
+            Size::Int => 0xffffffff,
+        };
+        let res = (val.val & !mask) | (val2.val & mask);
+        let res2 = (val2.val & !mask) | (val.val & mask);
+        to.write_size(&mut self.memory, &mut self.cpu, res, to.size())?;
+        to2.write_size(&mut self.memory, &mut self.cpu, res2, to2.size())
+    }
+
+    fn call(&mut self, val: Value, len: u32) {
+        self.push(Value::new(self.cpu.p + len, Size::Int));
+        self.cpu.p = val.val;
+    }
+
+    /// Decrements the counter and jumps to the target unless it reached 0. A counter which is
+    /// already 0 stays 0 and does not jump, so the loop body runs once.
+    fn repeat(
+        &mut self,
+        to: Writable,
+        counter: u32,
+        target: u32,
+        len: u32,
+    ) -> Result<(), Tx8Error> {
+        let counter = counter.saturating_sub(1);
+        to.write(&mut self.memory, &mut self.cpu, counter)?;
+        if counter == 0 {
+            self.cpu.p += len;
+        } else {
+            self.cpu.p = target;
+        }
+        Ok(())
+    }
+
+    fn ret(&mut self) {
+        let val = self.memory.read_int(self.cpu.s);
+        self.move_stack_pointer(self.cpu.s + 4);
+        self.cpu.p = val;
+    }
+
+    fn move_stack_pointer(&mut self, s: u32) {
+        self.memory.move_stack_pointer(self.cpu.s, s);
+        self.cpu.s = s;
+    }
+
+    fn add(
         &mut self,
         to: Writable,
         first: Value,
@@ -254,17 +1267,21 @@ impl<'a> Execution<'a> {
             Size::Int => first.val.overflowing_add(second.val),
         };
 
+        if self.check_overflow(to, kind, overflow, overflow_signed)? {
+            return Ok(());
+        }
         match kind {
             Type::Signed => to.write(&mut self.memory, &mut self.cpu, res_signed as u32)?,
             Type::Unsigned => to.write(&mut self.memory, &mut self.cpu, res)?,
             Type::Float => {
-                let res = f32::to_bits(f32::from_bits(first.val) + f32::from_bits(second.val));
+                let res = self.float_bits(f32::from_bits(first.val) + f32::from_bits(second.val));
                 to.write(&mut self.memory, &mut self.cpu, res)?;
                 return Ok(());
             }
         }
 
-        self.cpu.r = if overflow { 0b1 } else { 0b0 } | if overflow_signed { 0b10 } else { 0b0 };
+        self.cpu
+            .set_r(if overflow { 0b1 } else { 0b0 } | if overflow_signed { 0b10 } else { 0b0 });
         Ok(())
     }
     fn sub(
@@ -297,35 +1314,93 @@ impl<'a> Execution<'a> {
             Size::Int => first.val.overflowing_sub(second.val),
         };
 
+        if self.check_overflow(to, kind, overflow, overflow_signed)? {
+            return Ok(());
+        }
         match kind {
             Type::Signed => to.write(&mut self.memory, &mut self.cpu, res_signed as u32)?,
             Type::Unsigned => to.write(&mut self.memory, &mut self.cpu, res)?,
             Type::Float => {
-                let res = f32::to_bits(f32::from_bits(first.val) - f32::from_bits(second.val));
+                let res = self.float_bits(f32::from_bits(first.val) - f32::from_bits(second.val));
                 to.write(&mut self.memory, &mut self.cpu, res)?;
                 return Ok(());
             }
         }
 
-        self.cpu.r = if overflow { 0b1 } else { 0b0 } | if overflow_signed { 0b10 } else { 0b0 };
+        self.cpu
+            .set_r(if overflow { 0b1 } else { 0b0 } | if overflow_signed { 0b10 } else { 0b0 });
         Ok(())
     }
 
+    /// Traps in trapping mode if the operation of the given type overflowed. Returns whether the
+    /// trap handler took care of the instruction.
+    fn check_overflow(
+        &mut self,
+        to: Writable,
+        kind: Type,
+        overflow: bool,
+        overflow_signed: bool,
+    ) -> Result<bool, Tx8Error> {
+        let overflow = match kind {
+            Type::Signed => overflow_signed,
+            Type::Unsigned => overflow,
+            Type::Float => false,
+        };
+        if self.trap_overflow && overflow {
+            self.trap(TrapKind::Overflow, to)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Lets the trap handler resolve a fault of an instruction writing to `to`, fails with the
+    /// error of the fault without a handler
+    fn trap(&mut self, kind: TrapKind, to: Writable) -> Result<(), Tx8Error> {
+        let error = match kind {
+            TrapKind::DivisionByZero => Tx8Error::DivisionByZero,
+            TrapKind::Overflow => Tx8Error::ArithmeticOverflow,
+        };
+        let Some(handler) = &mut self.trap_handler else {
+            return Err(error);
+        };
+        match handler(kind, &mut self.cpu, &mut self.memory) {
+            TrapAction::Abort => Err(error),
+            TrapAction::Resume(val) => to.write(&mut self.memory, &mut self.cpu, val),
+            TrapAction::Skip => Ok(()),
+        }
+    }
+
     fn mul(&mut self, to: Writable, val: Value, val2: Value, kind: Type) -> Result<(), Tx8Error> {
         match kind {
             Type::Signed => {
                 let res = val.val as i32 as i64 * val2.val as i32 as i64;
+                let overflow = match to.size() {
+                    Size::Byte => i8::try_from(res).is_err(),
+                    Size::Short => i16::try_from(res).is_err(),
+                    Size::Int => i32::try_from(res).is_err(),
+                };
+                if self.check_overflow(to, kind, false, overflow)? {
+                    return Ok(());
+                }
                 to.write(&mut self.memory, &mut self.cpu, res as u32)?;
-                self.cpu.r = (res >> 32) as u32;
+                self.cpu.set_r((res >> 32) as u32);
             }
             Type::Unsigned => {
                 let res = val.val as u64 * val2.val as u64;
+                let overflow = match to.size() {
+                    Size::Byte => u8::try_from(res).is_err(),
+                    Size::Short => u16::try_from(res).is_err(),
+                    Size::Int => u32::try_from(res).is_err(),
+                };
+                if self.check_overflow(to, kind, overflow, false)? {
+                    return Ok(());
+                }
                 to.write(&mut self.memory, &mut self.cpu, res as u32)?;
-                self.cpu.r = (res >> 32) as u32;
+                self.cpu.set_r((res >> 32) as u32);
             }
             Type::Float => {
-                let res = f32::to_bits(f32::from_bits(val.val) * f32::from_bits(val2.val));
-                to.write(&mut self.memory, &mut self.cpu, res as u32)?;
+                let res = self.float_bits(f32::from_bits(val.val) * f32::from_bits(val2.val));
+                to.write(&mut self.memory, &mut self.cpu, res)?;
             }
         }
         Ok(())
@@ -340,12 +1415,13 @@ impl<'a> Execution<'a> {
         is_div: bool,
     ) -> Result<(), Tx8Error> {
         if kind != Type::Float && val2.val == 0 {
-            return Err(Tx8Error::DivisionByZero);
+            return self.trap(TrapKind::DivisionByZero, to);
         }
         let (res, remainder) = match kind {
             Type::Signed => {
-                let res = val.val as i32 / val2.val as i32;
-                let remainder = val.val as i32 % val2.val as i32;
+                // the smallest integer divided by -1 wraps around instead of panicking
+                let res = (val.val as i32).wrapping_div(val2.val as i32);
+                let remainder = (val.val as i32).wrapping_rem(val2.val as i32);
                 (res as u32, remainder as u32)
             }
             Type::Unsigned => {
@@ -354,21 +1430,67 @@ impl<'a> Execution<'a> {
                 (res, remainder)
             }
             Type::Float => {
-                let res = f32::to_bits(f32::from_bits(val.val) / f32::from_bits(val2.val));
-                let remainder = f32::to_bits(f32::from_bits(val.val) % f32::from_bits(val2.val));
+                let res = self.float_bits(f32::from_bits(val.val) / f32::from_bits(val2.val));
+                let remainder = self.float_bits(f32::from_bits(val.val) % f32::from_bits(val2.val));
                 (res, remainder)
             }
         };
         if is_div {
             to.write(&mut self.memory, &mut self.cpu, res)?;
-            self.cpu.r = remainder;
+            self.cpu.set_r(remainder);
         } else {
             to.write(&mut self.memory, &mut self.cpu, remainder)?;
-            self.cpu.r = res;
+            self.cpu.set_r(res);
         }
         Ok(())
     }
 
+    /// Increments or decrements without leaving the range of the size the value was read with.
+    /// `R` is 1 if the value was already at the limit.
+    fn inc_dec_saturating(
+        &mut self,
+        to: Writable,
+        val: Value,
+        is_inc: bool,
+    ) -> Result<(), Tx8Error> {
+        let max = val.size.mask();
+        let (res, saturated) = match is_inc {
+            true if val.val >= max => (max, true),
+            true => (val.val + 1, false),
+            false if val.val == 0 => (0, true),
+            false => (val.val - 1, false),
+        };
+        to.write(&mut self.memory, &mut self.cpu, res)?;
+        self.cpu.set_r(saturated as u32);
+        Ok(())
+    }
+
+    /// Stores the floor of the square root and the remainder to the next smaller square in `R`
+    fn isqrt(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
+        let root = val.val.isqrt();
+        to.write(&mut self.memory, &mut self.cpu, root)?;
+        self.cpu.set_r(val.val - root * root);
+        Ok(())
+    }
+
+    fn abs_diff(
+        &mut self,
+        to: Writable,
+        val: Value,
+        val2: Value,
+        kind: Type,
+    ) -> Result<(), Tx8Error> {
+        let res = match kind {
+            // the difference of two i32 always fits into an u32
+            Type::Signed => (val.val as i32).abs_diff(val2.val as i32),
+            Type::Unsigned => val.val.abs_diff(val2.val),
+            Type::Float => {
+                self.float_bits((f32::from_bits(val.val) - f32::from_bits(val2.val)).abs())
+            }
+        };
+        to.write(&mut self.memory, &mut self.cpu, res)
+    }
+
     fn max_min(
         &mut self,
         to: Writable,
@@ -393,7 +1515,20 @@ impl<'a> Execution<'a> {
                 }
             }
             Type::Float => {
-                if f32::from_bits(val.val) > f32::from_bits(val2.val) {
+                let (a, b) = (f32::from_bits(val.val), f32::from_bits(val2.val));
+                // like f32::max and f32::min a NaN is only kept if both operands are NaN
+                if a.is_nan() || b.is_nan() {
+                    let (kept, discarded) = if a.is_nan() && !b.is_nan() {
+                        (val2.val, val.val)
+                    } else {
+                        (val.val, val2.val)
+                    };
+                    let kept = self.float_bits(f32::from_bits(kept));
+                    to.write(&mut self.memory, &mut self.cpu, kept)?;
+                    self.cpu.set_r(discarded);
+                    return Ok(());
+                }
+                if a > b {
                     (val.val, val2.val)
                 } else {
                     (val2.val, val.val)
@@ -402,10 +1537,10 @@ impl<'a> Execution<'a> {
         };
         if is_max {
             to.write(&mut self.memory, &mut self.cpu, max)?;
-            self.cpu.r = min;
+            self.cpu.set_r(min);
         } else {
             to.write(&mut self.memory, &mut self.cpu, min)?;
-            self.cpu.r = max;
+            self.cpu.set_r(max);
         }
         Ok(())
     }
@@ -436,16 +1571,17 @@ impl<'a> Execution<'a> {
                 } else if value < 0.0 {
                     (f32::to_bits(value.neg()), f32::to_bits(-1.0))
                 } else {
-                    (val.val, f32::to_bits(1.0))
+                    // NaN ends up here as well
+                    (self.float_bits(value), f32::to_bits(1.0))
                 }
             }
         };
         if is_abs {
             to.write(&mut self.memory, &mut self.cpu, res)?;
-            self.cpu.r = sign as u32;
+            self.cpu.set_r(sign);
         } else {
-            to.write(&mut self.memory, &mut self.cpu, sign as u32)?;
-            self.cpu.r = res;
+            to.write(&mut self.memory, &mut self.cpu, sign)?;
+            self.cpu.set_r(res);
         }
         Ok(())
     }
@@ -471,34 +1607,25 @@ impl<'a> Execution<'a> {
     }
 
     fn slr(&mut self, to: Writable, val: Value, val2: Value) -> Result<(), Tx8Error> {
-        let filter = match to.size() {
-            Size::Byte => 0b111,
-            Size::Short => 0b1111,
-            Size::Int => 0b11111,
-        };
-        let shift_amount = val2.val & filter;
-        let res = val.val >> shift_amount;
-        let shifted_out = val.val & ((1 << shift_amount) - 1);
+        let (val, shift_amount) = shift_operands(val, val2);
+        let res = val >> shift_amount;
+        let shifted_out = val & ((1 << shift_amount) - 1);
         to.write(&mut self.memory, &mut self.cpu, res)?;
-        self.cpu.r = shifted_out;
+        self.cpu.set_r(shifted_out);
         Ok(())
     }
 
     fn sar(&mut self, to: Writable, val: Value, val2: Value) -> Result<(), Tx8Error> {
-        let filter = match to.size() {
-            Size::Byte => 0b111,
-            Size::Short => 0b1111,
-            Size::Int => 0b11111,
-        };
-        let shift_amount = val2.val & filter;
-        let res = match to.size() {
-            Size::Byte => (val.val as i8 >> shift_amount) as i32,
-            Size::Short => (val.val as i16 >> shift_amount) as i32,
-            Size::Int => val.val as i32 >> shift_amount,
+        let size = val.size;
+        let (val, shift_amount) = shift_operands(val, val2);
+        let res = match size {
+            Size::Byte => (val as i8 >> shift_amount) as i32,
+            Size::Short => (val as i16 >> shift_amount) as i32,
+            Size::Int => val as i32 >> shift_amount,
         };
-        let shifted_out = val.val & ((1 << shift_amount) - 1);
+        let shifted_out = val & ((1 << shift_amount) - 1);
         to.write(&mut self.memory, &mut self.cpu, res as u32)?;
-        self.cpu.r = shifted_out;
+        self.cpu.set_r(shifted_out);
         Ok(())
     }
 
@@ -516,7 +1643,7 @@ impl<'a> Execution<'a> {
             val.val >> (size - shift_amount)
         };
         to.write(&mut self.memory, &mut self.cpu, res)?;
-        self.cpu.r = shifted_out;
+        self.cpu.set_r(shifted_out);
         Ok(())
     }
 
@@ -549,9 +1676,9 @@ impl<'a> Execution<'a> {
         to.write(&mut self.memory, &mut self.cpu, res)?;
         let bit = val.val & (1 << i);
         if bit != 0 {
-            self.cpu.r = 1;
+            self.cpu.set_r(1);
         } else {
-            self.cpu.r = 0;
+            self.cpu.set_r(0);
         }
         Ok(())
     }
@@ -567,9 +1694,9 @@ impl<'a> Execution<'a> {
         to.write(&mut self.memory, &mut self.cpu, res)?;
         let bit = val.val & (1 << i);
         if bit != 0 {
-            self.cpu.r = 1;
+            self.cpu.set_r(1);
         } else {
-            self.cpu.r = 0;
+            self.cpu.set_r(0);
         }
         Ok(())
     }
@@ -585,13 +1712,33 @@ impl<'a> Execution<'a> {
         to.write(&mut self.memory, &mut self.cpu, res)?;
         let bit = val.val & (1 << i);
         if bit != 0 {
-            self.cpu.r = 1;
+            self.cpu.set_r(1);
         } else {
-            self.cpu.r = 0;
+            self.cpu.set_r(0);
         }
         Ok(())
     }
 
+    fn byte_swap(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
+        let size = to.size();
+        let res = match size {
+            Size::Byte => val.val & 0xff,
+            Size::Short => (val.val as u16).swap_bytes() as u32,
+            Size::Int => val.val.swap_bytes(),
+        };
+        to.write_size(&mut self.memory, &mut self.cpu, res, size)
+    }
+
+    fn bit_reverse(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
+        let size = to.size();
+        let res = match size {
+            Size::Byte => (val.val as u8).reverse_bits() as u32,
+            Size::Short => (val.val as u16).reverse_bits() as u32,
+            Size::Int => val.val.reverse_bits(),
+        };
+        to.write_size(&mut self.memory, &mut self.cpu, res, size)
+    }
+
     fn test(&mut self, val: Value, val2: Value) {
         let filter = match val.size {
             Size::Byte => 0b111,
@@ -601,75 +1748,113 @@ impl<'a> Execution<'a> {
         let i = val2.val & filter;
         let res = val.val & (1 << i);
         if res != 0 {
-            self.cpu.r = 1;
+            self.cpu.set_r(1);
         } else {
-            self.cpu.r = 0;
+            self.cpu.set_r(0);
+        }
+    }
+
+    /// The bits a float instruction stores for its result
+    fn float_bits(&self, val: f32) -> u32 {
+        if self.canonical_nan && val.is_nan() {
+            CANONICAL_NAN
+        } else {
+            val.to_bits()
         }
     }
 
     fn sin(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).sin();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).sin());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn cos(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).cos();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).cos());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn tan(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).tan();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).tan());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn arcsin(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).asin();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).asin());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn arccos(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).acos();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).acos());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn arctan(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).atan();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).atan());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn arctan2(&mut self, to: Writable, val: Value, val2: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).atan2(f32::from_bits(val2.val));
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).atan2(f32::from_bits(val2.val)));
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn sqrt(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).sqrt();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).sqrt());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn pow(&mut self, to: Writable, val: Value, val2: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).powf(f32::from_bits(val2.val));
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).powf(f32::from_bits(val2.val)));
+        to.write(&mut self.memory, &mut self.cpu, res)
+    }
+
+    /// Computes p1 * p2 + R with a single rounding
+    fn fma(&mut self, to: Writable, val: Value, val2: Value) -> Result<(), Tx8Error> {
+        let res = self.float_bits(
+            f32::from_bits(val.val).mul_add(f32::from_bits(val2.val), f32::from_bits(self.cpu.r)),
+        );
+        to.write(&mut self.memory, &mut self.cpu, res)
+    }
+
+    fn floor(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
+        let res = self.float_bits(f32::from_bits(val.val).floor());
+        to.write(&mut self.memory, &mut self.cpu, res)
+    }
+
+    fn ceil(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
+        let res = self.float_bits(f32::from_bits(val.val).ceil());
+        to.write(&mut self.memory, &mut self.cpu, res)
+    }
+
+    /// Rounds half-way cases away from zero
+    fn round(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
+        let res = self.float_bits(f32::from_bits(val.val).round());
+        to.write(&mut self.memory, &mut self.cpu, res)
+    }
+
+    fn trunc(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
+        let res = self.float_bits(f32::from_bits(val.val).trunc());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn exp(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).exp();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).exp());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn log(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).ln();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).ln());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn log2(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).log2();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).log2());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn log10(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
-        let res = f32::from_bits(val.val).log10();
-        to.write(&mut self.memory, &mut self.cpu, f32::to_bits(res))
+        let res = self.float_bits(f32::from_bits(val.val).log10());
+        to.write(&mut self.memory, &mut self.cpu, res)
     }
 
     fn i_to_f(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
@@ -688,6 +1873,25 @@ impl<'a> Execution<'a> {
         )
     }
 
+    /// Converts a float to a signed fixed-point number of the destination size with `frac`
+    /// fractional bits, rounding to the nearest value and saturating at the limits of the size
+    fn float_to_fixed(&mut self, to: Writable, val: Value, frac: Value) -> Result<(), Tx8Error> {
+        let size = to.size();
+        let limit = (size.mask() >> 1) as f64;
+        let scaled =
+            (f32::from_bits(val.val) as f64 * (1u64 << (frac.val & 0b11111)) as f64).round();
+        // NaN has no meaningful fixed-point value and becomes 0 like in a saturating cast
+        let res = scaled.clamp(-limit - 1.0, limit) as i64 as u32;
+        to.write_size(&mut self.memory, &mut self.cpu, res & size.mask(), size)
+    }
+
+    /// Converts a signed fixed-point number with `frac` fractional bits to a float
+    fn fixed_to_float(&mut self, to: Writable, val: Value, frac: Value) -> Result<(), Tx8Error> {
+        let res = val.val as i32 as f64 / (1u64 << (frac.val & 0b11111)) as f64;
+        let res = self.float_bits(res as f32);
+        to.write_size(&mut self.memory, &mut self.cpu, res, Size::Int)
+    }
+
     fn u_to_f(&mut self, to: Writable, val: Value) -> Result<(), Tx8Error> {
         to.write(
             &mut self.memory,
@@ -708,7 +1912,7 @@ impl<'a> Execution<'a> {
         let res = self.rand.next();
         let float = res as f32 / RANGE as f32;
         to.write(&mut self.memory, &mut self.cpu, f32::to_bits(float))?;
-        self.cpu.r = res;
+        self.cpu.set_r(res);
         Ok(())
     }
 
@@ -717,17 +1921,2085 @@ impl<'a> Execution<'a> {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Effect {
     None,
-    Halted,
+    Halted(HaltReason),
+    /// A `brk` instruction was executed, the next step continues after it
+    BreakpointHit,
+    /// The system call with this hash is about to be executed, see
+    /// `Execution::set_pause_on_sys_call`
+    SysCallPending(u32),
+    /// `run_steps` executed all of its steps, the next call continues where it stopped
+    Yielded,
+}
+
+/// Where system calls read their arguments from and store their results. `print_*` and `exit`
+/// always read the top of the stack and the `test_*` calls always print their register.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SysCallAbi {
+    /// Arguments are read from `A`, `B` and `C`, `read_char` stores the character in `O`
+    #[default]
+    Legacy,
+    /// Arguments are read from the stack with the first argument on top like for a function
+    /// call, results are stored in `R`. `parse_int` and `parse_float` replace their argument
+    /// with the number of bytes consumed.
+    Stack,
+}
+
+/// A fault a trap handler can resolve, see `Execution::set_trap_handler`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapKind {
+    /// Integer `div` or `mod` by zero
+    DivisionByZero,
+    /// An overflow of `add`, `sub` or `mul` with trapping overflow enabled
+    Overflow,
+}
+
+/// How a trap handler resolves a fault
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapAction {
+    /// Fail with the error of the fault like without a handler
+    Abort,
+    /// Write this value to the destination instead of the result
+    Resume(u32),
+    /// Leave the destination unchanged
+    Skip,
+}
+
+/// Why a program stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HaltReason {
+    /// A `hlt` instruction was executed
+    HaltOpcode,
+    /// The `exit` system call was called with this exit code
+    Exit(u32),
+    /// The program counter left the code, see `Execution::set_code_bounds_check`
+    RanOffEnd,
+}
+
+/// Masks the shifted value to the size it was read with and the shift amount to the bits
+/// addressing a bit of that size
+fn shift_operands(val: Value, amount: Value) -> (u32, u32) {
+    let filter = match val.size {
+        Size::Byte => 0b111,
+        Size::Short => 0b1111,
+        Size::Int => 0b11111,
+    };
+    (val.val & val.size.mask(), amount.val & filter)
+}
+
+/// Returns the register encodings selected by a register mask, bit 0 is `a` up to bit 5 for `o`
+fn mask_registers(mask: u32) -> Result<Vec<u8>, Tx8Error> {
+    if mask & !0x3f != 0 {
+        return Err(Tx8Error::InstructionError);
+    }
+    Ok((0..6).filter(|reg| mask & (1 << reg) != 0).collect())
+}
+
+/// Fails with the error for `start` if the `len` bytes starting there don't fit into memory.
+/// System calls processing blocks don't wrap around at the end of memory like single accesses.
+fn check_block(start: u32, len: u32, error: fn(u32) -> Tx8Error) -> Result<(), Tx8Error> {
+    if start as u64 + len as u64 > MB_16 as u64 {
+        return Err(error(start));
+    }
+    Ok(())
 }
 
-fn hash(s: &str) -> u32 {
+pub(crate) fn hash(s: &str) -> u32 {
     let mut s = s.chars();
     let mut h = s.next().unwrap_or(0 as char) as u32;
     for c in s {
-        h = (h << 5) - h + (c as u32);
+        h = (h << 5).wrapping_sub(h).wrapping_add(c as u32);
     }
     h
 }
+
+/// Whether the comparison result in `R` satisfies the condition of a conditional instruction
+fn eval_condition(cpu: &Cpu, comp: Comparison) -> bool {
+    let r = cpu.r as i32;
+    // unordered float comparisons only satisfy "not equal"
+    let ordered = !cpu.unordered;
+    match comp {
+        Comparison::None => true,
+        Comparison::Equal => r == 0,
+        Comparison::NotEqual => r != 0,
+        Comparison::Greater => r > 0 && ordered,
+        Comparison::GreaterEqual => r >= 0 && ordered,
+        Comparison::Less => r < 0,
+        Comparison::LessEqual => r <= 0,
+    }
+}
+
+fn compare_signed(val: Value, val2: u32) -> u32 {
+    let ordering = match val.size {
+        Size::Byte => (val.val as i8).cmp(&(val2 as i8)),
+        Size::Short => (val.val as i16).cmp(&(val2 as i16)),
+        Size::Int => (val.val as i32).cmp(&(val2 as i32)),
+    };
+    ordering as i32 as u32
+}
+
+fn compare_float(val: u32, val2: u32) -> u32 {
+    (match f32::from_bits(val).partial_cmp(&f32::from_bits(val2)) {
+        Some(Ordering::Less) => -1,
+        Some(Ordering::Equal) => 0,
+        Some(Ordering::Greater) => 1,
+        None => UNORDERED,
+    }) as u32
+}
+
+fn compare_unsigned(val: u32, val2: u32) -> u32 {
+    val.cmp(&val2) as i32 as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::SharedBuffer;
+
+    /// Creates an execution of the code with the data loaded at the given addresses
+    fn harness(code: &[u8], data: &[(u32, &[u8])]) -> Execution<'static> {
+        Execution::test_harness(code, data).unwrap()
+    }
+
+    /// Steps until the program halts, failing the test if a step fails
+    fn run_to_halt(execution: &mut Execution) {
+        while !matches!(execution.next_step().unwrap(), Effect::Halted(_)) {}
+    }
+
+    /// Runs the code with the data loaded at the given addresses and the given register values
+    /// until it halts
+    fn run_harness(
+        code: &[u8],
+        data: &[(u32, &[u8])],
+        registers: &[(Register, u32)],
+    ) -> Execution<'static> {
+        let mut execution = harness(code, data);
+        for &(reg, val) in registers {
+            execution.set_register(reg, val);
+        }
+        run_to_halt(&mut execution);
+        execution
+    }
+
+    /// Runs the code with the given register values until it halts
+    fn run(code: &[u8], registers: &[(Register, u32)]) -> Execution<'static> {
+        run_harness(code, &[], registers)
+    }
+
+    #[test]
+    fn compare_into_writes_each_destination() {
+        // cmpto a c, cmpto b c, hlt
+        let code = [0x83, 0x66, 0x00, 0x02, 0x83, 0x66, 0x01, 0x02, 0x00];
+        let registers = [
+            (Register::A, 5),
+            (Register::B, -3i32 as u32),
+            (Register::C, 0),
+            (Register::R, 0x5a),
+        ];
+        let execution = run(&code, &registers);
+        assert_eq!(execution.get_register(Register::A), 1);
+        assert_eq!(execution.get_register(Register::B), -1i32 as u32);
+        assert_eq!(execution.get_register(Register::R), 0x5a);
+    }
+
+    #[test]
+    fn compare_into_handles_extreme_values() {
+        // cmpto ab bb, cmpto bb ab, hlt
+        let code = [0x83, 0x66, 0x10, 0x11, 0x83, 0x66, 0x11, 0x10, 0x00];
+        let execution = run(&code, &[(Register::A, 0x7f), (Register::B, 0x80)]);
+        assert_eq!(execution.get_register(Register::A), 0x01);
+        assert_eq!(execution.get_register(Register::B), 0xff);
+
+        // cmpto a b, ucmpto c d, hlt
+        let code = [0x83, 0x66, 0x00, 0x01, 0x85, 0x66, 0x02, 0x03, 0x00];
+        let registers = [
+            (Register::A, i32::MIN as u32),
+            (Register::B, i32::MAX as u32),
+            (Register::C, u32::MAX),
+            (Register::D, 0),
+        ];
+        let execution = run(&code, &registers);
+        assert_eq!(execution.get_register(Register::A), -1i32 as u32);
+        assert_eq!(execution.get_register(Register::C), 1);
+    }
+
+    #[test]
+    fn float_compare_into_reports_nan_as_unordered() {
+        // fcmpto a b, fcmpto c d, hlt
+        let code = [0x84, 0x66, 0x00, 0x01, 0x84, 0x66, 0x02, 0x03, 0x00];
+        let registers = [
+            (Register::A, f32::NAN.to_bits()),
+            (Register::B, 1.0f32.to_bits()),
+            (Register::C, f32::NEG_INFINITY.to_bits()),
+            (Register::D, f32::MIN.to_bits()),
+        ];
+        let execution = run(&code, &registers);
+        assert_eq!(execution.get_register(Register::A), UNORDERED as u32);
+        assert_eq!(execution.get_register(Register::C), -1i32 as u32);
+    }
+
+    #[test]
+    fn reset_restores_the_state_after_construction() {
+        // sys read_char, ld #c01000 o, push a, sys print_u32, hlt
+        let mut code = vec![0x0e, 0x30];
+        code.extend(hash("read_char").to_le_bytes());
+        code.extend([
+            0x10, 0x46, 0x00, 0x10, 0xc0, 0x05, 0x1d, 0x60, 0x00, 0x0e, 0x30,
+        ]);
+        code.extend(hash("print_u32").to_le_bytes());
+        code.push(0x00);
+        let mut execution = harness(&code, &[]);
+        execution.set_input(b"xy".to_vec());
+        execution.set_budget(Some(10));
+        execution.set_output_limit(Some(4));
+        execution.set_replay_log(8);
+        let memory = execution.memory().export();
+        let registers = Register::ALL.map(|reg| execution.get_register(reg));
+
+        execution.set_register(Register::A, 7);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.memory().read_byte(0xc01000), b'x');
+        assert_eq!(execution.remaining_budget(), Some(5));
+        assert_eq!(execution.remaining_output(), Some(3));
+
+        execution.reset();
+        assert!(execution.memory().export() == memory);
+        assert_eq!(
+            Register::ALL.map(|reg| execution.get_register(reg)),
+            registers
+        );
+        assert_eq!(execution.remaining_budget(), Some(10));
+        assert_eq!(execution.remaining_output(), Some(4));
+        assert_eq!(execution.instruction_count(), 0);
+        assert_eq!(execution.recent_instructions().len(), 0);
+
+        // the input is read from the start again
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::O), b'x' as u32);
+    }
+
+    #[test]
+    fn reset_refills_an_exhausted_budget() {
+        // nop, nop, hlt
+        let mut execution = harness(&[0x01, 0x01, 0x00], &[]);
+        execution.set_budget(Some(2));
+        execution.next_step().unwrap();
+        execution.next_step().unwrap();
+        assert_eq!(execution.next_step(), Err(Tx8Error::BudgetExhausted));
+        execution.reset();
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+    }
+
+    #[test]
+    fn crc32_sys_call_matches_the_check_value() {
+        // sys crc32, hlt
+        let mut code = vec![0x0e, 0x30];
+        code.extend(hash("crc32").to_le_bytes());
+        code.push(0x00);
+        let execution = run_harness(
+            &code,
+            &[(0xc01000, b"123456789")],
+            &[(Register::A, 0xc01000), (Register::B, 9)],
+        );
+        assert_eq!(execution.get_register(Register::R), 0xcbf43926);
+    }
+
+    // inc a; rep c 0x400000u32; hlt
+    const COUNTED_LOOP: [u8; 11] = [
+        0x20, 0x60, 0x00, 0x9f, 0x63, 0x02, 0x00, 0x00, 0x40, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn repeat_runs_the_loop_count_times() {
+        let execution = run(&COUNTED_LOOP, &[(Register::C, 5)]);
+        assert_eq!(execution.get_register(Register::A), 5);
+        assert_eq!(execution.get_register(Register::C), 0);
+    }
+
+    #[test]
+    fn repeat_with_a_zero_count_runs_the_loop_once() {
+        let execution = run(&COUNTED_LOOP, &[(Register::C, 0)]);
+        assert_eq!(execution.get_register(Register::A), 1);
+        assert_eq!(execution.get_register(Register::C), 0);
+    }
+
+    // runs the code after storing `word` at 0xc01000
+    fn run_on_word(code: &[u8], word: u32, registers: &[(Register, u32)]) -> Execution<'static> {
+        let mut execution = harness(code, &[]);
+        execution.memory_mut().write_int(0xc01000, word);
+        for &(reg, val) in registers {
+            execution.set_register(reg, val);
+        }
+        run_to_halt(&mut execution);
+        execution
+    }
+
+    #[test]
+    fn fetch_add_stores_the_sum_and_returns_the_previous_value() {
+        // xadd #c01000 2; hlt
+        let code = [0xa0, 0x43, 0x00, 0x10, 0xc0, 0x02, 0x00, 0x00, 0x00, 0x00];
+        let mut execution = run_on_word(&code, 40, &[]);
+        assert_eq!(execution.memory_mut().read_int(0xc01000), 42);
+        assert_eq!(execution.get_register(Register::R), 40);
+
+        let mut execution = run_on_word(&code, u32::MAX, &[]);
+        assert_eq!(execution.memory_mut().read_int(0xc01000), 1);
+        assert_eq!(execution.get_register(Register::R), u32::MAX);
+    }
+
+    #[test]
+    fn fetch_add_takes_the_size_of_the_value_for_memory_targets() {
+        // xadd #c01000 2u8; hlt
+        let code = [0xa0, 0x41, 0x00, 0x10, 0xc0, 0x02, 0x00];
+        let mut execution = run_on_word(&code, 0x5555_77ff, &[]);
+        assert_eq!(execution.memory_mut().read_int(0xc01000), 0x5555_7701);
+        assert_eq!(execution.get_register(Register::R), 0xff);
+
+        // xadd #c01000 bs; hlt
+        let code = [0xa0, 0x46, 0x00, 0x10, 0xc0, 0x21, 0x00];
+        let mut execution = run_on_word(&code, 0x5555_fff0, &[(Register::B, 0x1234_0003)]);
+        assert_eq!(execution.memory_mut().read_int(0xc01000), 0x5555_fff3);
+        assert_eq!(execution.get_register(Register::R), 0xfff0);
+    }
+
+    #[test]
+    fn fetch_add_wraps_within_the_register_view() {
+        // xadd ab 1u8; hlt
+        let code = [0xa0, 0x61, 0x10, 0x01, 0x00];
+        let execution = run(&code, &[(Register::A, 0x1234_56ff)]);
+        assert_eq!(execution.get_register(Register::A), 0x1234_5600);
+        assert_eq!(execution.get_register(Register::R), 0xff);
+    }
+
+    #[test]
+    fn fetch_add_leaves_r_to_the_trap_handler() {
+        // xadd a 1u8; hlt
+        let code = [0xa0, 0x61, 0x00, 0x01, 0x00];
+        for (action, a) in [(TrapAction::Skip, u32::MAX), (TrapAction::Resume(7), 7)] {
+            let mut execution = harness(&code, &[]);
+            execution.set_trap_overflow(true);
+            execution.set_trap_handler(Some(Box::new(move |_, cpu, _| {
+                cpu.r = 99;
+                action
+            })));
+            execution.set_register(Register::A, u32::MAX);
+            run_to_halt(&mut execution);
+            assert_eq!(execution.get_register(Register::A), a, "{action:?}");
+            assert_eq!(execution.get_register(Register::R), 99, "{action:?}");
+        }
+    }
+
+    #[test]
+    fn replay_log_keeps_the_instructions_before_a_crash() {
+        // ld a 5u8; inc a; div a 0u8; hlt
+        let code = [
+            0x10, 0x61, 0x00, 0x05, 0x20, 0x60, 0x00, 0x25, 0x61, 0x00, 0x00, 0x00,
+        ];
+        let mut execution = harness(&code, &[]);
+        execution.set_replay_log(2);
+        let error = loop {
+            if let Err(error) = execution.next_step() {
+                break error;
+            }
+        };
+        assert_eq!(error, Tx8Error::DivisionByZero);
+        let log: Vec<String> = execution
+            .recent_instructions()
+            .map(|(pc, instruction)| format!("{pc:x} {instruction}"))
+            .collect();
+        assert_eq!(log, ["400004 uadd a 1", "400007 div a 0"]);
+    }
+
+    // sys name; hlt
+    fn sys_call_code(name: &str) -> Vec<u8> {
+        let mut code = vec![0x0e, 0x30];
+        code.extend(hash(name).to_le_bytes());
+        code.push(0x00);
+        code
+    }
+
+    #[test]
+    fn count_bytes_counts_the_matching_bytes() {
+        let code = sys_call_code("count_bytes");
+        let text = b"count the spaces in here";
+        let mut execution = run_harness(
+            &code,
+            &[(0xc01000, text)],
+            &[
+                (Register::A, 0xc01000),
+                (Register::B, text.len() as u32),
+                (Register::C, b' ' as u32),
+            ],
+        );
+        assert_eq!(execution.get_register(Register::R), 4);
+
+        execution.reset();
+        execution.set_register(Register::A, 0xc01000);
+        execution.set_register(Register::B, text.len() as u32);
+        execution.set_register(Register::C, b'x' as u32);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::R), 0);
+    }
+
+    #[test]
+    fn block_sys_calls_fail_at_the_end_of_memory() {
+        for (name, error) in [
+            ("count_bytes", Tx8Error::ReadViolation(0xfffff0)),
+            ("crc32", Tx8Error::ReadViolation(0xfffff0)),
+            ("memcpy", Tx8Error::OutOfBoundsWrite(0xfffff0)),
+        ] {
+            let mut execution = harness(&sys_call_code(name), &[]);
+            // count_bytes and crc32 read at a, memcpy reads at b and writes at a
+            execution.set_register(Register::A, 0xfffff0);
+            execution.set_register(Register::B, 0x11);
+            execution.set_register(Register::C, 0x11);
+            if name == "memcpy" {
+                execution.set_register(Register::B, 0xc01000);
+            }
+            assert_eq!(execution.next_step(), Err(error), "{name}");
+        }
+    }
+
+    // runs `sys name` with the arguments in `a`, `b` and `c` or on the stack, the other
+    // registers start at 0x77
+    fn call_with_abi(
+        abi: SysCallAbi,
+        name: &str,
+        args: &[u32],
+        input: &[u8],
+    ) -> Execution<'static> {
+        let text: &'static [u8] = b"  -42 text with spaces";
+        let mut execution = harness(&sys_call_code(name), &[(0xd00000, text)]);
+        execution.set_sys_call_abi(abi);
+        execution.set_input(input.to_vec());
+        for reg in [
+            Register::A,
+            Register::B,
+            Register::C,
+            Register::D,
+            Register::O,
+            Register::R,
+        ] {
+            execution.set_register(reg, 0x77);
+        }
+        match abi {
+            SysCallAbi::Legacy => {
+                for (reg, &arg) in [Register::A, Register::B, Register::C]
+                    .into_iter()
+                    .zip(args)
+                {
+                    execution.set_register(reg, arg);
+                }
+            }
+            SysCallAbi::Stack => {
+                let s = STACK_TOP - 4 * args.len() as u32;
+                execution.set_register(Register::S, s);
+                for (i, &arg) in args.iter().enumerate() {
+                    execution.memory_mut().write_int(s + 4 * i as u32, arg);
+                }
+            }
+        }
+        run_to_halt(&mut execution);
+        execution
+    }
+
+    #[test]
+    fn sys_calls_store_their_results_in_the_legacy_registers() {
+        let abi = SysCallAbi::Legacy;
+        let execution = call_with_abi(abi, "read_char", &[], b"z");
+        assert_eq!(execution.get_register(Register::O), b'z' as u32);
+        assert_eq!(execution.get_register(Register::R), 0x77);
+
+        let execution = call_with_abi(abi, "parse_int", &[0xd00000], b"");
+        assert_eq!(execution.get_register(Register::R), -42i32 as u32);
+        assert_eq!(execution.get_register(Register::B), 5);
+
+        let execution = call_with_abi(abi, "count_bytes", &[0xd00000, 22, b' ' as u32], b"");
+        assert_eq!(execution.get_register(Register::R), 5);
+
+        let mut execution = call_with_abi(abi, "read_line", &[0xd00100, 8], b"line\nrest");
+        assert_eq!(execution.get_register(Register::R), 4);
+        assert_eq!(
+            execution.memory_mut().read_int(0xd00100),
+            u32::from_le_bytes(*b"line")
+        );
+
+        let execution = call_with_abi(abi, "input_available", &[], b"abc");
+        assert_eq!(execution.get_register(Register::R), 3);
+    }
+
+    #[test]
+    fn sys_calls_store_their_results_in_r_with_the_stack_abi() {
+        let abi = SysCallAbi::Stack;
+        let execution = call_with_abi(abi, "read_char", &[], b"z");
+        assert_eq!(execution.get_register(Register::R), b'z' as u32);
+        assert_eq!(execution.get_register(Register::O), 0x77);
+
+        let mut execution = call_with_abi(abi, "parse_int", &[0xd00000], b"");
+        assert_eq!(execution.get_register(Register::R), -42i32 as u32);
+        assert_eq!(execution.get_register(Register::B), 0x77);
+        assert_eq!(execution.memory_mut().read_int(STACK_TOP - 4), 5);
+
+        let execution = call_with_abi(abi, "count_bytes", &[0xd00000, 22, b' ' as u32], b"");
+        assert_eq!(execution.get_register(Register::R), 5);
+        assert_eq!(execution.get_register(Register::A), 0x77);
+
+        let mut execution = call_with_abi(abi, "read_line", &[0xd00100, 8], b"line\nrest");
+        assert_eq!(execution.get_register(Register::R), 4);
+        assert_eq!(
+            execution.memory_mut().read_int(0xd00100),
+            u32::from_le_bytes(*b"line")
+        );
+
+        let execution = call_with_abi(abi, "input_available", &[], b"abc");
+        assert_eq!(execution.get_register(Register::R), 3);
+    }
+
+    #[test]
+    fn load_address_computes_absolute_and_relative_addresses_without_reading() {
+        // lea a #c01234; lea b $-4; hlt
+        let code = [
+            0x90, 0x64, 0x00, 0x34, 0x12, 0xc0, 0x90, 0x65, 0x01, 0xfc, 0xff, 0xff, 0x00,
+        ];
+        let mut execution = harness(&code, &[]);
+        execution.memory_mut().write_int(0xc01234, 0xdeadbeef);
+        execution.memory_mut().write_int(0xc00ffc, 0xdeadbeef);
+        execution.set_register(Register::O, 0xc01000);
+        execution.set_replay_log(2);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::A), 0xc01234);
+        assert_eq!(execution.get_register(Register::B), 0xc00ffc);
+        let (_, instruction) = execution.recent_instructions().next().unwrap();
+        assert_eq!(instruction.to_string(), "lea b 0xc00ffc");
+    }
+
+    #[test]
+    fn get_pc_loads_its_own_address() {
+        // nop; getpc c; getpc #c01000; hlt
+        let code = [0x01, 0x93, 0x60, 0x02, 0x93, 0x40, 0x00, 0x10, 0xc0, 0x00];
+        let mut execution = run(&code, &[]);
+        assert_eq!(execution.get_register(Register::C), 0x400001);
+        assert_eq!(execution.memory_mut().read_int(0xc01000), 0x400004);
+    }
+
+    #[test]
+    fn enter_and_leave_frame_a_local() {
+        // enter 8, ld [s] b, pop a, leave, hlt
+        let code = [
+            0x94, 0x10, 0x08, 0x10, 0x76, 0x07, 0x01, 0x1e, 0x60, 0x00, 0x95, 0x00,
+        ];
+        let registers = [(Register::B, 0x12345678), (Register::O, 0x1234)];
+        let execution = run(&code, &registers);
+        assert_eq!(execution.get_register(Register::A), 0x12345678);
+        assert_eq!(execution.get_register(Register::O), 0x1234);
+        assert_eq!(execution.get_register(Register::S), STACK_TOP);
+    }
+
+    #[test]
+    fn enter_fails_if_the_frame_leaves_the_stack() {
+        // enter 0x2000
+        let code = [0x94, 0x20, 0x00, 0x20];
+        let mut execution = harness(&code, &[]);
+        assert!(matches!(
+            execution.next_step(),
+            Err(Tx8Error::StackOverflow)
+        ));
+    }
+
+    #[test]
+    fn rand_bytes_fills_memory_from_the_seeded_generator() {
+        // rseed 42, sys rand_bytes, hlt
+        let mut code = vec![0x71, 0x10, 0x2a];
+        code.extend(sys_call_code("rand_bytes"));
+        let mut used = Vec::new();
+        for len in [0, 4] {
+            let mut execution = harness(&code, &[]);
+            execution.set_budget(Some(100));
+            execution.set_register(Register::A, 0xd00000);
+            execution.set_register(Register::B, len);
+            run_to_halt(&mut execution);
+            let bytes: Vec<u8> = (0..5)
+                .map(|i| execution.memory().read_byte(0xd00000 + i))
+                .collect();
+            let expected: &[u8] = match len {
+                0 => &[0; 5],
+                _ => &[0xaf, 0x20, 0xfd, 0xca, 0x00],
+            };
+            assert_eq!(bytes, expected);
+            used.push(100 - execution.remaining_budget().unwrap());
+        }
+        assert_eq!(used[1] - used[0], 4);
+    }
+
+    #[test]
+    fn poisoned_stack_reads_fail_until_written() {
+        // sub s 4, ld a [s], hlt
+        let code = [0x23, 0x61, 0x07, 0x04, 0x10, 0x67, 0x00, 0x07, 0x00];
+        let mut execution = harness(&code, &[]);
+        execution.set_poison_stack(true);
+        execution.next_step().unwrap();
+        assert_eq!(execution.next_step(), Err(Tx8Error::UninitializedRead));
+
+        // sub s 4, ld [s] b, ld a [s], hlt
+        let code = [
+            0x23, 0x61, 0x07, 0x04, 0x10, 0x76, 0x07, 0x01, 0x10, 0x67, 0x00, 0x07, 0x00,
+        ];
+        let mut execution = harness(&code, &[]);
+        execution.set_poison_stack(true);
+        execution.set_register(Register::B, 7);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::A), 7);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn state_json_contains_the_registers() {
+        // add a 3, hlt
+        let execution = run(&[0x22, 0x61, 0x00, 0x03, 0x00], &[(Register::A, 2)]);
+        let state: serde_json::Value = serde_json::from_str(&execution.state_json()).unwrap();
+        assert_eq!(state["registers"]["a"], 5);
+        assert_eq!(state["registers"]["p"], 0x400004);
+        assert_eq!(state["instruction_count"], 2);
+    }
+
+    #[test]
+    fn orderings_write_each_outcome() {
+        let nan = f32::NAN.to_bits();
+        let cases = [
+            // ocmp a b
+            (0x80, -2i32 as u32, 1, 0),
+            (0x80, 1, 1, 1),
+            (0x80, 1, -2i32 as u32, 2),
+            // uocmp a b
+            (0x82, 1, -2i32 as u32, 0),
+            (0x82, 7, 7, 1),
+            (0x82, -2i32 as u32, 1, 2),
+            // focmp a b
+            (0x81, (-1.5f32).to_bits(), 1.0f32.to_bits(), 0),
+            (0x81, 1.0f32.to_bits(), 1.0f32.to_bits(), 1),
+            (0x81, 2.0f32.to_bits(), 1.0f32.to_bits(), 2),
+            (0x81, nan, 1.0f32.to_bits(), 3),
+            (0x81, 1.0f32.to_bits(), nan, 3),
+        ];
+        for (op_code, a, b, expected) in cases {
+            let registers = [(Register::A, a), (Register::B, b), (Register::R, 0x5a)];
+            let execution = run(&[op_code, 0x66, 0x00, 0x01, 0x00], &registers);
+            assert_eq!(
+                execution.get_register(Register::A),
+                expected,
+                "{op_code:x} {a:x} {b:x}"
+            );
+            assert_eq!(execution.get_register(Register::R), 0x5a);
+        }
+    }
+
+    #[test]
+    fn register_watchers_see_the_increment() {
+        // add a 1, add b 1, hlt
+        let code = [0x22, 0x61, 0x00, 0x01, 0x22, 0x61, 0x01, 0x01, 0x00];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::A, 41);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let seen = changes.clone();
+        execution.on_register_change(
+            Register::A,
+            Box::new(move |reg, old, new| seen.borrow_mut().push((reg, old, new))),
+        );
+        run_to_halt(&mut execution);
+        assert_eq!(*changes.borrow(), [(Register::A, 41, 42)]);
+    }
+
+    #[test]
+    fn swap_exchanges_registers() {
+        let registers = [(Register::A, 0x11223344), (Register::B, 0x55667788)];
+        // swp a b, hlt
+        let execution = run(&[0x1f, 0x66, 0x00, 0x01, 0x00], &registers);
+        assert_eq!(execution.get_register(Register::A), 0x55667788);
+        assert_eq!(execution.get_register(Register::B), 0x11223344);
+
+        // swp al b, hlt
+        let execution = run(&[0x1f, 0x66, 0x10, 0x01, 0x00], &registers);
+        assert_eq!(execution.get_register(Register::A), 0x11223388);
+        assert_eq!(execution.get_register(Register::B), 0x55667744);
+    }
+
+    #[test]
+    fn swap_exchanges_memory_words() {
+        // swp #d00000 #d00004, hlt
+        let code = [0x1f, 0x44, 0x00, 0x00, 0xd0, 0x04, 0x00, 0xd0, 0x00];
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        let execution = run_harness(&code, &[(0xd00000, &data)], &[]);
+        assert_eq!(execution.memory().read_int(0xd00000), 0x08070605);
+        assert_eq!(execution.memory().read_int(0xd00004), 0x04030201);
+    }
+
+    #[test]
+    fn aliased_swaps_change_nothing() {
+        // swp a a, swp @b @c, hlt
+        let code = [0x1f, 0x66, 0x00, 0x00, 0x1f, 0x77, 0x01, 0x02, 0x00];
+        let data = [1, 2, 3, 4];
+        let execution = run_harness(
+            &code,
+            &[(0xd00000, &data)],
+            &[
+                (Register::A, 0x11223344),
+                (Register::B, 0xd00000),
+                (Register::C, 0xd00000),
+            ],
+        );
+        assert_eq!(execution.get_register(Register::A), 0x11223344);
+        assert_eq!(execution.memory().read_int(0xd00000), 0x04030201);
+    }
+
+    #[test]
+    fn data_sections_are_loaded_at_their_address() {
+        let strings = b"one\0two\0";
+        // ld a #d00004, hlt
+        let code = [0x10, 0x64, 0x00, 0x04, 0x00, 0xd0, 0x00];
+        let bytes = crate::RomBuilder::new(&code)
+            .section("strings", 0xd00000, strings)
+            .build()
+            .unwrap();
+        let rom = crate::parse_rom(&bytes).unwrap();
+        let mut execution = Execution::new_with_rom(&rom).unwrap();
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::A), b't' as u32);
+        let loaded: Vec<u8> = (0..8)
+            .map(|i| execution.memory().read_byte(0xd00000 + i))
+            .collect();
+        assert_eq!(loaded, strings);
+    }
+
+    #[test]
+    fn simulated_cycles_add_up_the_instruction_costs() {
+        // add a 1, mul a 2, div a 2, hlt
+        let code = [
+            0x22, 0x61, 0x00, 0x01, 0x24, 0x61, 0x00, 0x02, 0x25, 0x61, 0x00, 0x02, 0x00,
+        ];
+        let execution = run(&code, &[]);
+        assert_eq!(execution.simulated_cycles(), 1 + 3 + 20 + 1);
+
+        let mut execution = harness(&code, &[]);
+        execution.set_cycle_costs(CycleCosts {
+            base: 2,
+            div: 5,
+            ..Default::default()
+        });
+        run_to_halt(&mut execution);
+        assert_eq!(execution.simulated_cycles(), 2 + 3 + 5 + 2);
+    }
+
+    #[test]
+    fn read_line_reads_short_lines() {
+        let mut execution = call_with_abi(SysCallAbi::Legacy, "read_line", &[0xd00100, 8], b"hi\n");
+        assert_eq!(execution.get_register(Register::R), 2);
+        assert_eq!(execution.memory_mut().read_int(0xd00100), 0x6968);
+    }
+
+    #[test]
+    fn read_line_discards_the_rest_of_long_lines() {
+        // sys read_line, ld d r, sys read_line, hlt
+        let mut code = sys_call_code("read_line");
+        code.pop();
+        code.extend([0x10, 0x66, 0x03, 0x04]);
+        code.extend(sys_call_code("read_line"));
+        let mut execution = harness(&code, &[]);
+        execution.set_input(b"toolong\nnext".to_vec());
+        execution.set_register(Register::A, 0xd00100);
+        execution.set_register(Register::B, 4);
+        execution.next_step().unwrap();
+        assert_eq!(execution.get_register(Register::R), 4);
+        assert_eq!(
+            execution.memory_mut().read_int(0xd00100),
+            u32::from_le_bytes(*b"tool")
+        );
+        assert_eq!(execution.memory_mut().read_byte(0xd00104), 0);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::D), 4);
+        assert_eq!(execution.get_register(Register::R), 4);
+        assert_eq!(
+            execution.memory_mut().read_int(0xd00100),
+            u32::from_le_bytes(*b"next")
+        );
+    }
+
+    #[test]
+    fn unknown_sys_calls_fail_unless_lenient() {
+        let code = sys_call_code("no_such_call");
+        let mut execution = harness(&code, &[]);
+        assert_eq!(execution.next_step(), Err(Tx8Error::InvalidSysCall));
+        assert_eq!(execution.program_counter(), 0x400000);
+
+        let mut execution = harness(&code, &[]);
+        execution.set_lenient_sys_calls(true);
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        assert_eq!(execution.program_counter(), 0x400006);
+    }
+
+    #[test]
+    fn byte_overflows_trap_only_in_trapping_mode() {
+        // add al 100, hlt
+        let code = [0x22, 0x61, 0x10, 0x64, 0x00];
+        let execution = run(&code, &[(Register::A, 0x1100 + 100)]);
+        assert_eq!(execution.get_register(Register::A), 0x1100 + 200);
+
+        let mut execution = harness(&code, &[]);
+        execution.set_trap_overflow(true);
+        execution.set_register(Register::A, 0x1100 + 100);
+        assert_eq!(execution.next_step(), Err(Tx8Error::ArithmeticOverflow));
+        assert_eq!(execution.get_register(Register::A), 0x1100 + 100);
+
+        // 27 + 100 fits into a signed byte
+        execution.set_register(Register::A, 0x1100 + 27);
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        assert_eq!(execution.get_register(Register::A), 0x1100 + 127);
+    }
+
+    #[test]
+    fn reading_characters_until_the_end_of_the_input() {
+        for name in ["read_char", "read_char_eof"] {
+            // sys name, jmp 0x400000
+            let mut code = sys_call_code(name);
+            code.pop();
+            code.extend([0x02, 0x30, 0x00, 0x00, 0x40, 0x00]);
+            let mut execution = harness(&code, &[]);
+            execution.set_input(b"ab".to_vec());
+            let mut read = Vec::new();
+            for _ in 0..2 {
+                execution.next_step().unwrap();
+                read.push(execution.get_register(Register::O));
+                execution.next_step().unwrap();
+            }
+            assert_eq!(read, [b'a' as u32, b'b' as u32], "{name}");
+            let end = execution.next_step();
+            if name == "read_char" {
+                assert_eq!(end, Err(Tx8Error::NoInputGiven));
+            } else {
+                assert_eq!(end, Ok(Effect::None));
+                assert_eq!(execution.get_register(Register::O), 0xffffffff);
+            }
+        }
+    }
+
+    #[test]
+    fn push_and_pop_multiple_registers() {
+        // pushm 7, ld a 0, ld b 0, ld c 0, popm 7, hlt
+        let code = [
+            0x91, 0x10, 0x07, 0x10, 0x61, 0x00, 0x00, 0x10, 0x61, 0x01, 0x00, 0x10, 0x61, 0x02,
+            0x00, 0x92, 0x10, 0x07, 0x00,
+        ];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::A, 1);
+        execution.set_register(Register::B, 2);
+        execution.set_register(Register::C, 3);
+        execution.next_step().unwrap();
+        assert_eq!(execution.get_register(Register::S), STACK_TOP - 12);
+        let stack: Vec<u32> = (1..=3)
+            .map(|i| execution.memory().read_int(STACK_TOP - 4 * i))
+            .collect();
+        assert_eq!(stack, [1, 2, 3]);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::A), 1);
+        assert_eq!(execution.get_register(Register::B), 2);
+        assert_eq!(execution.get_register(Register::C), 3);
+        assert_eq!(execution.get_register(Register::S), STACK_TOP);
+    }
+
+    #[test]
+    fn fma_rounds_once() {
+        let x = 1.0 + 2f32.powi(-12);
+        let sum = -(1.0 + 2f32.powi(-11));
+        let registers = [
+            (Register::A, x.to_bits()),
+            (Register::B, x.to_bits()),
+            (Register::C, sum.to_bits()),
+            (Register::R, sum.to_bits()),
+        ];
+        // fma a b, hlt
+        let execution = run(&[0x58, 0x66, 0x00, 0x01, 0x00], &registers);
+        let fused = f32::from_bits(execution.get_register(Register::A));
+        assert_eq!(fused, 2f32.powi(-24));
+
+        // fmul a b, fadd a c, hlt
+        let code = [0x44, 0x66, 0x00, 0x01, 0x42, 0x66, 0x00, 0x02, 0x00];
+        let execution = run(&code, &registers);
+        let separate = f32::from_bits(execution.get_register(Register::A));
+        assert_eq!(separate, 0.0);
+    }
+
+    #[test]
+    fn step_with_diff_reports_the_written_bytes() {
+        // ld #d00000 b
+        let code = [0x10, 0x46, 0x00, 0x00, 0xd0, 0x01];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::B, 0x1234);
+        let (effect, diff) = execution.step_with_diff().unwrap();
+        assert_eq!(effect, Effect::None);
+        // the upper bytes were zero before
+        assert_eq!(
+            diff.memory,
+            [(0xd00000, 0x00, 0x34), (0xd00001, 0x00, 0x12)]
+        );
+        assert_eq!(diff.registers, [(Register::P, 0x400000, 0x400006)]);
+        assert_eq!(diff.pc, 0x400006);
+    }
+
+    #[test]
+    fn rounding_handles_negative_and_halfway_values() {
+        let inputs = [-2.5f32, -1.5, -0.5, 0.5, 2.5, -1.7];
+        let cases = [
+            // floor
+            (0x59, [-3.0, -2.0, -1.0, 0.0, 2.0, -2.0]),
+            // ceil
+            (0x5a, [-2.0, -1.0, -0.0, 1.0, 3.0, -1.0]),
+            // round
+            (0x5b, [-3.0, -2.0, -1.0, 1.0, 3.0, -2.0]),
+            // trunc
+            (0x5c, [-2.0, -1.0, -0.0, 0.0, 2.0, -1.0]),
+        ];
+        for (op_code, expected) in cases {
+            for (input, expected) in inputs.into_iter().zip(expected) {
+                // op a, hlt
+                let code = [op_code, 0x60, 0x00, 0x00];
+                let execution = run(&code, &[(Register::A, input.to_bits())]);
+                let res = f32::from_bits(execution.get_register(Register::A));
+                assert_eq!(res, expected, "{op_code:x} {input}");
+            }
+        }
+    }
+
+    // appends every byte written to it to a host buffer
+    struct Sink(Rc<RefCell<Vec<u8>>>);
+
+    impl MmioHandler for Sink {
+        fn read(&mut self, _addr: u32, _size: u32) -> u32 {
+            0
+        }
+
+        fn write(&mut self, _addr: u32, size: u32, val: u32) {
+            let bytes = val.to_le_bytes();
+            self.0.borrow_mut().extend(&bytes[..size as usize]);
+        }
+    }
+
+    #[test]
+    fn writes_to_mapped_devices_reach_the_host() {
+        // ld #e00000 'h', ld #e00000 'i', hlt
+        let code = [
+            0x10, 0x41, 0x00, 0x00, 0xe0, b'h', 0x10, 0x41, 0x00, 0x00, 0xe0, b'i', 0x00,
+        ];
+        let mut execution = harness(&code, &[]);
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        execution
+            .map_io(0xe00000..0xe00001, Box::new(Sink(buffer.clone())))
+            .unwrap();
+        run_to_halt(&mut execution);
+        assert_eq!(*buffer.borrow(), b"hi");
+        assert_eq!(execution.memory().read_byte(0xe00000), 0);
+    }
+
+    #[test]
+    fn memcpy_stops_partway_once_the_budget_is_used_up() {
+        let data = [0xaa; 100];
+        let code = sys_call_code("memcpy");
+        let mut execution = harness(&code, &[(0xd10000, &data)]);
+        execution.set_register(Register::A, 0xd00000);
+        execution.set_register(Register::B, 0xd10000);
+        execution.set_register(Register::C, 100);
+        // one unit for the instruction and ten for the bytes
+        execution.set_budget(Some(11));
+        assert_eq!(execution.next_step(), Err(Tx8Error::BudgetExhausted));
+        let copied: Vec<u8> = (0..12)
+            .map(|i| execution.memory().read_byte(0xd00000 + i))
+            .collect();
+        assert_eq!(copied[..10], [0xaa; 10]);
+        assert_eq!(copied[10..], [0, 0]);
+        assert_eq!(execution.program_counter(), 0x400000);
+    }
+
+    #[test]
+    fn peeking_changes_nothing() {
+        // add a 3, hlt
+        let code = [0x22, 0x61, 0x00, 0x03, 0x00];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::A, 2);
+        let registers = Register::ALL.map(|reg| execution.get_register(reg));
+        let instruction = execution.peek_instruction().unwrap();
+        assert_eq!(
+            instruction.to_string(),
+            execution.peek_instruction().unwrap().to_string()
+        );
+        assert!(matches!(instruction, Instruction::Add(..)));
+        assert_eq!(
+            Register::ALL.map(|reg| execution.get_register(reg)),
+            registers
+        );
+        assert_eq!(execution.program_counter(), 0x400000);
+        assert_eq!(execution.instruction_count(), 0);
+        execution.next_step().unwrap();
+        assert_eq!(execution.get_register(Register::A), 5);
+    }
+
+    #[test]
+    fn unordered_float_comparisons_only_take_jne() {
+        let registers = [
+            (Register::A, f32::NAN.to_bits()),
+            (Register::B, 1.0f32.to_bits()),
+        ];
+        // jeq, jne, jgt, jge, jlt, jle
+        for op_code in 0x03..=0x08 {
+            // fcmp a b, jump 0x400100, hlt
+            let code = [
+                0x0a, 0x66, 0x00, 0x01, op_code, 0x30, 0x00, 0x01, 0x40, 0x00, 0x00,
+            ];
+            let execution = run(&code, &registers);
+            let taken = execution.program_counter() == 0x400100;
+            assert_eq!(taken, op_code == 0x04, "{op_code:x}");
+        }
+    }
+
+    #[test]
+    fn r_values_of_2_are_only_unordered_after_fcmp() {
+        // ld r 2, jgt 0x400100, hlt
+        let code = [
+            0x10, 0x61, 0x04, 0x02, 0x05, 0x30, 0x00, 0x01, 0x40, 0x00, 0x00,
+        ];
+        assert_eq!(run(&code, &[]).program_counter(), 0x400100);
+
+        // add a 1 overflows into the signed flag 0b10, jgt 0x400100, hlt
+        let code = [
+            0x22, 0x61, 0x00, 0x01, 0x05, 0x30, 0x00, 0x01, 0x40, 0x00, 0x00,
+        ];
+        let execution = run(&code, &[(Register::A, i32::MAX as u32)]);
+        assert_eq!(execution.get_register(Register::R), 2);
+        assert_eq!(execution.program_counter(), 0x400100);
+
+        // fcmp a a with NaN, ld r 2, jgt 0x400100, hlt
+        let code = [
+            0x0a, 0x66, 0x00, 0x00, 0x10, 0x61, 0x04, 0x02, 0x05, 0x30, 0x00, 0x01, 0x40, 0x00,
+            0x00,
+        ];
+        let execution = run(&code, &[(Register::A, f32::NAN.to_bits())]);
+        assert_eq!(execution.program_counter(), 0x400100);
+    }
+
+    #[test]
+    fn byte_swap_reverses_the_bytes_of_the_view() {
+        // bswap a, hlt
+        let execution = run(&[0x3e, 0x60, 0x00, 0x00], &[(Register::A, 0x12345678)]);
+        assert_eq!(execution.get_register(Register::A), 0x78563412);
+
+        // bswap as, hlt
+        let execution = run(&[0x3e, 0x60, 0x20, 0x00], &[(Register::A, 0xabcd1234)]);
+        assert_eq!(execution.get_register(Register::A), 0xabcd3412);
+
+        // bswap ab, hlt
+        let execution = run(&[0x3e, 0x60, 0x10, 0x00], &[(Register::A, 0xabcd1234)]);
+        assert_eq!(execution.get_register(Register::A), 0xabcd1234);
+    }
+
+    #[test]
+    fn step_over_runs_a_call_until_it_returns() {
+        // call 0x400007, hlt, add a 1, add a 2, ret
+        let code = [
+            0x0c, 0x30, 0x07, 0x00, 0x40, 0x00, 0x00, 0x22, 0x61, 0x00, 0x01, 0x22, 0x61, 0x00,
+            0x02, 0x0d,
+        ];
+        let mut execution = harness(&code, &[]);
+        assert_eq!(execution.step_over(), Ok(Effect::None));
+        assert_eq!(execution.program_counter(), 0x400006);
+        assert_eq!(execution.get_register(Register::A), 3);
+        assert_eq!(execution.get_register(Register::S), STACK_TOP);
+        assert_eq!(execution.instruction_count(), 4);
+    }
+
+    #[test]
+    fn debug_breaks_pause_and_resume() {
+        // brk, add a 1, hlt
+        let code = [0x0f, 0x22, 0x61, 0x00, 0x01, 0x00];
+        let mut execution = harness(&code, &[]);
+        assert_eq!(execution.run_steps(10), Ok(Effect::BreakpointHit));
+        assert_eq!(execution.program_counter(), 0x400001);
+        assert_eq!(execution.get_register(Register::A), 0);
+        assert_eq!(
+            execution.run_steps(10),
+            Ok(Effect::Halted(HaltReason::HaltOpcode))
+        );
+        assert_eq!(execution.get_register(Register::A), 1);
+    }
+
+    /// Runs the code like `run` and returns the output of the program
+    fn run_printing(code: &[u8], registers: &[(Register, u32)]) -> (Execution<'static>, String) {
+        let mut execution = harness(code, &[]);
+        let output = SharedBuffer::default();
+        execution.set_output(Box::new(output.clone()));
+        for &(reg, val) in registers {
+            execution.set_register(reg, val);
+        }
+        run_to_halt(&mut execution);
+        let output = String::from_utf8(output.0.take()).unwrap();
+        (execution, output)
+    }
+
+    #[test]
+    fn prints_integers_in_other_radixes() {
+        for (name, expected) in [
+            ("print_hex", "ff"),
+            ("print_bin", "11111111"),
+            ("print_oct", "377"),
+            ("print_u32", "255"),
+        ] {
+            // push 255, sys name, hlt
+            let mut code = vec![0x1d, 0x30, 0xff, 0x00, 0x00, 0x00];
+            code.extend(sys_call_code(name));
+            let (_, output) = run_printing(&code, &[]);
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn printing_in_a_loop_stops_at_the_output_limit() {
+        // push 1234, sys print_u32, jmp 0x400006
+        let mut code = vec![0x1d, 0x30, 0xd2, 0x04, 0x00, 0x00];
+        code.extend(sys_call_code("print_u32"));
+        code.pop();
+        code.extend([0x02, 0x30, 0x06, 0x00, 0x40, 0x00]);
+        let mut execution = harness(&code, &[]);
+        let output = SharedBuffer::default();
+        execution.set_output(Box::new(output.clone()));
+        execution.set_output_limit(Some(10));
+        let error = loop {
+            if let Err(error) = execution.next_step() {
+                break error;
+            }
+        };
+        assert_eq!(error, Tx8Error::OutputLimitReached);
+        assert_eq!(output.0.take(), b"1234123412");
+        assert_eq!(execution.remaining_output(), Some(0));
+    }
+
+    #[test]
+    fn available_input_decreases_while_reading() {
+        // sys input_available, sys read_char, jmp 0x400000
+        let mut code = sys_call_code("input_available");
+        code.pop();
+        code.extend(sys_call_code("read_char"));
+        code.pop();
+        code.extend([0x02, 0x30, 0x00, 0x00, 0x40, 0x00]);
+        let mut execution = harness(&code, &[]);
+        execution.set_input(b"abc".to_vec());
+        let mut available = Vec::new();
+        for _ in 0..3 {
+            execution.next_step().unwrap();
+            available.push(execution.get_register(Register::R));
+            execution.run_steps(2).unwrap();
+        }
+        execution.next_step().unwrap();
+        available.push(execution.get_register(Register::R));
+        assert_eq!(available, [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn absolute_differences_handle_the_signed_limits() {
+        let cases = [
+            // adiff al 0
+            ([0x2b, 0x61, 0x10, 0x00], 0x80, 0, 0x80),
+            // adiff al bl
+            ([0x2b, 0x66, 0x10, 0x11], 0x80, 0x7f, 0xff),
+            // adiff a b
+            ([0x2b, 0x66, 0x00, 0x01], 0x80000000, 0x7fffffff, 0xffffffff),
+            ([0x2b, 0x66, 0x00, 0x01], -3i32 as u32, 4, 7),
+            // uadiff a b
+            ([0x67, 0x66, 0x00, 0x01], 3, 10, 7),
+        ];
+        for (code, a, b, expected) in cases {
+            let registers = [(Register::A, a), (Register::B, b), (Register::R, 0x5a)];
+            let execution = run(&code, &registers);
+            assert_eq!(execution.get_register(Register::A), expected, "{code:x?}");
+            assert_eq!(execution.get_register(Register::R), 0x5a);
+        }
+    }
+
+    #[test]
+    fn access_hooks_see_every_access_of_a_loop() {
+        // inc #d00000, dec c, ucmp c 0u8, jne 0x400000, hlt
+        let code = [
+            0x20, 0x40, 0x00, 0x00, 0xd0, 0x21, 0x60, 0x02, 0x0b, 0x61, 0x02, 0x00, 0x04, 0x30,
+            0x00, 0x00, 0x40, 0x00, 0x00,
+        ];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::C, 2);
+        let accesses = Rc::new(RefCell::new(Vec::new()));
+        let seen = accesses.clone();
+        execution
+            .memory_mut()
+            .set_access_hook(Some(Box::new(move |ptr, size, is_write| {
+                seen.borrow_mut().push((ptr, size, is_write))
+            })));
+        execution.next_step().unwrap();
+        // the opcode, the parameter mode, the address and the counter
+        assert_eq!(
+            *accesses.borrow(),
+            [
+                (0x400000, 1, false),
+                (0x400001, 1, false),
+                (0x400002, 3, false),
+                (0xd00000, 1, false),
+                (0xd00000, 1, true),
+            ]
+        );
+        run_to_halt(&mut execution);
+        let data: Vec<(u32, u32, bool)> = accesses
+            .borrow()
+            .iter()
+            .copied()
+            .filter(|&(ptr, _, _)| ptr >= 0xd00000)
+            .collect();
+        let counter = [(0xd00000, 1, false), (0xd00000, 1, true)];
+        assert_eq!(data, [counter, counter].concat());
+        assert_eq!(execution.memory().read_byte(0xd00000), 2);
+    }
+
+    #[test]
+    fn shifts_right_ignore_dirty_high_bits() {
+        let cases = [
+            // sar al 1
+            ([0x36, 0x61, 0x10, 0x01], 0x12345681, 0x123456c0, 1),
+            ([0x36, 0x61, 0x10, 0x01], 0xffffff7e, 0xffffff3f, 0),
+            // slr al 1
+            ([0x35, 0x61, 0x10, 0x01], 0x12345681, 0x12345640, 1),
+            // sar as 4
+            ([0x36, 0x61, 0x20, 0x04], 0xabcd8003, 0xabcdf800, 3),
+            ([0x36, 0x61, 0x20, 0x04], 0xffff7ff0, 0xffff07ff, 0),
+            // slr as 4
+            ([0x35, 0x61, 0x20, 0x04], 0xabcd8003, 0xabcd0800, 3),
+        ];
+        for (code, a, expected, r) in cases {
+            let execution = run(&code, &[(Register::A, a)]);
+            assert_eq!(
+                execution.get_register(Register::A),
+                expected,
+                "{code:x?} {a:x}"
+            );
+            assert_eq!(execution.get_register(Register::R), r, "{code:x?} {a:x}");
+        }
+    }
+
+    #[test]
+    fn halts_report_their_reason() {
+        let mut execution = harness(&[0x00], &[]);
+        assert_eq!(
+            execution.next_step(),
+            Ok(Effect::Halted(HaltReason::HaltOpcode))
+        );
+
+        // push 7, sys exit
+        let mut code = vec![0x1d, 0x30, 0x07, 0x00, 0x00, 0x00];
+        code.extend(sys_call_code("exit"));
+        let mut execution = harness(&code, &[]);
+        assert_eq!(
+            execution.run_steps(10),
+            Ok(Effect::Halted(HaltReason::Exit(7)))
+        );
+
+        let mut execution = harness(&[0x01], &[]);
+        execution.set_code_bounds_check(true);
+        assert_eq!(
+            execution.run_steps(10),
+            Ok(Effect::Halted(HaltReason::RanOffEnd))
+        );
+    }
+
+    #[test]
+    fn roms_without_a_trailing_halt_run_off_their_end() {
+        // add a 1, add a 1
+        let code = [0x22, 0x61, 0x00, 0x01, 0x22, 0x61, 0x00, 0x01];
+        let mut execution = harness(&code, &[]);
+        execution.set_code_bounds_check(true);
+        assert_eq!(
+            execution.run_steps(10),
+            Ok(Effect::Halted(HaltReason::RanOffEnd))
+        );
+        assert_eq!(execution.program_counter(), 0x400008);
+        assert_eq!(execution.get_register(Register::A), 2);
+        assert_eq!(execution.instruction_count(), 2);
+
+        // without the check, the zero padding after the code decodes as `hlt`
+        let execution = run(&code, &[]);
+        assert_eq!(execution.program_counter(), 0x400008);
+        assert_eq!(execution.instruction_count(), 3);
+    }
+
+    #[test]
+    fn increments_wrap_or_saturate() {
+        // inc al, hlt
+        let execution = run(&[0x20, 0x60, 0x10, 0x00], &[(Register::A, 0x12ff)]);
+        assert_eq!(execution.get_register(Register::A), 0x1200);
+        // incs al, hlt
+        let execution = run(&[0x68, 0x60, 0x10, 0x00], &[(Register::A, 0x12ff)]);
+        assert_eq!(execution.get_register(Register::A), 0x12ff);
+        // decs al, hlt
+        let execution = run(&[0x69, 0x60, 0x10, 0x00], &[(Register::A, 0x1200)]);
+        assert_eq!(execution.get_register(Register::A), 0x1200);
+        // dec al, hlt
+        let execution = run(&[0x21, 0x60, 0x10, 0x00], &[(Register::A, 0x1200)]);
+        assert_eq!(execution.get_register(Register::A), 0x12ff);
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn status_messages_go_to_the_logger() {
+        use std::sync::Mutex;
+
+        static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+
+        struct Capture;
+
+        impl log::Log for Capture {
+            fn enabled(&self, _metadata: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                let message = record.args().to_string();
+                RECORDS.lock().unwrap().push((record.level(), message));
+            }
+
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+        let mut execution = harness(&sys_call_code("log_test"), &[]);
+        execution.set_lenient_sys_calls(true);
+        run_to_halt(&mut execution);
+        let expected = format!(
+            "unimplemented syscall {}",
+            format_reg(hash("log_test"), false)
+        );
+        assert!(RECORDS
+            .lock()
+            .unwrap()
+            .contains(&(log::Level::Warn, expected)));
+    }
+
+    #[test]
+    fn test_harness_runs_snippets_on_fixture_data() {
+        // ld a #d00000, add a #d00001
+        let code = [
+            0x10, 0x64, 0x00, 0x00, 0x00, 0xd0, 0x22, 0x64, 0x00, 0x01, 0x00, 0xd0,
+        ];
+        let mut execution = harness(&code, &[(0xd00000, &[40, 2])]);
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        assert_eq!(execution.get_register(Register::A), 40);
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        assert_eq!(execution.get_register(Register::A), 42);
+    }
+
+    #[test]
+    fn writes_into_the_code_fail_only_when_protected() {
+        // ld #400007 0x01u8, hlt, hlt
+        let code = [0x10, 0x41, 0x07, 0x00, 0x40, 0x01, 0x00, 0x00];
+        let mut execution = harness(&code, &[]);
+        execution.set_code_protection(true);
+        assert_eq!(execution.next_step(), Err(Tx8Error::SelfModifyingCode));
+        assert_eq!(execution.memory().read_byte(0x400007), 0x00);
+
+        // the write turns the second `hlt` into a `nop`
+        let mut execution = harness(&code, &[]);
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        assert_eq!(execution.memory().read_byte(0x400007), 0x01);
+        execution.set_register(Register::P, 0x400007);
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+    }
+
+    #[test]
+    fn float_max_and_min_skip_nan_operands() {
+        let (nan, nan2) = (0x7fc00001, 0x7fc00002);
+        let one = 1.0f32.to_bits();
+        for op_code in [0x47, 0x48] {
+            for (a, b, expected, r) in [
+                (nan, one, one, nan),
+                (one, nan, one, nan),
+                (nan, nan2, nan, nan2),
+            ] {
+                // fmax a b or fmin a b, hlt
+                let code = [op_code, 0x66, 0x00, 0x01, 0x00];
+                let execution = run(&code, &[(Register::A, a), (Register::B, b)]);
+                assert_eq!(execution.get_register(Register::A), expected, "{op_code:x}");
+                assert_eq!(execution.get_register(Register::R), r, "{op_code:x}");
+            }
+        }
+    }
+
+    #[test]
+    fn set_registers_are_used_by_the_next_step() {
+        // hlt, add a b
+        let code = [0x00, 0x22, 0x66, 0x00, 0x01];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::A, 40);
+        execution.set_register(Register::B, 2);
+        execution.set_register(Register::P, 0x400001);
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        assert_eq!(execution.get_register(Register::A), 42);
+        assert_eq!(execution.get_register(Register::B), 2);
+        assert_eq!(execution.program_counter(), 0x400005);
+    }
+
+    #[test]
+    fn parses_numbers_from_text() {
+        for (name, text, r, b) in [
+            ("parse_int", &b"-42\0"[..], -42i32 as u32, 3),
+            ("parse_float", b" 2.75 rest", 2.75f32.to_bits(), 5),
+            ("parse_int", b"12ab", 0, 0),
+            ("parse_int", b"3.14", 0, 0),
+            ("parse_float", b"1.5x", 0, 0),
+        ] {
+            let code = sys_call_code(name);
+            let execution = run_harness(
+                &code,
+                &[(0xd00000, text)],
+                &[(Register::A, 0xd00000), (Register::B, 0x77)],
+            );
+            let text = String::from_utf8_lossy(text);
+            assert_eq!(execution.get_register(Register::R), r, "{name} {text}");
+            assert_eq!(execution.get_register(Register::B), b, "{name} {text}");
+        }
+    }
+
+    #[test]
+    fn parsing_stops_at_the_length_limit_and_the_end_of_memory() {
+        let longest = [b"0".repeat(MAX_NUMBER_LENGTH - 1), b"7".to_vec()].concat();
+        let too_long = b"1".repeat(MAX_NUMBER_LENGTH + 1);
+        let spaces = [b' '; 16];
+        for (text, address, r, b) in [
+            (&longest[..], 0xd00000, 7, MAX_NUMBER_LENGTH as u32),
+            (&too_long, 0xd00000, 0, 0),
+            (b"12", 0xfffffe, 12, 2),
+            (&spaces, 0xfffff0, 0, 0),
+        ] {
+            let code = sys_call_code("parse_int");
+            let execution = run_harness(&code, &[(address, text)], &[(Register::A, address)]);
+            assert_eq!(execution.get_register(Register::R), r, "{address:x}");
+            assert_eq!(execution.get_register(Register::B), b, "{address:x}");
+        }
+    }
+
+    #[test]
+    fn parsing_charges_every_byte_before_reading_it() {
+        let code = sys_call_code("parse_int");
+        for budget in 2..8 {
+            let mut execution = harness(&code, &[(0xd00000, b"  12345")]);
+            let reads = Rc::new(RefCell::new(0));
+            let counter = reads.clone();
+            execution
+                .memory_mut()
+                .set_access_hook(Some(Box::new(move |ptr, _, is_write| {
+                    if ptr >= 0xd00000 && !is_write {
+                        *counter.borrow_mut() += 1;
+                    }
+                })));
+            execution.set_register(Register::A, 0xd00000);
+            execution.set_budget(Some(budget));
+            assert_eq!(execution.next_step(), Err(Tx8Error::BudgetExhausted));
+            assert_eq!(*reads.borrow(), budget - 1, "{budget}");
+        }
+    }
+
+    #[test]
+    fn running_in_chunks_matches_a_single_run() {
+        let single = run(&COUNTED_LOOP, &[(Register::C, 100)]);
+
+        let mut chunked = harness(&COUNTED_LOOP, &[]);
+        chunked.set_register(Register::C, 100);
+        let mut chunks = 1;
+        while chunked.run_steps(7) == Ok(Effect::Yielded) {
+            chunks += 1;
+        }
+        assert_eq!(chunks, single.instruction_count().div_ceil(7));
+        for reg in Register::ALL {
+            assert_eq!(
+                chunked.get_register(reg),
+                single.get_register(reg),
+                "{reg:?}"
+            );
+        }
+        assert_eq!(chunked.instruction_count(), single.instruction_count());
+    }
+
+    #[test]
+    fn relocated_jumps_resolve_at_every_base() {
+        // jmp 0x40000b, ld a 1, hlt, ld a 2, hlt
+        let code = [
+            0x02, 0x30, 0x0b, 0x00, 0x40, 0x00, 0x10, 0x61, 0x00, 0x01, 0x00, 0x10, 0x61, 0x00,
+            0x02, 0x00,
+        ];
+        let bytes = crate::RomBuilder::new(&code).relocation(2).build().unwrap();
+        let rom = crate::parse_rom(&bytes).unwrap();
+        for base in [0x400000, 0x123400] {
+            let mut execution = Execution::new_with_rom_at(&rom, base).unwrap();
+            run_to_halt(&mut execution);
+            assert_eq!(execution.get_register(Register::A), 2, "{base:x}");
+            assert_eq!(execution.memory().read_int(base + 2), base + 11, "{base:x}");
+        }
+    }
+
+    #[test]
+    fn bounded_offsets_reject_relative_addresses_outside_memory() {
+        // ld a [0x10 + o], hlt / ld [0x10 + o] a, hlt / ld a [-0x10 + o], hlt
+        let load = [0x10, 0x65, 0x00, 0x10, 0x00, 0x00, 0x00];
+        let store = [0x10, 0x56, 0x10, 0x00, 0x00, 0x00, 0x00];
+        let below = [0x10, 0x65, 0x00, 0xf0, 0xff, 0xff, 0x00];
+        for (code, o) in [(load, 0xfffff0), (store, 0xfffff0), (below, 8)] {
+            let mut execution = harness(&code, &[]);
+            execution.set_register(Register::O, o);
+            execution.set_bounded_offsets(true);
+            assert_eq!(execution.next_step(), Err(Tx8Error::OffsetOutOfBounds));
+
+            // without the check the address wraps around
+            let mut execution = harness(&code, &[]);
+            execution.set_register(Register::O, o);
+            assert!(execution.next_step().is_ok());
+        }
+
+        // addresses within memory are unaffected
+        let mut execution = harness(&load, &[(0xd00010, &[42])]);
+        execution.set_register(Register::O, 0xd00000);
+        execution.set_bounded_offsets(true);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::A), 42);
+    }
+
+    #[test]
+    fn prints_floats_with_the_given_precision() {
+        // push 1.0 / 3.0, sys print_f32_prec, hlt
+        let mut code = vec![0x1d, 0x30];
+        code.extend((1.0f32 / 3.0).to_bits().to_le_bytes());
+        code.extend(sys_call_code("print_f32_prec"));
+        for (precision, expected) in [(3, "0.333"), (6, "0.333333"), (0, "0")] {
+            let (_, output) = run_printing(&code, &[(Register::A, precision)]);
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn zero_divided_by_zero_stores_the_canonical_nan() {
+        // fdiv a b, hlt
+        let code = [0x45, 0x66, 0x00, 0x01, 0x00];
+        for canonical in [false, true] {
+            let mut execution = harness(&code, &[]);
+            execution.set_canonical_nan(canonical);
+            run_to_halt(&mut execution);
+            let res = execution.get_register(Register::A);
+            // the raw bits depend on the host, x86 produces a negative NaN
+            assert!(f32::from_bits(res).is_nan());
+            if canonical {
+                assert_eq!(res, CANONICAL_NAN);
+            }
+        }
+    }
+
+    #[test]
+    fn spilled_blocks_reload_unchanged() {
+        let block = [1, 2, 3, 4, 5, 6, 7, 8];
+        // spill #d00000 8, reload #d00100 8, hlt
+        let code = [
+            0x96, 0x41, 0x00, 0x00, 0xd0, 0x08, 0x97, 0x41, 0x00, 0x01, 0xd0, 0x08, 0x00,
+        ];
+        let mut execution = harness(&code, &[(0xd00000, &block)]);
+        let read = |execution: &Execution, addr: u32| -> Vec<u8> {
+            (0..8)
+                .map(|i| execution.memory().read_byte(addr + i))
+                .collect()
+        };
+
+        execution.next_step().unwrap();
+        let s = execution.get_register(Register::S);
+        assert_eq!(s, STACK_TOP - 8);
+        assert_eq!(read(&execution, s), block);
+
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::S), STACK_TOP);
+        assert_eq!(read(&execution, 0xd00100), block);
+    }
+
+    #[test]
+    fn spill_and_reload_stay_within_the_stack() {
+        // spill #d00000 0x3000, hlt
+        let code = [0x96, 0x42, 0x00, 0x00, 0xd0, 0x00, 0x30, 0x00];
+        let mut execution = harness(&code, &[]);
+        assert_eq!(execution.next_step(), Err(Tx8Error::StackOverflow));
+        // reload #d00000 8, hlt
+        let code = [0x97, 0x41, 0x00, 0x00, 0xd0, 0x08, 0x00];
+        let mut execution = harness(&code, &[]);
+        assert_eq!(execution.next_step(), Err(Tx8Error::StackUnderflow));
+    }
+
+    #[test]
+    fn conditional_halts_evaluate_r_like_the_jumps() {
+        // each opcode with the values of R it halts for, out of less, equal, greater, 2 and an
+        // unordered float comparison (None)
+        let cases: [(u8, &[Option<i32>]); 6] = [
+            (0x98, &[Some(0)]),
+            (0x99, &[Some(-1), Some(1), Some(2), None]),
+            (0x9a, &[Some(1), Some(2)]),
+            (0x9b, &[Some(0), Some(1), Some(2)]),
+            (0x9c, &[Some(-1)]),
+            (0x9d, &[Some(-1), Some(0)]),
+        ];
+        for (op, halting) in cases {
+            for r in [Some(-1), Some(0), Some(1), Some(2), None] {
+                // hltxx, inc a, hlt, or fcmp b c first for the unordered comparison
+                let mut code = match r {
+                    Some(_) => vec![],
+                    None => vec![0x0a, 0x66, 0x01, 0x02],
+                };
+                let start = 0x400000 + code.len() as u32;
+                code.extend([op, 0x20, 0x60, 0x00, 0x00]);
+                let registers = [
+                    (Register::R, r.unwrap_or(0) as u32),
+                    (Register::B, f32::NAN.to_bits()),
+                ];
+                let execution = run(&code, &registers);
+                let halted = execution.get_register(Register::A) == 0;
+                assert_eq!(halted, halting.contains(&r), "{op:x} with r = {r:?}");
+                if halted {
+                    assert_eq!(execution.get_register(Register::P), start);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn diagnostics_do_not_appear_in_the_output() {
+        // push 7, sys print_u32, sys test_au, hlt
+        let mut code = vec![0x1d, 0x30, 0x07, 0x00, 0x00, 0x00];
+        code.extend(sys_call_code("print_u32"));
+        code.pop();
+        code.extend(sys_call_code("test_au"));
+        let mut execution = harness(&code, &[]);
+        let output = SharedBuffer::default();
+        let error_output = SharedBuffer::default();
+        execution.set_output(Box::new(output.clone()));
+        execution.set_error_output(Box::new(error_output.clone()));
+        execution.set_register(Register::A, 0xab);
+        run_to_halt(&mut execution);
+        assert_eq!(output.0.take(), b"7");
+        assert_eq!(error_output.0.take(), b"ab\n");
+    }
+
+    #[test]
+    fn jump_targets_use_the_full_operand() {
+        let table = 0x400123u32.to_le_bytes();
+        for (code, target) in [
+            // jmp 0x10, jmp 0x1234, jmp 0x400010, jmp @a, call 0x10
+            (vec![0x02, 0x10, 0x10], 0x10),
+            (vec![0x02, 0x20, 0x34, 0x12], 0x1234),
+            (vec![0x02, 0x30, 0x10, 0x00, 0x40, 0x00], 0x400010),
+            (vec![0x02, 0x70, 0x00], 0x400123),
+            (vec![0x0c, 0x10, 0x10], 0x10),
+        ] {
+            let mut execution = harness(&code, &[(0xd00000, &table)]);
+            execution.set_register(Register::A, 0xd00000);
+            execution.next_step().unwrap();
+            assert_eq!(execution.get_register(Register::P), target, "{code:x?}");
+        }
+    }
+
+    #[test]
+    fn taker_moves_the_high_word_and_clears_r() {
+        // mul a b, taker c, hlt
+        let code = [0x24, 0x66, 0x00, 0x01, 0x9e, 0x60, 0x02, 0x00];
+        let execution = run(&code, &[(Register::A, 0x30000), (Register::B, 0x20000)]);
+        assert_eq!(execution.get_register(Register::A), 0);
+        assert_eq!(execution.get_register(Register::C), 6);
+        assert_eq!(execution.get_register(Register::R), 0);
+
+        // taker r, hlt
+        let execution = run(&[0x9e, 0x60, 0x04, 0x00], &[(Register::R, 5)]);
+        assert_eq!(execution.get_register(Register::R), 0);
+    }
+
+    #[test]
+    fn isqrt_rounds_down_exactly() {
+        for (val, root, remainder) in [
+            (0, 0, 0),
+            (15, 3, 6),
+            (16, 4, 0),
+            (17, 4, 1),
+            (0xfffc0004, 65534, 0),
+            (0xfffe0000, 65534, 131068),
+            (0xfffe0001, 65535, 0),
+            (u32::MAX, 65535, 131070),
+        ] {
+            // isqrt a, hlt
+            let execution = run(&[0x6a, 0x60, 0x00, 0x00], &[(Register::A, val)]);
+            assert_eq!(execution.get_register(Register::A), root, "{val:x}");
+            assert_eq!(execution.get_register(Register::R), remainder, "{val:x}");
+        }
+
+        // isqrt ab, hlt / isqrt as, hlt
+        let execution = run(&[0x6a, 0x60, 0x10, 0x00], &[(Register::A, 0x123400ff)]);
+        assert_eq!(execution.get_register(Register::A), 0x1234000f);
+        assert_eq!(execution.get_register(Register::R), 30);
+        let execution = run(&[0x6a, 0x60, 0x20, 0x00], &[(Register::A, 0x1234ffff)]);
+        assert_eq!(execution.get_register(Register::A), 0x123400ff);
+        assert_eq!(execution.get_register(Register::R), 510);
+    }
+
+    #[test]
+    fn skipping_a_faulting_instruction_continues_after_it() {
+        // div a b, inc c, hlt
+        let code = [0x25, 0x66, 0x00, 0x01, 0x20, 0x60, 0x02, 0x00];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::A, 7);
+        assert_eq!(execution.next_step(), Err(Tx8Error::DivisionByZero));
+        assert_eq!(execution.get_register(Register::P), 0x400000);
+        assert_eq!(execution.get_register(Register::A), 7);
+        assert_eq!(execution.instruction_count(), 0);
+
+        execution.skip_faulting_instruction().unwrap();
+        assert_eq!(execution.get_register(Register::P), 0x400004);
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::A), 7);
+        assert_eq!(execution.get_register(Register::C), 1);
+    }
+
+    #[test]
+    fn print_u32_needs_its_argument_on_the_stack() {
+        let code = sys_call_code("print_u32");
+        for s in [STACK_TOP, STACK_TOP - 2, 0xd00000, STACK_BOTTOM - 4] {
+            let mut execution = harness(&code, &[]);
+            execution.set_output(Box::new(std::io::sink()));
+            execution.set_register(Register::S, s);
+            assert_eq!(
+                execution.next_step(),
+                Err(Tx8Error::MissingSysCallArgument(s)),
+                "{s:x}"
+            );
+        }
+        let (_, output) = run_printing(&code, &[(Register::S, STACK_TOP - 4)]);
+        assert_eq!(output, "0");
+    }
+
+    #[test]
+    fn brev_reverses_the_bits_within_the_destination_size() {
+        // brev ab, hlt
+        let execution = run(&[0x3f, 0x60, 0x10, 0x00], &[(Register::A, 0x12345601)]);
+        assert_eq!(execution.get_register(Register::A), 0x12345680);
+        // brev as, hlt
+        let execution = run(&[0x3f, 0x60, 0x20, 0x00], &[(Register::A, 0x12340001)]);
+        assert_eq!(execution.get_register(Register::A), 0x12348000);
+        // brev a, hlt
+        let execution = run(&[0x3f, 0x60, 0x00, 0x00], &[(Register::A, 0x12345678)]);
+        assert_eq!(execution.get_register(Register::A), 0x1e6a2c48);
+
+        // brev #d00000, hlt
+        let code = [0x3f, 0x40, 0x00, 0x00, 0xd0, 0x00];
+        let execution = run_harness(&code, &[(0xd00000, &[1, 0, 0, 0])], &[]);
+        assert_eq!(execution.memory().read_int(0xd00000), 0x80000000);
+    }
+
+    #[test]
+    fn pauses_before_system_calls_until_resumed() {
+        // push 42, sys print_u32, hlt
+        let mut code = vec![0x1d, 0x30, 0x2a, 0x00, 0x00, 0x00];
+        code.extend(sys_call_code("print_u32"));
+        let mut execution = harness(&code, &[]);
+        let output = SharedBuffer::default();
+        execution.set_output(Box::new(output.clone()));
+        execution.set_pause_on_sys_call(true);
+
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        let pending = Ok(Effect::SysCallPending(hash("print_u32")));
+        assert_eq!(execution.next_step(), pending);
+        // stepping again does not get past the pause
+        assert_eq!(execution.next_step(), pending);
+        assert_eq!(
+            execution
+                .memory()
+                .read_int(execution.get_register(Register::S)),
+            42
+        );
+        assert!(output.0.borrow().is_empty());
+
+        execution.resume_sys_call().unwrap();
+        assert_eq!(output.0.take(), b"42");
+        assert!(matches!(execution.next_step(), Ok(Effect::Halted(_))));
+    }
+
+    #[test]
+    fn memory_permissions_fault_each_violation() {
+        use crate::hardware::Permissions;
+        let data = 0xd00000..0xd00100;
+        let restricted = |code: &[u8], permissions| {
+            let mut execution = harness(code, &[(0xd00000, &[1; 4])]);
+            execution
+                .memory_mut()
+                .set_permissions(data.clone(), permissions);
+            execution.set_register(Register::A, 7);
+            execution
+        };
+
+        // ld a #d00000, hlt
+        let code = [0x10, 0x64, 0x00, 0x00, 0x00, 0xd0, 0x00];
+        let no_read = Permissions {
+            read: false,
+            ..Permissions::ALL
+        };
+        let mut execution = restricted(&code, no_read);
+        assert_eq!(
+            execution.next_step(),
+            Err(Tx8Error::ReadViolation(0xd00000))
+        );
+        assert_eq!(execution.get_register(Register::A), 7);
+
+        // ld #d00000 a, hlt
+        let code = [0x10, 0x46, 0x00, 0x00, 0xd0, 0x00, 0x00];
+        let no_write = Permissions {
+            write: false,
+            ..Permissions::ALL
+        };
+        let mut execution = restricted(&code, no_write);
+        assert_eq!(
+            execution.next_step(),
+            Err(Tx8Error::WriteViolation(0xd00000))
+        );
+        assert_eq!(execution.memory().read_int(0xd00000), 0x01010101);
+
+        // jmp 0xd00000
+        let code = [0x02, 0x30, 0x00, 0x00, 0xd0, 0x00];
+        let no_execute = Permissions {
+            execute: false,
+            ..Permissions::ALL
+        };
+        let mut execution = restricted(&code, no_execute);
+        execution.next_step().unwrap();
+        assert_eq!(
+            execution.next_step(),
+            Err(Tx8Error::ExecuteViolation(0xd00000))
+        );
+
+        // everything is allowed by default
+        let mut execution = restricted(&code, Permissions::ALL);
+        execution.next_step().unwrap();
+        assert!(execution.next_step().is_ok());
+    }
+
+    #[test]
+    fn executions_use_the_given_input_instead_of_stdin() {
+        // nop, hlt
+        let rom = Rom::from_code(&[0x01, 0x00]);
+        let mut execution = Execution::new_with_rom_and_input(&rom, Vec::new()).unwrap();
+        assert_eq!(execution.next_step(), Ok(Effect::None));
+        assert_eq!(execution.get_register(Register::P), 0x400001);
+
+        let code = sys_call_code("read_char");
+        let rom = Rom::from_code(&code);
+        let mut execution = Execution::new_with_rom_and_input(&rom, Vec::new()).unwrap();
+        assert_eq!(execution.next_step(), Err(Tx8Error::NoInputGiven));
+        let mut execution = Execution::new_with_rom_and_input(&rom, b"x".to_vec()).unwrap();
+        execution.next_step().unwrap();
+        assert_eq!(execution.get_register(Register::O), b'x' as u32);
+    }
+
+    #[test]
+    fn trap_handlers_resolve_division_by_zero() {
+        // div a b, inc c, hlt
+        let code = [0x25, 0x66, 0x00, 0x01, 0x20, 0x60, 0x02, 0x00];
+        for (action, a) in [
+            (TrapAction::Resume(0), Ok(0)),
+            (TrapAction::Skip, Ok(7)),
+            (TrapAction::Abort, Err(Tx8Error::DivisionByZero)),
+        ] {
+            let mut execution = harness(&code, &[]);
+            execution.set_register(Register::A, 7);
+            let traps = Rc::new(RefCell::new(Vec::new()));
+            let seen = traps.clone();
+            execution.set_trap_handler(Some(Box::new(move |kind, _, _| {
+                seen.borrow_mut().push(kind);
+                action
+            })));
+            let result = loop {
+                match execution.next_step() {
+                    Ok(Effect::Halted(_)) => break Ok(execution.get_register(Register::A)),
+                    Ok(_) => {}
+                    Err(error) => break Err(error),
+                }
+            };
+            assert_eq!(result, a, "{action:?}");
+            assert_eq!(*traps.borrow(), [TrapKind::DivisionByZero]);
+            if a.is_ok() {
+                assert_eq!(execution.get_register(Register::C), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn jumps_read_loaded_r_values_as_signed() {
+        for (load, jump, taken) in [
+            // ld r 0xff zero-extends to 255, lds r 0xff sign-extends to -1
+            (vec![0x10, 0x61, 0x04, 0xff], 0x07, false),
+            (vec![0x10, 0x61, 0x04, 0xff], 0x05, true),
+            (vec![0x11, 0x61, 0x04, 0xff], 0x07, true),
+            (vec![0x11, 0x61, 0x04, 0xff], 0x05, false),
+            // ld r 0xffffffff is -1 as well
+            (vec![0x10, 0x63, 0x04, 0xff, 0xff, 0xff, 0xff], 0x07, true),
+            (vec![0x10, 0x63, 0x04, 0xff, 0xff, 0xff, 0xff], 0x08, true),
+            (vec![0x10, 0x63, 0x04, 0xff, 0xff, 0xff, 0xff], 0x06, false),
+            // ld r 2 is greater, not an unordered comparison
+            (vec![0x10, 0x61, 0x04, 0x02], 0x05, true),
+            (vec![0x10, 0x61, 0x04, 0x02], 0x06, true),
+            (vec![0x10, 0x61, 0x04, 0x02], 0x04, true),
+            (vec![0x10, 0x61, 0x04, 0x02], 0x08, false),
+        ] {
+            // load, jump 0x400100
+            let mut code = load.clone();
+            code.extend([jump, 0x30, 0x00, 0x01, 0x40, 0x00]);
+            let mut execution = harness(&code, &[]);
+            execution.next_step().unwrap();
+            execution.next_step().unwrap();
+            let target = if taken {
+                0x400100
+            } else {
+                0x400000 + code.len() as u32
+            };
+            assert_eq!(
+                execution.get_register(Register::P),
+                target,
+                "{load:x?} {jump:x}"
+            );
+        }
+    }
+
+    #[test]
+    fn jumps_between_loaded_images() {
+        // inc b, jmp 0x100000
+        let program = [0x20, 0x60, 0x01, 0x02, 0x30, 0x00, 0x00, 0x10, 0x00];
+        // inc a, hlt
+        let kernel = [0x20, 0x60, 0x00, 0x00];
+        let images: [(&[u8], u32); 2] = [(&program, 0x400000), (&kernel, 0x100000)];
+        let mut execution = Execution::new_with_images(&images, 0x400000).unwrap();
+        run_to_halt(&mut execution);
+        assert_eq!(execution.get_register(Register::A), 1);
+        assert_eq!(execution.get_register(Register::B), 1);
+        assert_eq!(execution.get_register(Register::P), 0x100003);
+
+        execution.reset();
+        assert_eq!(execution.get_register(Register::P), 0x400000);
+
+        let overlapping: [(&[u8], u32); 2] = [(&program, 0x400000), (&kernel, 0x400008)];
+        assert_eq!(
+            Execution::new_with_images(&overlapping, 0x400000).err(),
+            Some(Tx8Error::SectionOverlap)
+        );
+        let outside: [(&[u8], u32); 2] = [(&program, 0x400000), (&kernel, 0xfffffe)];
+        assert_eq!(
+            Execution::new_with_images(&outside, 0x400000).err(),
+            Some(Tx8Error::OutOfBoundsWrite(0xfffffe))
+        );
+    }
+
+    #[test]
+    fn every_loaded_image_counts_as_code() {
+        // inc b, jmp 0x100000
+        let program = [0x20, 0x60, 0x01, 0x02, 0x30, 0x00, 0x00, 0x10, 0x00];
+        let run_checked = |kernel: &[u8]| {
+            let images: [(&[u8], u32); 2] = [(&program, 0x400000), (kernel, 0x100000)];
+            let mut execution = Execution::new_with_images(&images, 0x400000).unwrap();
+            execution.set_code_bounds_check(true);
+            execution.set_code_protection(true);
+            let result = loop {
+                match execution.next_step() {
+                    Ok(Effect::Halted(reason)) => break Ok(reason),
+                    Ok(_) => {}
+                    Err(error) => break Err(error),
+                }
+            };
+            (execution, result)
+        };
+
+        // inc a, hlt
+        let (execution, result) = run_checked(&[0x20, 0x60, 0x00, 0x00]);
+        assert_eq!(result, Ok(HaltReason::HaltOpcode));
+        assert_eq!(execution.get_register(Register::A), 1);
+
+        // inc a, running off the end of the second image
+        let (execution, result) = run_checked(&[0x20, 0x60, 0x00]);
+        assert_eq!(result, Ok(HaltReason::RanOffEnd));
+        assert_eq!(execution.get_register(Register::P), 0x100003);
+
+        // ld #100000 a, hlt
+        let (_, result) = run_checked(&[0x10, 0x46, 0x00, 0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(result, Err(Tx8Error::SelfModifyingCode));
+    }
+
+    #[test]
+    fn null_guard_catches_low_addresses() {
+        // ld a @b, hlt / ld @b a, hlt
+        let load = [0x10, 0x67, 0x00, 0x01, 0x00];
+        let store = [0x10, 0x76, 0x01, 0x00, 0x00];
+        for (code, b, guard, result) in [
+            (load, 0, 0x1000, Err(Tx8Error::NullPointerAccess(0))),
+            (store, 0x10, 0x1000, Err(Tx8Error::NullPointerAccess(0x10))),
+            (load, 0x1000, 0x1000, Ok(Effect::None)),
+            (load, 0, 0, Ok(Effect::None)),
+        ] {
+            let mut execution = harness(&code, &[]);
+            execution.set_null_guard(guard);
+            execution.set_register(Register::B, b);
+            assert_eq!(execution.next_step(), result, "{code:x?} {b:x}");
+        }
+    }
+
+    #[test]
+    fn fixed_point_conversions_round_trip_and_saturate() {
+        // ftx as 8, xtf as 8, hlt
+        let code = [0x79, 0x61, 0x20, 0x08, 0x7a, 0x61, 0x20, 0x08, 0x00];
+        let mut execution = harness(&code, &[]);
+        execution.set_register(Register::A, 0.5f32.to_bits());
+        execution.next_step().unwrap();
+        assert_eq!(execution.get_register(Register::A) & 0xffff, 0x0080);
+        execution.next_step().unwrap();
+        assert_eq!(f32::from_bits(execution.get_register(Register::A)), 0.5);
+
+        for (val, fixed) in [
+            (-0.5f32, 0xff80),
+            (1000.0, 0x7fff),
+            (-1000.0, 0x8000),
+            (f32::NAN, 0),
+        ] {
+            // ftx as 8, hlt
+            let execution = run(
+                &[0x79, 0x61, 0x20, 0x08, 0x00],
+                &[(Register::A, val.to_bits())],
+            );
+            assert_eq!(execution.get_register(Register::A) & 0xffff, fixed, "{val}");
+        }
+    }
+
+    #[test]
+    fn prints_codepoints_as_utf8() {
+        for (codepoint, expected) in [(0x1f600, "😀"), (0xe9, "é"), (0x41, "A")] {
+            // push codepoint, sys print_utf8_codepoint, hlt
+            let mut code = vec![0x1d, 0x30];
+            code.extend(u32::to_le_bytes(codepoint));
+            code.extend(sys_call_code("print_utf8_codepoint"));
+            let (_, output) = run_printing(&code, &[]);
+            assert_eq!(output, expected);
+        }
+        for codepoint in [0xd800, 0x110000] {
+            let mut code = vec![0x1d, 0x30];
+            code.extend(u32::to_le_bytes(codepoint));
+            code.extend(sys_call_code("print_utf8_codepoint"));
+            let mut execution = harness(&code, &[]);
+            execution.next_step().unwrap();
+            assert_eq!(
+                execution.next_step(),
+                Err(Tx8Error::InvalidCodepoint(codepoint))
+            );
+        }
+    }
+}
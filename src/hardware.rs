@@ -1,10 +1,21 @@
-use crate::Tx8Error;
+use std::{
+    cell::{Cell, RefCell},
+    ops::Range,
+    rc::Rc,
+};
 
-const MB_16: usize = 1 << 24;
-const MB_8: usize = 1 << 23;
-const MB_4: usize = 1 << 22;
+use crate::{DataSection, Tx8Error};
+
+pub(crate) const MB_16: usize = 1 << 24;
+pub(crate) const MB_8: usize = 1 << 23;
+pub const MB_4: usize = 1 << 22;
+
+pub const STACK_TOP: u32 = 0xc02000;
+const STACK_SIZE: u32 = 0x2000;
+pub(crate) const STACK_BOTTOM: u32 = STACK_TOP - STACK_SIZE;
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Cpu {
     pub a: u32,
     pub b: u32,
@@ -14,6 +25,9 @@ pub struct Cpu {
     pub o: u32,
     pub s: u32,
     pub p: u32,
+    /// Whether `R` holds the result of an unordered float comparison, which no conditional jump
+    /// except `jne` takes. Every other write to `R` clears it.
+    pub unordered: bool,
 }
 
 impl Cpu {
@@ -25,31 +39,522 @@ impl Cpu {
             d: 0,
             r: 0,
             o: 0,
-            s: 0xc02000,
+            s: STACK_TOP,
             p: MB_4 as u32,
+            unordered: false,
+        }
+    }
+
+    /// Sets `R` to a result which is not an unordered float comparison
+    pub fn set_r(&mut self, val: u32) {
+        self.r = val;
+        self.unordered = false;
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The CPU registers in the order of their encoding in register parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    R,
+    O,
+    P,
+    S,
+}
+
+impl Register {
+    pub const ALL: [Register; 8] = [
+        Register::A,
+        Register::B,
+        Register::C,
+        Register::D,
+        Register::R,
+        Register::O,
+        Register::P,
+        Register::S,
+    ];
+}
+
+impl Cpu {
+    pub fn get(&self, reg: Register) -> u32 {
+        match reg {
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::D => self.d,
+            Register::R => self.r,
+            Register::O => self.o,
+            Register::P => self.p,
+            Register::S => self.s,
         }
     }
+
+    pub fn set(&mut self, reg: Register, val: u32) {
+        match reg {
+            Register::A => self.a = val,
+            Register::B => self.b = val,
+            Register::C => self.c = val,
+            Register::D => self.d = val,
+            Register::R => self.set_r(val),
+            Register::O => self.o = val,
+            Register::P => self.p = val,
+            Register::S => self.s = val,
+        }
+    }
+}
+
+/// Formats a register value as zero-padded hex, e.g. `0x0000002a`. With `as_signed`, the two's
+/// complement interpretation follows in parentheses, e.g. `0xffffffff (-1)`.
+pub fn format_reg(val: u32, as_signed: bool) -> String {
+    if as_signed {
+        format!("{val:#010x} ({})", val as i32)
+    } else {
+        format!("{val:#010x}")
+    }
+}
+
+/// What the program may do with a memory region
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    /// Whether instructions may be fetched from it
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const ALL: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: true,
+    };
+}
+
+/// A device reacting to reads and writes of the memory range it is mapped to. `addr` is the
+/// absolute address, `size` the number of bytes accessed (1, 2 or 4).
+pub trait MmioHandler {
+    fn read(&mut self, addr: u32, size: u32) -> u32;
+    fn write(&mut self, addr: u32, size: u32, val: u32);
+}
+
+// clones of a memory share their devices
+#[derive(Clone)]
+struct IoMapping {
+    range: Range<u32>,
+    handler: Rc<RefCell<Box<dyn MmioHandler>>>,
+}
+
+impl std::fmt::Debug for IoMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IoMapping")
+            .field("range", &self.range)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Called with the address, the number of bytes and whether it was a write for every memory
+/// access, see `Memory::set_access_hook`
+pub type AccessHook = Box<dyn FnMut(u32, u32, bool)>;
+
+// clones of a memory share the hook like their devices
+#[derive(Clone)]
+struct SharedHook(Rc<RefCell<AccessHook>>);
+
+impl std::fmt::Debug for SharedHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AccessHook")
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Memory {
     array: Vec<u8>,
+    // the code and data sections with their load addresses, kept to restore the memory on reset
+    images: Vec<(u32, Vec<u8>)>,
+    // the address ranges of the code images, without the data sections
+    code: Vec<Range<u32>>,
+    // one entry per stack byte, true if it was written since the stack pointer last moved over it
+    stack_shadow: Option<Vec<bool>>,
+    uninitialized_read: Cell<Option<u32>>,
+    // address, previous and new value of every byte written while recording
+    write_log: Option<Vec<(u32, u8, u8)>>,
+    io_mappings: Vec<IoMapping>,
+    access_hook: Option<SharedHook>,
+    // writes into the code are discarded and the first address is recorded if enabled
+    protect_code: bool,
+    code_write: Option<u32>,
+    // relative addresses outside of the memory fail instead of wrapping around if enabled
+    bounded_offsets: bool,
+    // regions with restricted permissions, later regions take precedence
+    permissions: Vec<(Range<u32>, Permissions)>,
+    // accesses below this address are reported as null pointer accesses, 0 disables the guard
+    null_guard: u32,
+    access_violation: Cell<Option<Tx8Error>>,
 }
 
 impl Memory {
-    pub fn load_rom(data: &[u8]) -> Result<Self, Tx8Error> {
+    pub fn load_rom(data: &[u8], sections: &[DataSection]) -> Result<Self, Tx8Error> {
+        Self::load_rom_relocated(data, sections, &[], MB_4 as u32)
+    }
+
+    /// Loads the code at `base` instead of the usual code address. Every relocation is the offset
+    /// of a 32 bit address in the code, which is moved by the same distance as the code. Fails with
+    /// `Tx8Error::InvalidRelocation` if a moved address leaves the memory.
+    pub fn load_rom_relocated(
+        data: &[u8],
+        sections: &[DataSection],
+        relocations: &[u32],
+        base: u32,
+    ) -> Result<Self, Tx8Error> {
         if data.len() > MB_8 {
+            return Err(Tx8Error::RomTooLarge {
+                size: data.len(),
+                max: MB_8,
+            });
+        }
+        let start = base as usize;
+        if start + data.len() > MB_16 {
+            return Err(Tx8Error::OutOfBoundsWrite(base));
+        }
+
+        let mut code = data.to_vec();
+        for &offset in relocations {
+            let bytes = code
+                .get_mut(offset as usize..offset as usize + 4)
+                .ok_or(Tx8Error::InvalidRelocation(offset))?;
+            let address = u32::from_le_bytes((*bytes).try_into()?) as i64;
+            let moved = address - MB_4 as i64 + start as i64;
+            if !(0..MB_16 as i64).contains(&moved) {
+                return Err(Tx8Error::InvalidRelocation(offset));
+            }
+            bytes.copy_from_slice(&(moved as u32).to_le_bytes());
+        }
+
+        // sections must lie within memory and must not overlap the code or each other
+        let mut ranges = vec![(start, start + code.len())];
+        for section in sections {
+            let start = section.address as usize;
+            let end = start + section.data.len();
+            if end > MB_16 {
+                return Err(Tx8Error::OutOfBoundsWrite(section.address));
+            }
+            ranges.push((start, end));
+        }
+        ranges.sort_unstable();
+        if ranges.windows(2).any(|w| w[0].1 > w[1].0) {
+            return Err(Tx8Error::SectionOverlap);
+        }
+
+        let code_range = base..base + code.len() as u32;
+        let mut images = vec![(base, code)];
+        images.extend(
+            sections
+                .iter()
+                .map(|section| (section.address, section.data.to_vec())),
+        );
+        let mut memory = Memory {
+            array: vec![0; MB_16],
+            images,
+            code: vec![code_range],
+            stack_shadow: None,
+            uninitialized_read: Cell::new(None),
+            write_log: None,
+            io_mappings: Vec::new(),
+            access_hook: None,
+            protect_code: false,
+            code_write: None,
+            bounded_offsets: false,
+            permissions: Vec::new(),
+            null_guard: 0,
+            access_violation: Cell::new(None),
+        };
+        memory.copy_images();
+        Ok(memory)
+    }
+
+    /// Loads another code image at `base`, e.g. a second rom next to the one the memory was
+    /// created with. It must lie within memory and must not overlap the code, the data sections or
+    /// another image. Like the rom, it is restored by `reset` and counts as code for the code
+    /// protection and `is_code`.
+    pub fn load_rom_at(&mut self, data: &[u8], base: u32) -> Result<(), Tx8Error> {
+        let start = base as usize;
+        let end = start + data.len();
+        if end > MB_16 {
+            return Err(Tx8Error::OutOfBoundsWrite(base));
+        }
+        let overlaps = |(address, image): &(u32, Vec<u8>)| {
+            start < *address as usize + image.len() && (*address as usize) < end
+        };
+        if self.images.iter().any(overlaps) {
+            return Err(Tx8Error::SectionOverlap);
+        }
+        self.array[start..end].copy_from_slice(data);
+        self.images.push((base, data.to_vec()));
+        self.code.push(base..end as u32);
+        Ok(())
+    }
+
+    /// Zeroes the whole memory and copies the rom back in, reusing the existing allocation.
+    /// Stack poisoning stays enabled if it was, with the whole stack uninitialized again.
+    pub fn reset(&mut self) {
+        self.array.fill(0);
+        self.copy_images();
+        if let Some(shadow) = &mut self.stack_shadow {
+            shadow.fill(false);
+        }
+        self.uninitialized_read.set(None);
+        self.access_violation.set(None);
+    }
+
+    /// Copies the whole memory array, bypassing mapped devices
+    pub fn export(&self) -> Vec<u8> {
+        self.array.clone()
+    }
+
+    /// Replaces the whole memory array with an image created by `export`. The rom images used by
+    /// `reset` are kept. Fails with `Tx8Error::ParseError` if the image has the wrong size.
+    pub fn import(&mut self, image: &[u8]) -> Result<(), Tx8Error> {
+        if image.len() != self.array.len() {
             return Err(Tx8Error::ParseError);
         }
-        let mut array = vec![0; MB_16];
-        array[MB_4..MB_4 + data.len()].copy_from_slice(data);
-        Ok(Memory { array })
+        self.array.copy_from_slice(image);
+        Ok(())
+    }
+
+    fn copy_images(&mut self) {
+        for (address, data) in &self.images {
+            let start = *address as usize;
+            self.array[start..start + data.len()].copy_from_slice(data);
+        }
+    }
+
+    pub fn set_stack_poisoning(&mut self, enabled: bool) {
+        self.stack_shadow = if enabled {
+            Some(vec![false; STACK_SIZE as usize])
+        } else {
+            None
+        };
+        self.uninitialized_read.set(None);
+    }
+
+    /// Makes the code read-only. Writes into it are discarded and the first such address is
+    /// returned by `take_code_write`.
+    pub fn set_code_protection(&mut self, enabled: bool) {
+        self.protect_code = enabled;
+        self.code_write = None;
+    }
+
+    /// Returns the address of the first discarded write into the code since the last call
+    pub fn take_code_write(&mut self) -> Option<u32> {
+        self.code_write.take()
+    }
+
+    /// Makes relative addresses fail with `Tx8Error::OffsetOutOfBounds` if the address parameter,
+    /// read as a signed 24 bit offset, plus the `O` register lies outside of the memory
+    pub fn set_bounded_offsets(&mut self, enabled: bool) {
+        self.bounded_offsets = enabled;
+    }
+
+    /// Adds the `O` register to the address parameter of a relative address
+    pub fn relative_address(&self, ptr: u32, offset: u32) -> Result<u32, Tx8Error> {
+        if self.bounded_offsets {
+            let signed = ((ptr << 8) as i32 >> 8) as i64;
+            if !(0..MB_16 as i64).contains(&(signed + offset as i64)) {
+                return Err(Tx8Error::OffsetOutOfBounds);
+            }
+        }
+        Ok(ptr.wrapping_add(offset))
+    }
+
+    /// Restricts what the program may do with the given range, overriding earlier calls for the
+    /// same addresses. Everything is readable, writable and executable by default. Reads of a
+    /// non-readable region still return the memory contents, writes to a non-writable region are
+    /// discarded and the first violation is returned by `take_access_violation`.
+    pub fn set_permissions(&mut self, range: Range<u32>, permissions: Permissions) {
+        self.permissions.push((range, permissions));
+    }
+
+    pub fn permissions(&self, ptr: u32) -> Permissions {
+        let ptr = truncate_ptr(ptr) as u32;
+        self.permissions
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&ptr))
+            .map_or(Permissions::ALL, |(_, permissions)| *permissions)
+    }
+
+    /// Treats reads and writes of the addresses below `threshold` like accesses the permissions
+    /// forbid, reported as `Tx8Error::NullPointerAccess`, as they most likely dereference a null
+    /// pointer. Fetching an instruction there fails the same way. 0 disables the guard.
+    pub fn set_null_guard(&mut self, threshold: u32) {
+        self.null_guard = threshold;
+    }
+
+    /// Returns the first read of a non-readable or write to a non-writable address since the last
+    /// call as `Tx8Error::ReadViolation` or `Tx8Error::WriteViolation`, or the first access below
+    /// the null guard as `Tx8Error::NullPointerAccess`
+    pub fn take_access_violation(&self) -> Option<Tx8Error> {
+        self.access_violation.take()
+    }
+
+    /// Fails with `Tx8Error::ExecuteViolation` if an instruction may not be fetched from the range
+    pub fn check_executable(&self, range: Range<u32>) -> Result<(), Tx8Error> {
+        if truncate_ptr(range.start) < self.null_guard as usize {
+            return Err(Tx8Error::NullPointerAccess(truncate_ptr(range.start) as u32));
+        }
+        match range
+            .into_iter()
+            .find(|ptr| !self.permissions(*ptr).execute)
+        {
+            Some(ptr) => Err(Tx8Error::ExecuteViolation(truncate_ptr(ptr) as u32)),
+            None => Ok(()),
+        }
     }
+
+    // records the first address of the access the permissions forbid, if any
+    fn check_permissions(&self, ptr: u32, size: u32, is_write: bool) -> bool {
+        if truncate_ptr(ptr) < self.null_guard as usize {
+            if self.access_violation.get().is_none() {
+                let ptr = truncate_ptr(ptr) as u32;
+                self.access_violation
+                    .set(Some(Tx8Error::NullPointerAccess(ptr)));
+            }
+            return false;
+        }
+        if self.permissions.is_empty() {
+            return true;
+        }
+        let denied = (0..size).map(|i| ptr.wrapping_add(i)).find(|ptr| {
+            let permissions = self.permissions(*ptr);
+            !if is_write {
+                permissions.write
+            } else {
+                permissions.read
+            }
+        });
+        let Some(ptr) = denied else {
+            return true;
+        };
+        if self.access_violation.get().is_none() {
+            let ptr = truncate_ptr(ptr) as u32;
+            self.access_violation.set(Some(if is_write {
+                Tx8Error::WriteViolation(ptr)
+            } else {
+                Tx8Error::ReadViolation(ptr)
+            }));
+        }
+        false
+    }
+
+    /// Whether the address belongs to one of the loaded code images, data sections do not count
+    pub fn is_code(&self, ptr: u32) -> bool {
+        self.code.iter().any(|range| range.contains(&ptr))
+    }
+
+    /// Marks all stack bytes between the old and the new stack pointer as uninitialized. Bytes
+    /// below the stack pointer are freshly allocated, bytes above it were freed by a pop.
+    pub fn move_stack_pointer(&mut self, old: u32, new: u32) {
+        if let Some(shadow) = &mut self.stack_shadow {
+            for ptr in old.min(new)..old.max(new) {
+                if let Some(idx) = stack_index(ptr) {
+                    shadow[idx] = false;
+                }
+            }
+        }
+    }
+
+    /// Routes all accesses to the given address range to the handler instead of the memory
+    pub fn map_io(
+        &mut self,
+        range: Range<u32>,
+        handler: Box<dyn MmioHandler>,
+    ) -> Result<(), Tx8Error> {
+        if range.end as usize > MB_16 {
+            return Err(Tx8Error::OutOfBoundsWrite(range.start));
+        }
+        let overlaps =
+            |other: &IoMapping| range.start < other.range.end && other.range.start < range.end;
+        if self.io_mappings.iter().any(overlaps) {
+            return Err(Tx8Error::SectionOverlap);
+        }
+        self.io_mappings.push(IoMapping {
+            range,
+            handler: Rc::new(RefCell::new(handler)),
+        });
+        Ok(())
+    }
+
+    /// Reports every read and write to the hook, including instruction fetches and accesses of
+    /// mapped devices. Multi-byte accesses are reported once with their size. `None` removes it.
+    pub fn set_access_hook(&mut self, hook: Option<AccessHook>) {
+        self.access_hook = hook.map(|hook| SharedHook(Rc::new(RefCell::new(hook))));
+    }
+
+    fn log_access(&self, ptr: u32, size: u32, is_write: bool) {
+        if let Some(hook) = &self.access_hook {
+            (hook.0.borrow_mut())(truncate_ptr(ptr) as u32, size, is_write);
+        }
+    }
+
+    fn io_mapping(&self, ptr: u32) -> Option<&IoMapping> {
+        if self.io_mappings.is_empty() {
+            return None;
+        }
+        let ptr = truncate_ptr(ptr) as u32;
+        self.io_mappings
+            .iter()
+            .find(|mapping| mapping.range.contains(&ptr))
+    }
+
+    fn io_read(&self, ptr: u32, size: u32) -> Option<u32> {
+        let mapping = self.io_mapping(ptr)?;
+        let addr = truncate_ptr(ptr) as u32;
+        Some(mapping.handler.borrow_mut().read(addr, size))
+    }
+
+    fn io_write(&self, ptr: u32, size: u32, val: u32) -> bool {
+        let Some(mapping) = self.io_mapping(ptr) else {
+            return false;
+        };
+        let addr = truncate_ptr(ptr) as u32;
+        mapping.handler.borrow_mut().write(addr, size, val);
+        true
+    }
+
+    /// Returns the address of the first uninitialized stack byte read since the last call.
+    pub fn take_uninitialized_read(&self) -> Option<u32> {
+        self.uninitialized_read.take()
+    }
+
     pub fn read_byte(&self, ptr: u32) -> u8 {
         self.read(ptr)
     }
 
     pub fn read(&self, ptr: u32) -> u8 {
+        self.log_access(ptr, 1, false);
+        self.check_permissions(ptr, 1, false);
+        self.load(ptr)
+    }
+
+    // reads a byte without reporting it to the access hook
+    fn load(&self, ptr: u32) -> u8 {
+        if let Some(val) = self.io_read(ptr, 1) {
+            return val as u8;
+        }
+        if let (Some(shadow), Some(idx)) = (&self.stack_shadow, stack_index(ptr)) {
+            if !shadow[idx] && self.uninitialized_read.get().is_none() {
+                self.uninitialized_read.set(Some(ptr));
+            }
+        }
         let ptr = truncate_ptr(ptr);
         // If the pointer is out of bounds return 0, otherwise the byte
         match self.array.get(ptr) {
@@ -59,42 +564,156 @@ impl Memory {
     }
 
     pub fn read_short(&self, ptr: u32) -> u16 {
-        let bytes = [self.read(ptr), self.read(ptr + 1)];
+        self.log_access(ptr, 2, false);
+        self.check_permissions(ptr, 2, false);
+        if let Some(val) = self.io_read(ptr, 2) {
+            return val as u16;
+        }
+        let bytes = [self.load(ptr), self.load(ptr.wrapping_add(1))];
         u16::from_le_bytes(bytes)
     }
     pub fn read_24bit(&self, ptr: u32) -> u32 {
-        let bytes = [self.read(ptr), self.read(ptr + 1), self.read(ptr + 2), 0];
+        self.log_access(ptr, 3, false);
+        self.check_permissions(ptr, 3, false);
+        if let Some(val) = self.io_read(ptr, 3) {
+            return val & 0xffffff;
+        }
+        let bytes = [
+            self.load(ptr),
+            self.load(ptr.wrapping_add(1)),
+            self.load(ptr.wrapping_add(2)),
+            0,
+        ];
         u32::from_le_bytes(bytes)
     }
+    /// Reads 3 bytes and sign extends them from bit 23
+    pub fn read_24bit_signed(&self, ptr: u32) -> i32 {
+        ((self.read_24bit(ptr) << 8) as i32) >> 8
+    }
     pub fn read_int(&self, ptr: u32) -> u32 {
+        self.log_access(ptr, 4, false);
+        self.check_permissions(ptr, 4, false);
+        if let Some(val) = self.io_read(ptr, 4) {
+            return val;
+        }
         let bytes = [
-            self.read(ptr),
-            self.read(ptr + 1),
-            self.read(ptr + 2),
-            self.read(ptr + 3),
+            self.load(ptr),
+            self.load(ptr.wrapping_add(1)),
+            self.load(ptr.wrapping_add(2)),
+            self.load(ptr.wrapping_add(3)),
         ];
         u32::from_le_bytes(bytes)
     }
 
+    /// Returns the first address at or after `start` where `pattern` occurs
+    pub fn find(&self, pattern: &[u8], start: u32) -> Option<u32> {
+        self.matches(pattern, start).next()
+    }
+
+    /// Returns all addresses at or after `start` where `pattern` occurs
+    pub fn find_all(&self, pattern: &[u8], start: u32) -> Vec<u32> {
+        self.matches(pattern, start).collect()
+    }
+
+    fn matches<'a>(&'a self, pattern: &'a [u8], start: u32) -> impl Iterator<Item = u32> + 'a {
+        let start = (start as usize).min(self.array.len());
+        // an empty pattern never matches
+        let size = pattern.len().max(1);
+        self.array[start..]
+            .windows(size)
+            .enumerate()
+            .filter(move |(_, window)| *window == pattern)
+            .map(move |(i, _)| (start + i) as u32)
+    }
+
     pub fn write(&mut self, ptr: u32, val: u8) {
+        self.log_access(ptr, 1, true);
+        if !self.check_permissions(ptr, 1, true) {
+            return;
+        }
+        self.store(ptr, val);
+    }
+
+    // writes a byte without reporting it to the access hook
+    fn store(&mut self, ptr: u32, val: u8) {
+        if self.io_write(ptr, 1, val as u32) {
+            return;
+        }
+        if self.protect_code && self.is_code(truncate_ptr(ptr) as u32) {
+            self.code_write.get_or_insert(truncate_ptr(ptr) as u32);
+            return;
+        }
+        if let (Some(shadow), Some(idx)) = (&mut self.stack_shadow, stack_index(ptr)) {
+            shadow[idx] = true;
+        }
         let ptr = truncate_ptr(ptr);
+        if let Some(log) = &mut self.write_log {
+            log.push((ptr as u32, self.array[ptr], val));
+        }
         self.array[ptr] = val;
     }
 
+    /// Starts recording all writes until `take_write_log` is called
+    pub(crate) fn start_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stops recording and returns the address, previous and new value of every byte written
+    pub(crate) fn take_write_log(&mut self) -> Vec<(u32, u8, u8)> {
+        self.write_log.take().unwrap_or_default()
+    }
+
     pub fn write_byte(&mut self, ptr: u32, val: u8) {
         self.write(ptr, val)
     }
     pub fn write_short(&mut self, ptr: u32, val: u16) {
+        self.log_access(ptr, 2, true);
+        if !self.check_permissions(ptr, 2, true) {
+            return;
+        }
+        if self.io_write(ptr, 2, val as u32) {
+            return;
+        }
         let [first, second] = val.to_le_bytes();
-        self.write(ptr, first);
-        self.write(ptr + 1, second);
+        self.store(ptr, first);
+        self.store(ptr.wrapping_add(1), second);
+    }
+    /// Writes the lowest 3 bytes of `val`, the highest byte is ignored
+    pub fn write_24bit(&mut self, ptr: u32, val: u32) {
+        self.log_access(ptr, 3, true);
+        if !self.check_permissions(ptr, 3, true) {
+            return;
+        }
+        if self.io_write(ptr, 3, val & 0xffffff) {
+            return;
+        }
+        let [first, second, third, _] = val.to_le_bytes();
+        self.store(ptr, first);
+        self.store(ptr.wrapping_add(1), second);
+        self.store(ptr.wrapping_add(2), third);
     }
     pub fn write_int(&mut self, ptr: u32, val: u32) {
+        self.log_access(ptr, 4, true);
+        if !self.check_permissions(ptr, 4, true) {
+            return;
+        }
+        if self.io_write(ptr, 4, val) {
+            return;
+        }
         let [first, second, third, fourth] = val.to_le_bytes();
-        self.write(ptr, first);
-        self.write(ptr + 1, second);
-        self.write(ptr + 2, third);
-        self.write(ptr + 3, fourth);
+        self.store(ptr, first);
+        self.store(ptr.wrapping_add(1), second);
+        self.store(ptr.wrapping_add(2), third);
+        self.store(ptr.wrapping_add(3), fourth);
+    }
+}
+
+fn stack_index(ptr: u32) -> Option<usize> {
+    let ptr = truncate_ptr(ptr) as u32;
+    if (STACK_BOTTOM..STACK_TOP).contains(&ptr) {
+        Some((ptr - STACK_BOTTOM) as usize)
+    } else {
+        None
     }
 }
 
@@ -102,3 +721,116 @@ fn truncate_ptr(ptr: u32) -> usize {
     // take only the last 24 bit
     0xffffff & ptr as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roms_just_over_8_mb_are_too_large() {
+        let code = vec![0; MB_8 + 1];
+        let error = Memory::load_rom(&code, &[]).unwrap_err();
+        assert_eq!(
+            error,
+            Tx8Error::RomTooLarge {
+                size: MB_8 + 1,
+                max: MB_8
+            }
+        );
+        assert!(Memory::load_rom(&code[..MB_8], &[]).is_ok());
+    }
+
+    #[test]
+    fn writes_24_bit_values_and_reads_them_back() {
+        let mut mem = Memory::load_rom(&[], &[]).unwrap();
+        mem.write_24bit(0x1000, 0xab123456);
+        assert_eq!(mem.read_24bit(0x1000), 0x123456);
+        assert_eq!(mem.read_24bit_signed(0x1000), 0x123456);
+        assert_eq!(mem.read_byte(0x1003), 0);
+
+        mem.write_24bit(0x1000, 0xfedcba);
+        assert_eq!(mem.read_24bit(0x1000), 0xfedcba);
+        assert_eq!(mem.read_24bit_signed(0x1000), -0x012346);
+    }
+
+    // answers every read with its size and keeps the last write
+    struct SizeDevice(Rc<RefCell<(u32, u32)>>);
+
+    impl MmioHandler for SizeDevice {
+        fn read(&mut self, _addr: u32, size: u32) -> u32 {
+            0xff00_0000 | size
+        }
+
+        fn write(&mut self, _addr: u32, size: u32, val: u32) {
+            *self.0.borrow_mut() = (size, val);
+        }
+    }
+
+    #[test]
+    fn accesses_24_bits_of_mapped_devices_at_once() {
+        let mut mem = Memory::load_rom(&[], &[]).unwrap();
+        let last_write = Rc::new(RefCell::new((0, 0)));
+        mem.map_io(0x2000..0x2010, Box::new(SizeDevice(last_write.clone())))
+            .unwrap();
+        assert_eq!(mem.read_24bit(0x2000), 3);
+        mem.write_24bit(0x2000, 0xab123456);
+        assert_eq!(*last_write.borrow(), (3, 0x123456));
+    }
+
+    #[test]
+    fn finds_embedded_strings() {
+        let section = DataSection {
+            name: "strings",
+            address: 0xd00000,
+            data: b"hello world, hello",
+        };
+        let mem = Memory::load_rom(&[], &[section]).unwrap();
+        assert_eq!(mem.find(b"hello", 0), Some(0xd00000));
+        assert_eq!(mem.find(b"hello", 0xd00001), Some(0xd0000d));
+        assert_eq!(mem.find_all(b"hello", 0), [0xd00000, 0xd0000d]);
+        assert_eq!(mem.find(b"goodbye", 0), None);
+        assert!(mem.find_all(b"goodbye", 0).is_empty());
+    }
+
+    #[test]
+    fn formats_registers_signed_and_unsigned() {
+        assert_eq!(format_reg(0x2a, false), "0x0000002a");
+        assert_eq!(format_reg(0x2a, true), "0x0000002a (42)");
+        assert_eq!(format_reg(0xffffffff, false), "0xffffffff");
+        assert_eq!(format_reg(0xffffffff, true), "0xffffffff (-1)");
+        assert_eq!(format_reg(0x80000000, true), "0x80000000 (-2147483648)");
+    }
+
+    #[test]
+    fn exported_images_import_back() {
+        let mut mem = Memory::load_rom(&[0x01, 0x02], &[]).unwrap();
+        mem.write_int(0xd00000, 0xdeadbeef);
+        let image = mem.export();
+        mem.import(&vec![0; MB_16]).unwrap();
+        assert_eq!(mem.read_int(0xd00000), 0);
+        mem.import(&image).unwrap();
+        assert_eq!(mem.export(), image);
+        assert_eq!(mem.read_int(0xd00000), 0xdeadbeef);
+        assert_eq!(mem.import(&image[1..]), Err(Tx8Error::ParseError));
+    }
+
+    #[test]
+    fn accesses_at_the_top_of_memory_wrap_around() {
+        let mut memory = Memory::load_rom(&[], &[]).unwrap();
+        memory.write_int(0xfffffe, 0x11223344);
+        assert_eq!(memory.read_byte(0xffffff), 0x33);
+        assert_eq!(memory.read_short(0), 0x1122);
+        assert_eq!(memory.read_int(0xfffffe), 0x11223344);
+        // the whole 32 bit range is truncated to 24 bits the same way
+        assert_eq!(memory.read_int(u32::MAX - 1), 0x11223344);
+
+        memory.write_short(u32::MAX, 0x5566);
+        assert_eq!(memory.read_byte(0xffffff), 0x66);
+        assert_eq!(memory.read_byte(0), 0x55);
+        memory.write_24bit(u32::MAX, 0x778899);
+        assert_eq!(memory.read_24bit(0xffffff), 0x778899);
+        memory.write_int(u32::MAX, 0xaabbccdd);
+        assert_eq!(memory.read_int(u32::MAX), 0xaabbccdd);
+        assert_eq!(memory.read_short(u32::MAX), 0xccdd);
+    }
+}
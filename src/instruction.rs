@@ -1,40 +1,153 @@
 use crate::parameter::*;
 use crate::Size::*;
 use crate::{Cpu, Memory, Tx8Error};
+use std::ops::RangeInclusive;
+
+/// Prefix byte selecting an opcode from the extension table with the following byte
+pub const EXTENSION_PREFIX: u8 = 0xff;
+
+/// Opcodes without an instruction, kept free for future additions to the base instruction set
+pub const RESERVED_OP_CODES: [RangeInclusive<u8>; 7] = [
+    0x2c..=0x2f,
+    0x5e..=0x5f,
+    0x6b..=0x6f,
+    0x76..=0x78,
+    0x7b..=0x7f,
+    0x86..=0x8f,
+    0xa1..=0xfe,
+];
 
 pub fn parse_instruction(
     cpu: &Cpu,
     mem: &Memory,
     ptr: u32,
 ) -> Result<(Instruction, u32), Tx8Error> {
+    let raw = decode_instruction(mem, ptr)?;
+    // the instruction bytes only need to be executable, not readable
+    mem.take_access_violation();
+    mem.check_executable(ptr..ptr.wrapping_add(raw.len))?;
+    Ok((resolve(raw, cpu, mem)?, raw.len))
+}
+
+/// Resolves the parameters of a decoded instruction against the registers and the memory
+fn resolve<M: ReadMemory + ?Sized>(
+    raw: RawInstruction,
+    cpu: &Cpu,
+    mem: &M,
+) -> Result<Instruction, Tx8Error> {
+    Ok(match raw.op_code {
+        OpCode::Halt
+        | OpCode::HaltEqual
+        | OpCode::HaltNotEqual
+        | OpCode::HaltGreaterThan
+        | OpCode::HaltGreaterEqual
+        | OpCode::HaltLessThan
+        | OpCode::HaltLessEqual
+        | OpCode::Nop
+        | OpCode::ExtendedNop
+        | OpCode::Return
+        | OpCode::DebugBreak
+        | OpCode::Leave => Instruction::no_params(raw.op_code),
+        _ => Instruction::with_params(raw.op_code, raw.first, raw.second, cpu, mem)?,
+    })
+}
+
+/// Replaces a register view with the whole register
+fn whole_register(par: Parameter) -> Parameter {
+    match par {
+        Parameter::Register(byte) => Parameter::Register(byte & 0x0f),
+        par => par,
+    }
+}
+
+/// An instruction as encoded in memory, before its parameters are resolved
+#[derive(Clone, Copy, Debug)]
+pub struct RawInstruction {
+    pub op_code: OpCode,
+    pub first: Parameter,
+    pub second: Parameter,
+    pub len: u32,
+}
+
+/// Decodes the opcode and the parameters of the instruction at `ptr` without executing anything
+pub fn decode_instruction(mem: &Memory, ptr: u32) -> Result<RawInstruction, Tx8Error> {
+    decode(mem, ptr)
+}
+
+/// Decodes the instruction at `offset` of a plain byte slice like `parse_instruction`, for tools
+/// which don't want to allocate a whole `Memory`. The parameters are resolved against the
+/// registers of a new `Cpu` and a memory reading 0 everywhere, so only constants and destinations
+/// are as encoded. Returns the instruction and its length in bytes, fails with `ParseError` if
+/// the instruction doesn't fit into the slice.
+pub fn decode_from_bytes(bytes: &[u8], offset: usize) -> Result<(Instruction, usize), Tx8Error> {
+    let raw = decode_raw_from_bytes(bytes, offset)?;
+    Ok((resolve(raw, &Cpu::new(), &Unmapped)?, raw.len as usize))
+}
+
+/// Decodes the instruction at `offset` of a plain byte slice like `decode_instruction`, fails
+/// with `ParseError` if the instruction doesn't fit into the slice
+pub fn decode_raw_from_bytes(bytes: &[u8], offset: usize) -> Result<RawInstruction, Tx8Error> {
+    decode(bytes, u32::try_from(offset)?)
+}
+
+fn decode<S: ByteSource + ?Sized>(src: &S, ptr: u32) -> Result<RawInstruction, Tx8Error> {
     let mut len = 0;
 
-    // Read OpCode
-    let op_code = parse_op_code(mem.read_byte(ptr))?;
+    // Read OpCode, the extension prefix selects an opcode from the extension table
+    let op_code = match src.read_le(ptr, 1)? as u8 {
+        EXTENSION_PREFIX => {
+            len += 1;
+            parse_extended_op_code(src.read_le(ptr + len, 1)? as u8)?
+        }
+        byte => parse_op_code(byte)?,
+    };
     len += 1;
 
     // if no parameters are passed, then the instruction is fully parsed
-    match op_code {
-        OpCode::Halt | OpCode::Nop | OpCode::Return => {
-            return Ok((Instruction::no_params(op_code), 1))
-        }
-        _ => (),
-    };
+    if !takes_parameters(op_code) {
+        return Ok(RawInstruction {
+            op_code,
+            first: Parameter::Unused,
+            second: Parameter::Unused,
+            len,
+        });
+    }
 
     // Read parameter mode
-    let parameter_mode_byte = mem.read_byte(ptr + len);
+    let parameter_mode_byte = src.read_le(ptr + len, 1)? as u8;
     let first_parameter = parse_par_mode(parameter_mode_byte >> 4)?;
     let second_parameter = parse_par_mode(parameter_mode_byte & 0x0f)?;
     len += 1;
 
-    let (first_parameter, par_len) = parse_parameter(mem, ptr + len, first_parameter);
+    let (first, par_len) = parse_parameter(src, ptr + len, first_parameter)?;
     len += par_len;
-    let (second_parameter, par_len) = parse_parameter(mem, ptr + len, second_parameter);
+    let (second, par_len) = parse_parameter(src, ptr + len, second_parameter)?;
     len += par_len;
-    Ok((
-        Instruction::with_params(op_code, first_parameter, second_parameter, cpu, mem)?,
+    Ok(RawInstruction {
+        op_code,
+        first,
+        second,
         len,
-    ))
+    })
+}
+
+/// Whether a parameter mode byte and parameters follow the opcode
+fn takes_parameters(op_code: OpCode) -> bool {
+    !matches!(
+        op_code,
+        OpCode::Halt
+            | OpCode::HaltEqual
+            | OpCode::HaltNotEqual
+            | OpCode::HaltGreaterThan
+            | OpCode::HaltGreaterEqual
+            | OpCode::HaltLessThan
+            | OpCode::HaltLessEqual
+            | OpCode::Nop
+            | OpCode::ExtendedNop
+            | OpCode::Return
+            | OpCode::DebugBreak
+            | OpCode::Leave
+    )
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -55,9 +168,24 @@ pub enum Type {
     Float,
 }
 
+/// What an instruction is used for, as counted by `instruction_summary`
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Category {
+    /// Integer arithmetic, logic and bit instructions
+    Arithmetic,
+    /// Instructions operating on floats, including conversions from and to integers
+    Float,
+    /// Jumps, calls, returns and the halts, including the conditional ones like `hlteq`
+    ControlFlow,
+    SysCall,
+    /// Everything else, e.g. loads, stack operations and integer comparisons
+    Other,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Instruction {
     Halt,
+    HaltIf(Comparison),
     Nop,
     Jump(Value, Comparison),
     CompareSigned(Value, Value),
@@ -66,14 +194,33 @@ pub enum Instruction {
     Call(Value),
     SysCall(Value),
     Return,
+    DebugBreak,
     Load(Writable, Value),
+    /// `lea`, the value is the address computed without reading memory
+    LoadAddress(Writable, Value),
+    /// `getpc`, the value is the address of the instruction itself
+    GetPc(Writable, Value),
     Push(Value),
     Pop(Writable),
+    PushMulti(Value),
+    PopMulti(Value),
+    Enter(Value),
+    Leave,
+    TakeR(Writable),
+    Repeat(Writable, Value, Value),
+    FetchAdd(Writable, Value, Value),
+    /// Address and length of the block
+    Spill(Value, Value),
+    Reload(Value, Value),
+    Swap(Writable, Writable, Value, Value),
     Add(Writable, Value, Value, Type),
     Sub(Writable, Value, Value, Type),
     Mul(Writable, Value, Value, Type),
     DivMod(Writable, Value, Value, Type, bool),
     MaxMin(Writable, Value, Value, Type, bool),
+    AbsDiff(Writable, Value, Value, Type),
+    IncDecSaturating(Writable, Value, bool),
+    ISqrt(Writable, Value),
     AbsSign(Writable, Value, Type, bool),
     And(Writable, Value, Value),
     Or(Writable, Value, Value),
@@ -89,6 +236,8 @@ pub enum Instruction {
     Clear(Writable, Value, Value),
     Toggle(Writable, Value, Value),
     Test(Value, Value),
+    ByteSwap(Writable, Value),
+    BitReverse(Writable, Value),
     Sin(Writable, Value),
     Cos(Writable, Value),
     Tan(Writable, Value),
@@ -98,6 +247,11 @@ pub enum Instruction {
     ArcTan2(Writable, Value, Value),
     Sqrt(Writable, Value),
     Pow(Writable, Value, Value),
+    Fma(Writable, Value, Value),
+    Floor(Writable, Value),
+    Ceil(Writable, Value),
+    Round(Writable, Value),
+    Trunc(Writable, Value),
     Exp(Writable, Value),
     Log(Writable, Value),
     Log2(Writable, Value),
@@ -106,53 +260,215 @@ pub enum Instruction {
     RSeed(Value),
     ItoF(Writable, Value),
     FtoI(Writable, Value),
+    FloatToFixed(Writable, Value, Value),
+    FixedToFloat(Writable, Value, Value),
     UtoF(Writable, Value),
     FtoU(Writable, Value),
+    Order(Writable, Value, Value, Type),
+    CompareInto(Writable, Value, Value, Type),
+}
+
+/// Renders the instruction with its resolved operands, e.g. `add a 5` or `jeq 0x400010`.
+/// Operands which were read from memory or registers show the value that was read.
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let int = |val: &Value| val.val.to_string();
+        let signed = |val: &Value| (val.val as i32).to_string();
+        let float = |val: &Value| format!("{:?}", f32::from_bits(val.val));
+        let typed = |val: &Value, kind: &Type| match kind {
+            Type::Signed => signed(val),
+            Type::Unsigned => int(val),
+            Type::Float => float(val),
+        };
+        let prefix = |kind: &Type| match kind {
+            Type::Signed => "",
+            Type::Unsigned => "u",
+            Type::Float => "f",
+        };
+        match self {
+            Instruction::Halt => write!(f, "hlt"),
+            Instruction::HaltIf(comp) => {
+                let name = match comp {
+                    Comparison::None => "hlt",
+                    Comparison::Equal => "hlteq",
+                    Comparison::NotEqual => "hltne",
+                    Comparison::Greater => "hltgt",
+                    Comparison::GreaterEqual => "hltge",
+                    Comparison::Less => "hltlt",
+                    Comparison::LessEqual => "hltle",
+                };
+                write!(f, "{name}")
+            }
+            Instruction::Nop => write!(f, "nop"),
+            Instruction::Jump(target, comp) => {
+                let name = match comp {
+                    Comparison::None => "jmp",
+                    Comparison::Equal => "jeq",
+                    Comparison::NotEqual => "jne",
+                    Comparison::Greater => "jgt",
+                    Comparison::GreaterEqual => "jge",
+                    Comparison::Less => "jlt",
+                    Comparison::LessEqual => "jle",
+                };
+                write!(f, "{name} {:#x}", target.val)
+            }
+            Instruction::Repeat(to, _, target) => write!(f, "rep {to} {:#x}", target.val),
+            Instruction::CompareSigned(val, val2) => {
+                write!(f, "cmp {} {}", signed(val), signed(val2))
+            }
+            Instruction::CompareFloat(val, val2) => {
+                write!(f, "fcmp {} {}", float(val), float(val2))
+            }
+            Instruction::CompareUnsigned(val, val2) => write!(f, "ucmp {} {}", int(val), int(val2)),
+            Instruction::Call(target) => write!(f, "call {:#x}", target.val),
+            Instruction::SysCall(val) => write!(f, "sys {:#x}", val.val),
+            Instruction::Return => write!(f, "ret"),
+            Instruction::DebugBreak => write!(f, "brk"),
+            Instruction::Load(to, val) => write!(f, "ld {to} {}", int(val)),
+            Instruction::LoadAddress(to, addr) => write!(f, "lea {to} {:#x}", addr.val),
+            Instruction::GetPc(to, _) => write!(f, "getpc {to}"),
+            Instruction::Push(val) => write!(f, "push {}", int(val)),
+            Instruction::Pop(to) => write!(f, "pop {to}"),
+            Instruction::PushMulti(mask) => write!(f, "pushm {:#b}", mask.val),
+            Instruction::PopMulti(mask) => write!(f, "popm {:#b}", mask.val),
+            Instruction::Enter(size) => write!(f, "enter {}", size.val),
+            Instruction::Leave => write!(f, "leave"),
+            Instruction::TakeR(to) => write!(f, "taker {to}"),
+            Instruction::FetchAdd(to, _, val) => write!(f, "xadd {to} {}", int(val)),
+            Instruction::Spill(addr, len) => write!(f, "spill #{:06x} {}", addr.val, len.val),
+            Instruction::Reload(addr, len) => write!(f, "reload #{:06x} {}", addr.val, len.val),
+            Instruction::Swap(to, to2, _, _) => write!(f, "swp {to} {to2}"),
+            Instruction::Add(to, _, val, kind) => {
+                write!(f, "{}add {to} {}", prefix(kind), typed(val, kind))
+            }
+            Instruction::Sub(to, _, val, kind) => {
+                write!(f, "{}sub {to} {}", prefix(kind), typed(val, kind))
+            }
+            Instruction::Mul(to, _, val, kind) => {
+                write!(f, "{}mul {to} {}", prefix(kind), typed(val, kind))
+            }
+            Instruction::DivMod(to, _, val, kind, is_div) => {
+                let name = if *is_div { "div" } else { "mod" };
+                write!(f, "{}{name} {to} {}", prefix(kind), typed(val, kind))
+            }
+            Instruction::MaxMin(to, _, val, kind, is_max) => {
+                let name = if *is_max { "max" } else { "min" };
+                write!(f, "{}{name} {to} {}", prefix(kind), typed(val, kind))
+            }
+            Instruction::IncDecSaturating(to, _, is_inc) => {
+                let name = if *is_inc { "incs" } else { "decs" };
+                write!(f, "{name} {to}")
+            }
+            Instruction::ISqrt(to, _) => write!(f, "isqrt {to}"),
+            Instruction::AbsDiff(to, _, val, kind) => {
+                write!(f, "{}adiff {to} {}", prefix(kind), typed(val, kind))
+            }
+            Instruction::AbsSign(to, _, kind, is_abs) => {
+                let name = if *is_abs { "abs" } else { "sign" };
+                write!(f, "{}{name} {to}", prefix(kind))
+            }
+            Instruction::And(to, _, val) => write!(f, "and {to} {}", int(val)),
+            Instruction::Or(to, _, val) => write!(f, "or {to} {}", int(val)),
+            Instruction::Not(to, _) => write!(f, "not {to}"),
+            Instruction::Nand(to, _, val) => write!(f, "nand {to} {}", int(val)),
+            Instruction::Xor(to, _, val) => write!(f, "xor {to} {}", int(val)),
+            Instruction::ShiftLogicalRight(to, _, val) => write!(f, "slr {to} {}", int(val)),
+            Instruction::ShiftArithRight(to, _, val) => write!(f, "sar {to} {}", int(val)),
+            Instruction::ShiftLogicLeft(to, _, val) => write!(f, "sll {to} {}", int(val)),
+            Instruction::RotateRight(to, _, val) => write!(f, "ror {to} {}", int(val)),
+            Instruction::RotateLeft(to, _, val) => write!(f, "rol {to} {}", int(val)),
+            Instruction::Set(to, _, val) => write!(f, "set {to} {}", int(val)),
+            Instruction::Clear(to, _, val) => write!(f, "clr {to} {}", int(val)),
+            Instruction::Toggle(to, _, val) => write!(f, "tgl {to} {}", int(val)),
+            Instruction::Test(val, val2) => write!(f, "test {} {}", int(val), int(val2)),
+            Instruction::ByteSwap(to, _) => write!(f, "bswap {to}"),
+            Instruction::BitReverse(to, _) => write!(f, "brev {to}"),
+            Instruction::Sin(to, _) => write!(f, "sin {to}"),
+            Instruction::Cos(to, _) => write!(f, "cos {to}"),
+            Instruction::Tan(to, _) => write!(f, "tan {to}"),
+            Instruction::ArcSin(to, _) => write!(f, "asin {to}"),
+            Instruction::ArcCos(to, _) => write!(f, "acos {to}"),
+            Instruction::ArcTan(to, _) => write!(f, "atan {to}"),
+            Instruction::ArcTan2(to, _, val) => write!(f, "atan2 {to} {}", float(val)),
+            Instruction::Sqrt(to, _) => write!(f, "sqrt {to}"),
+            Instruction::Pow(to, _, val) => write!(f, "pow {to} {}", float(val)),
+            Instruction::Fma(to, _, val) => write!(f, "fma {to} {}", float(val)),
+            Instruction::Floor(to, _) => write!(f, "floor {to}"),
+            Instruction::Ceil(to, _) => write!(f, "ceil {to}"),
+            Instruction::Round(to, _) => write!(f, "round {to}"),
+            Instruction::Trunc(to, _) => write!(f, "trunc {to}"),
+            Instruction::Exp(to, _) => write!(f, "exp {to}"),
+            Instruction::Log(to, _) => write!(f, "log {to}"),
+            Instruction::Log2(to, _) => write!(f, "log2 {to}"),
+            Instruction::Log10(to, _) => write!(f, "log10 {to}"),
+            Instruction::Rand(to) => write!(f, "rand {to}"),
+            Instruction::RSeed(val) => write!(f, "rseed {}", int(val)),
+            Instruction::ItoF(to, _) => write!(f, "itf {to}"),
+            Instruction::FtoI(to, _) => write!(f, "fti {to}"),
+            Instruction::FloatToFixed(to, _, frac) => write!(f, "ftx {to} {}", frac.val),
+            Instruction::FixedToFloat(to, _, frac) => write!(f, "xtf {to} {}", frac.val),
+            Instruction::UtoF(to, _) => write!(f, "utf {to}"),
+            Instruction::FtoU(to, _) => write!(f, "ftu {to}"),
+            Instruction::Order(to, _, val, kind) => {
+                write!(f, "{}ocmp {to} {}", prefix(kind), typed(val, kind))
+            }
+            Instruction::CompareInto(to, _, val, kind) => {
+                write!(f, "{}cmpto {to} {}", prefix(kind), typed(val, kind))
+            }
+        }
+    }
 }
 
 impl Instruction {
     fn no_params(op: OpCode) -> Self {
         match op {
             OpCode::Halt => Instruction::Halt,
-            OpCode::Nop => Instruction::Nop,
+            OpCode::HaltEqual => Instruction::HaltIf(Comparison::Equal),
+            OpCode::HaltNotEqual => Instruction::HaltIf(Comparison::NotEqual),
+            OpCode::HaltGreaterThan => Instruction::HaltIf(Comparison::Greater),
+            OpCode::HaltGreaterEqual => Instruction::HaltIf(Comparison::GreaterEqual),
+            OpCode::HaltLessThan => Instruction::HaltIf(Comparison::Less),
+            OpCode::HaltLessEqual => Instruction::HaltIf(Comparison::LessEqual),
+            OpCode::Nop | OpCode::ExtendedNop => Instruction::Nop,
             OpCode::Return => Instruction::Return,
+            OpCode::DebugBreak => Instruction::DebugBreak,
+            OpCode::Leave => Instruction::Leave,
             _ => unreachable!("No operation could be found for the no parameter OpCode"),
         }
     }
-    fn with_params(
+    fn with_params<M: ReadMemory + ?Sized>(
         op_code: OpCode,
         first_par: Parameter,
         sec_par: Parameter,
         cpu: &Cpu,
-        mem: &Memory,
+        mem: &M,
     ) -> Result<Self, Tx8Error> {
         Ok(match op_code {
-            OpCode::Jump => Instruction::Jump(
-                Value::from_par(first_par, cpu, mem, Byte)?,
-                Comparison::None,
-            ),
+            // targets in memory are full addresses, constants of every size are zero-extended
+            OpCode::Jump => {
+                Instruction::Jump(Value::from_par(first_par, cpu, mem, Int)?, Comparison::None)
+            }
             OpCode::JumpEqual => Instruction::Jump(
-                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
                 Comparison::Equal,
             ),
             OpCode::JumpNotEqual => Instruction::Jump(
-                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
                 Comparison::NotEqual,
             ),
             OpCode::JumpGreaterThan => Instruction::Jump(
-                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
                 Comparison::Greater,
             ),
             OpCode::JumpGreaterEqual => Instruction::Jump(
-                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
                 Comparison::GreaterEqual,
             ),
-            OpCode::JumpLessThan => Instruction::Jump(
-                Value::from_par(first_par, cpu, mem, Byte)?,
-                Comparison::Less,
-            ),
+            OpCode::JumpLessThan => {
+                Instruction::Jump(Value::from_par(first_par, cpu, mem, Int)?, Comparison::Less)
+            }
             OpCode::JumpLessEqual => Instruction::Jump(
-                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
                 Comparison::LessEqual,
             ),
             OpCode::CompareSigned => Instruction::CompareSigned(
@@ -167,11 +483,25 @@ impl Instruction {
                 Value::from_par(first_par, cpu, mem, Byte)?,
                 Value::from_par(sec_par, cpu, mem, Byte)?,
             ),
-            OpCode::Call => Instruction::Call(Value::from_par(first_par, cpu, mem, Byte)?),
+            OpCode::Call => Instruction::Call(Value::from_par(first_par, cpu, mem, Int)?),
+            OpCode::Repeat => Instruction::Repeat(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Int)?,
+            ),
             OpCode::SysCall => Instruction::SysCall(Value::from_par(first_par, cpu, mem, Byte)?),
             OpCode::Halt => unreachable!(),
+            OpCode::HaltEqual => unreachable!(),
+            OpCode::HaltNotEqual => unreachable!(),
+            OpCode::HaltGreaterThan => unreachable!(),
+            OpCode::HaltGreaterEqual => unreachable!(),
+            OpCode::HaltLessThan => unreachable!(),
+            OpCode::HaltLessEqual => unreachable!(),
             OpCode::Nop => unreachable!(),
+            OpCode::ExtendedNop => unreachable!(),
             OpCode::Return => unreachable!(),
+            OpCode::DebugBreak => unreachable!(),
+            OpCode::Leave => unreachable!(),
             OpCode::Load => Instruction::Load(
                 Writable::from_par(first_par)?,
                 Value::from_par(sec_par, cpu, mem, Byte)?,
@@ -182,28 +512,28 @@ impl Instruction {
                 Value::from_par_signed(sec_par, cpu, mem, Byte)?,
             ),
             OpCode::LoadA => Instruction::Load(
-                Writable::Register(Register(0x00)),
+                Writable::from_par(Parameter::Register(0x00))?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
             ),
             OpCode::StoreA => {
                 Instruction::Load(Writable::from_par(first_par)?, Value::new(cpu.a, Int))
             }
             OpCode::LoadB => Instruction::Load(
-                Writable::Register(Register(0x01)),
+                Writable::from_par(Parameter::Register(0x01))?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
             ),
             OpCode::StoreB => {
                 Instruction::Load(Writable::from_par(first_par)?, Value::new(cpu.b, Int))
             }
             OpCode::LoadC => Instruction::Load(
-                Writable::Register(Register(0x02)),
+                Writable::from_par(Parameter::Register(0x02))?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
             ),
             OpCode::StoreC => {
                 Instruction::Load(Writable::from_par(first_par)?, Value::new(cpu.c, Int))
             }
             OpCode::LoadD => Instruction::Load(
-                Writable::Register(Register(0x03)),
+                Writable::from_par(Parameter::Register(0x03))?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
             ),
             OpCode::StoreD => {
@@ -211,13 +541,20 @@ impl Instruction {
             }
             OpCode::Zero => Instruction::Load(Writable::from_par(first_par)?, Value::new(0, Int)),
             OpCode::Pop => Instruction::Pop(Writable::from_par(first_par)?),
-            OpCode::LoadWord => Instruction::Load(
+            OpCode::Swap => Instruction::Swap(
                 Writable::from_par(first_par)?,
+                Writable::from_par(sec_par)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
                 Value::from_par(sec_par, cpu, mem, Int)?,
             ),
+            // the extended value is stored as a whole word, also for smaller constants and registers
+            OpCode::LoadWord => Instruction::Load(
+                Writable::from_par(first_par)?,
+                Value::new(Value::from_par(sec_par, cpu, mem, Int)?.val, Int),
+            ),
             OpCode::LoadWordSigned => Instruction::Load(
                 Writable::from_par(first_par)?,
-                Value::from_par_signed(sec_par, cpu, mem, Int)?,
+                Value::new(Value::from_par_signed(sec_par, cpu, mem, Int)?.val, Int),
             ),
             OpCode::Inc => Instruction::Add(
                 Writable::from_par(first_par)?,
@@ -231,6 +568,20 @@ impl Instruction {
                 Value::new(1, Int),
                 Type::Unsigned,
             ),
+            OpCode::IncSaturating => Instruction::IncDecSaturating(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                true,
+            ),
+            OpCode::DecSaturating => Instruction::IncDecSaturating(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                false,
+            ),
+            OpCode::ISqrt => Instruction::ISqrt(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+            ),
             OpCode::Add => Instruction::Add(
                 Writable::from_par(first_par)?,
                 Value::from_par_signed(first_par, cpu, mem, Byte)?,
@@ -321,6 +672,24 @@ impl Instruction {
                 Type::Unsigned,
                 false,
             ),
+            OpCode::AbsDiffSigned => Instruction::AbsDiff(
+                Writable::from_par(first_par)?,
+                Value::from_par_signed(first_par, cpu, mem, Byte)?,
+                Value::from_par_signed(sec_par, cpu, mem, Byte)?,
+                Type::Signed,
+            ),
+            OpCode::AbsDiffUnsigned => Instruction::AbsDiff(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+                Type::Unsigned,
+            ),
+            OpCode::AbsDiffFloat => Instruction::AbsDiff(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+                Type::Float,
+            ),
             OpCode::MaxUnsigned => Instruction::MaxMin(
                 Writable::from_par(first_par)?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
@@ -473,6 +842,14 @@ impl Instruction {
                 Value::from_par(first_par, cpu, mem, Byte)?,
                 Value::from_par(sec_par, cpu, mem, Byte)?,
             ),
+            OpCode::ByteSwap => Instruction::ByteSwap(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
+            ),
+            OpCode::BitReverse => Instruction::BitReverse(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Int)?,
+            ),
             OpCode::Sin => Instruction::Sin(
                 Writable::from_par(first_par)?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
@@ -511,6 +888,27 @@ impl Instruction {
                 Value::from_par(first_par, cpu, mem, Byte)?,
                 Value::from_par(sec_par, cpu, mem, Byte)?,
             ),
+            OpCode::Fma => Instruction::Fma(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+            ),
+            OpCode::Floor => Instruction::Floor(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+            ),
+            OpCode::Ceil => Instruction::Ceil(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+            ),
+            OpCode::Round => Instruction::Round(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+            ),
+            OpCode::Trunc => Instruction::Trunc(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+            ),
             OpCode::Exp => Instruction::Exp(
                 Writable::from_par(first_par)?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
@@ -537,6 +935,17 @@ impl Instruction {
                 Writable::from_par(first_par)?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
             ),
+            // the float is always in the whole register, the fixed-point number in the view
+            OpCode::FloatToFixed => Instruction::FloatToFixed(
+                Writable::from_par(first_par)?,
+                Value::from_par(whole_register(first_par), cpu, mem, Int)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+            ),
+            OpCode::FixedToFloat => Instruction::FixedToFloat(
+                Writable::from_par(whole_register(first_par))?,
+                Value::from_par_signed(first_par, cpu, mem, Int)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+            ),
             OpCode::UtoF => Instruction::UtoF(
                 Writable::from_par(first_par)?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
@@ -545,209 +954,484 @@ impl Instruction {
                 Writable::from_par(first_par)?,
                 Value::from_par(first_par, cpu, mem, Byte)?,
             ),
+            OpCode::LoadAddress => Instruction::LoadAddress(
+                Writable::from_par(first_par)?,
+                Value::new(effective_address(sec_par, cpu, mem)?, Int),
+            ),
+            OpCode::GetPc => {
+                Instruction::GetPc(Writable::from_par(first_par)?, Value::new(cpu.p, Int))
+            }
+            OpCode::PushMulti => {
+                Instruction::PushMulti(Value::from_par(first_par, cpu, mem, Byte)?)
+            }
+            OpCode::PopMulti => Instruction::PopMulti(Value::from_par(first_par, cpu, mem, Byte)?),
+            OpCode::Enter => Instruction::Enter(Value::from_par(first_par, cpu, mem, Int)?),
+            OpCode::TakeR => Instruction::TakeR(Writable::from_par(first_par)?),
+            OpCode::FetchAdd => {
+                // like `ld`, memory targets take as many bytes as a constant or register has
+                let val = Value::from_par(sec_par, cpu, mem, Int)?;
+                Instruction::FetchAdd(
+                    Writable::from_par(first_par)?,
+                    Value::from_par(first_par, cpu, mem, val.size)?,
+                    val,
+                )
+            }
+            OpCode::Spill => Instruction::Spill(
+                Value::new(effective_address(first_par, cpu, mem)?, Int),
+                Value::from_par(sec_par, cpu, mem, Int)?,
+            ),
+            OpCode::Reload => Instruction::Reload(
+                Value::new(effective_address(first_par, cpu, mem)?, Int),
+                Value::from_par(sec_par, cpu, mem, Int)?,
+            ),
+            OpCode::OrderSigned => Instruction::Order(
+                Writable::from_par(first_par)?,
+                Value::from_par_signed(first_par, cpu, mem, Byte)?,
+                Value::from_par_signed(sec_par, cpu, mem, Byte)?,
+                Type::Signed,
+            ),
+            OpCode::OrderFloat => Instruction::Order(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+                Type::Float,
+            ),
+            OpCode::OrderUnsigned => Instruction::Order(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+                Type::Unsigned,
+            ),
+            OpCode::CompareSignedInto => Instruction::CompareInto(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+                Type::Signed,
+            ),
+            OpCode::CompareFloatInto => Instruction::CompareInto(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+                Type::Float,
+            ),
+            OpCode::CompareUnsignedInto => Instruction::CompareInto(
+                Writable::from_par(first_par)?,
+                Value::from_par(first_par, cpu, mem, Byte)?,
+                Value::from_par(sec_par, cpu, mem, Byte)?,
+                Type::Unsigned,
+            ),
         })
     }
 
     pub fn increase_program_counter(&self) -> bool {
-        match self {
-            Instruction::Halt => false,
-            Instruction::Jump(_, _) => false,
-            Instruction::Call(_) => false,
-            Instruction::Return => false,
-            _ => true,
-        }
+        !matches!(
+            self,
+            Instruction::Halt
+                | Instruction::HaltIf(_)
+                | Instruction::Jump(_, _)
+                | Instruction::Repeat(_, _, _)
+                | Instruction::Call(_)
+                | Instruction::Return
+        )
     }
 }
-fn parse_op_code(byte: u8) -> Result<OpCode, Tx8Error> {
-    Ok(match byte {
-        0x00 => OpCode::Halt,
-        0x01 => OpCode::Nop,
-        0x02 => OpCode::Jump,
-        0x03 => OpCode::JumpEqual,
-        0x04 => OpCode::JumpNotEqual,
-        0x05 => OpCode::JumpGreaterThan,
-        0x06 => OpCode::JumpGreaterEqual,
-        0x07 => OpCode::JumpLessThan,
-        0x08 => OpCode::JumpLessEqual,
-        0x09 => OpCode::CompareSigned,
-        0x0a => OpCode::CompareFloat,
-        0x0b => OpCode::CompareUnsigned,
-        0x0c => OpCode::Call,
-        0x0d => OpCode::Return,
-        0x0e => OpCode::SysCall,
-        0x10 => OpCode::Load,
-        0x11 => OpCode::LoadSigned,
-        0x12 => OpCode::LoadWord,
-        0x13 => OpCode::LoadWordSigned,
-        0x14 => OpCode::LoadA,
-        0x15 => OpCode::StoreA,
-        0x16 => OpCode::LoadB,
-        0x17 => OpCode::StoreB,
-        0x18 => OpCode::LoadC,
-        0x19 => OpCode::StoreC,
-        0x1a => OpCode::LoadD,
-        0x1b => OpCode::StoreD,
-        0x1c => OpCode::Zero,
-        0x1d => OpCode::Push,
-        0x1e => OpCode::Pop,
-        0x20 => OpCode::Inc,
-        0x21 => OpCode::Dec,
-        0x22 => OpCode::Add,
-        0x23 => OpCode::Sub,
-        0x24 => OpCode::Mul,
-        0x25 => OpCode::Div,
-        0x26 => OpCode::Mod,
-        0x27 => OpCode::Max,
-        0x28 => OpCode::Min,
-        0x29 => OpCode::Abs,
-        0x2a => OpCode::Sign,
-        0x30 => OpCode::And,
-        0x31 => OpCode::Or,
-        0x32 => OpCode::Not,
-        0x33 => OpCode::Nand,
-        0x34 => OpCode::Xor,
-        0x35 => OpCode::ShiftLogicalRight,
-        0x36 => OpCode::ShiftArithRight,
-        0x37 => OpCode::ShiftLogicLeft,
-        0x38 => OpCode::RotateRight,
-        0x39 => OpCode::RotateLeft,
-        0x3a => OpCode::Set,
-        0x3b => OpCode::Clear,
-        0x3c => OpCode::Toggle,
-        0x3d => OpCode::Test,
-        0x40 => OpCode::IncFloat,
-        0x41 => OpCode::DecFloat,
-        0x42 => OpCode::AddFloat,
-        0x43 => OpCode::SubFloat,
-        0x44 => OpCode::MulFloat,
-        0x45 => OpCode::DivFloat,
-        0x46 => OpCode::ModFloat,
-        0x47 => OpCode::MaxFloat,
-        0x48 => OpCode::MinFloat,
-        0x49 => OpCode::AbsFloat,
-        0x4a => OpCode::SignFloat,
-        0x4b => OpCode::Sin,
-        0x4c => OpCode::Cos,
-        0x4d => OpCode::Tan,
-        0x4e => OpCode::ArcSin,
-        0x4f => OpCode::ArcCos,
-        0x50 => OpCode::ArcTan,
-        0x51 => OpCode::ArcTan2,
-        0x52 => OpCode::Sqrt,
-        0x53 => OpCode::Pow,
-        0x54 => OpCode::Exp,
-        0x55 => OpCode::Log,
-        0x56 => OpCode::Log2,
-        0x57 => OpCode::Log10,
-        0x60 => OpCode::AddUnsigned,
-        0x61 => OpCode::SubUnsigned,
-        0x62 => OpCode::MulUnsigned,
-        0x63 => OpCode::DivUnsigned,
-        0x64 => OpCode::ModUnsigned,
-        0x65 => OpCode::MaxUnsigned,
-        0x66 => OpCode::MinUnsigned,
-        0x70 => OpCode::Rand,
-        0x71 => OpCode::RSeed,
-        0x72 => OpCode::ItoF,
-        0x73 => OpCode::FtoI,
-        0x74 => OpCode::UtoF,
-        0x75 => OpCode::FtoU,
-        _ => return Err(Tx8Error::InvalidOpCode(byte)),
-    })
+
+/// Defines `OpCode`, its decoding and the `OP_CODES` and `EXTENDED_OP_CODES` metadata tables
+/// from a single list. The opcodes after `extended:` follow the extension prefix.
+macro_rules! op_codes {
+    (
+        $($byte:literal => $name:ident, $mnemonic:literal, $operands:literal, $kind:ident,
+            $category:ident;)*
+        extended:
+        $($ext_byte:literal => $ext_name:ident, $ext_mnemonic:literal, $ext_operands:literal,
+            $ext_kind:ident, $ext_category:ident;)*
+    ) => {
+        #[derive(Clone, Copy, Debug)]
+        pub enum OpCode {
+            $($name,)*
+            $($ext_name,)*
+        }
+
+        impl OpCode {
+            pub fn mnemonic(self) -> &'static str {
+                match self {
+                    $(OpCode::$name => $mnemonic,)*
+                    $(OpCode::$ext_name => $ext_mnemonic,)*
+                }
+            }
+
+            /// Returns the metadata of the opcode from `OP_CODES` or `EXTENDED_OP_CODES`
+            pub fn info(self) -> &'static OpCodeInfo {
+                // the variants are declared in the order of the tables
+                let index = self as usize;
+                match OP_CODES.get(index) {
+                    Some(info) => info,
+                    None => &EXTENDED_OP_CODES[index - OP_CODES.len()],
+                }
+            }
+        }
+
+        fn parse_op_code(byte: u8) -> Result<OpCode, Tx8Error> {
+            Ok(match byte {
+                $($byte => OpCode::$name,)*
+                _ => return Err(Tx8Error::InvalidOpCode(byte)),
+            })
+        }
+
+        /// Opcodes following the extension prefix
+        fn parse_extended_op_code(byte: u8) -> Result<OpCode, Tx8Error> {
+            Ok(match byte {
+                $($ext_byte => OpCode::$ext_name,)*
+                _ => return Err(Tx8Error::InvalidExtendedOpCode(byte)),
+            })
+        }
+
+        /// Metadata of every single byte opcode, ordered by value
+        pub const OP_CODES: &[OpCodeInfo] = &[
+            $(OpCodeInfo {
+                op_code: $byte,
+                mnemonic: $mnemonic,
+                operands: $operands,
+                kind: Type::$kind,
+                category: Category::$category,
+            },)*
+        ];
+
+        /// Metadata of every opcode of the extension table, ordered by the byte following the
+        /// extension prefix, which is their `op_code`
+        pub const EXTENDED_OP_CODES: &[OpCodeInfo] = &[
+            $(OpCodeInfo {
+                op_code: $ext_byte,
+                mnemonic: $ext_mnemonic,
+                operands: $ext_operands,
+                kind: Type::$ext_kind,
+                category: Category::$ext_category,
+            },)*
+        ];
+    };
+}
+
+op_codes! {
+    0x00 => Halt, "hlt", 0, Unsigned, ControlFlow;
+    0x01 => Nop, "nop", 0, Unsigned, Other;
+    0x02 => Jump, "jmp", 1, Unsigned, ControlFlow;
+    0x03 => JumpEqual, "jeq", 1, Unsigned, ControlFlow;
+    0x04 => JumpNotEqual, "jne", 1, Unsigned, ControlFlow;
+    0x05 => JumpGreaterThan, "jgt", 1, Unsigned, ControlFlow;
+    0x06 => JumpGreaterEqual, "jge", 1, Unsigned, ControlFlow;
+    0x07 => JumpLessThan, "jlt", 1, Unsigned, ControlFlow;
+    0x08 => JumpLessEqual, "jle", 1, Unsigned, ControlFlow;
+    0x09 => CompareSigned, "cmp", 2, Signed, Other;
+    0x0a => CompareFloat, "fcmp", 2, Float, Float;
+    0x0b => CompareUnsigned, "ucmp", 2, Unsigned, Other;
+    0x0c => Call, "call", 1, Unsigned, ControlFlow;
+    0x0d => Return, "ret", 0, Unsigned, ControlFlow;
+    0x0e => SysCall, "sys", 1, Unsigned, SysCall;
+    0x0f => DebugBreak, "brk", 0, Unsigned, Other;
+    0x10 => Load, "ld", 2, Unsigned, Other;
+    0x11 => LoadSigned, "lds", 2, Signed, Other;
+    0x12 => LoadWord, "lw", 2, Unsigned, Other;
+    0x13 => LoadWordSigned, "lws", 2, Signed, Other;
+    0x14 => LoadA, "lda", 1, Unsigned, Other;
+    0x15 => StoreA, "sta", 1, Unsigned, Other;
+    0x16 => LoadB, "ldb", 1, Unsigned, Other;
+    0x17 => StoreB, "stb", 1, Unsigned, Other;
+    0x18 => LoadC, "ldc", 1, Unsigned, Other;
+    0x19 => StoreC, "stc", 1, Unsigned, Other;
+    0x1a => LoadD, "ldd", 1, Unsigned, Other;
+    0x1b => StoreD, "std", 1, Unsigned, Other;
+    0x1c => Zero, "zero", 1, Unsigned, Other;
+    0x1d => Push, "push", 1, Unsigned, Other;
+    0x1e => Pop, "pop", 1, Unsigned, Other;
+    0x1f => Swap, "swp", 2, Unsigned, Other;
+    0x20 => Inc, "inc", 1, Unsigned, Arithmetic;
+    0x21 => Dec, "dec", 1, Unsigned, Arithmetic;
+    0x22 => Add, "add", 2, Signed, Arithmetic;
+    0x23 => Sub, "sub", 2, Signed, Arithmetic;
+    0x24 => Mul, "mul", 2, Signed, Arithmetic;
+    0x25 => Div, "div", 2, Signed, Arithmetic;
+    0x26 => Mod, "mod", 2, Signed, Arithmetic;
+    0x27 => Max, "max", 2, Signed, Arithmetic;
+    0x28 => Min, "min", 2, Signed, Arithmetic;
+    0x29 => Abs, "abs", 1, Signed, Arithmetic;
+    0x2a => Sign, "sign", 1, Signed, Arithmetic;
+    0x2b => AbsDiffSigned, "adiff", 2, Signed, Arithmetic;
+    0x30 => And, "and", 2, Unsigned, Arithmetic;
+    0x31 => Or, "or", 2, Unsigned, Arithmetic;
+    0x32 => Not, "not", 1, Unsigned, Arithmetic;
+    0x33 => Nand, "nand", 2, Unsigned, Arithmetic;
+    0x34 => Xor, "xor", 2, Unsigned, Arithmetic;
+    0x35 => ShiftLogicalRight, "slr", 2, Unsigned, Arithmetic;
+    0x36 => ShiftArithRight, "sar", 2, Unsigned, Arithmetic;
+    0x37 => ShiftLogicLeft, "sll", 2, Unsigned, Arithmetic;
+    0x38 => RotateRight, "ror", 2, Unsigned, Arithmetic;
+    0x39 => RotateLeft, "rol", 2, Unsigned, Arithmetic;
+    0x3a => Set, "set", 2, Unsigned, Arithmetic;
+    0x3b => Clear, "clr", 2, Unsigned, Arithmetic;
+    0x3c => Toggle, "tgl", 2, Unsigned, Arithmetic;
+    0x3d => Test, "test", 2, Unsigned, Arithmetic;
+    0x3e => ByteSwap, "bswap", 1, Unsigned, Arithmetic;
+    0x3f => BitReverse, "brev", 1, Unsigned, Arithmetic;
+    0x40 => IncFloat, "finc", 1, Float, Float;
+    0x41 => DecFloat, "fdec", 1, Float, Float;
+    0x42 => AddFloat, "fadd", 2, Float, Float;
+    0x43 => SubFloat, "fsub", 2, Float, Float;
+    0x44 => MulFloat, "fmul", 2, Float, Float;
+    0x45 => DivFloat, "fdiv", 2, Float, Float;
+    0x46 => ModFloat, "fmod", 2, Float, Float;
+    0x47 => MaxFloat, "fmax", 2, Float, Float;
+    0x48 => MinFloat, "fmin", 2, Float, Float;
+    0x49 => AbsFloat, "fabs", 1, Float, Float;
+    0x4a => SignFloat, "fsign", 1, Float, Float;
+    0x4b => Sin, "sin", 1, Float, Float;
+    0x4c => Cos, "cos", 1, Float, Float;
+    0x4d => Tan, "tan", 1, Float, Float;
+    0x4e => ArcSin, "asin", 1, Float, Float;
+    0x4f => ArcCos, "acos", 1, Float, Float;
+    0x50 => ArcTan, "atan", 1, Float, Float;
+    0x51 => ArcTan2, "atan2", 2, Float, Float;
+    0x52 => Sqrt, "sqrt", 1, Float, Float;
+    0x53 => Pow, "pow", 2, Float, Float;
+    0x54 => Exp, "exp", 1, Float, Float;
+    0x55 => Log, "log", 1, Float, Float;
+    0x56 => Log2, "log2", 1, Float, Float;
+    0x57 => Log10, "log10", 1, Float, Float;
+    0x58 => Fma, "fma", 2, Float, Float;
+    0x59 => Floor, "floor", 1, Float, Float;
+    0x5a => Ceil, "ceil", 1, Float, Float;
+    0x5b => Round, "round", 1, Float, Float;
+    0x5c => Trunc, "trunc", 1, Float, Float;
+    0x5d => AbsDiffFloat, "fadiff", 2, Float, Float;
+    0x60 => AddUnsigned, "uadd", 2, Unsigned, Arithmetic;
+    0x61 => SubUnsigned, "usub", 2, Unsigned, Arithmetic;
+    0x62 => MulUnsigned, "umul", 2, Unsigned, Arithmetic;
+    0x63 => DivUnsigned, "udiv", 2, Unsigned, Arithmetic;
+    0x64 => ModUnsigned, "umod", 2, Unsigned, Arithmetic;
+    0x65 => MaxUnsigned, "umax", 2, Unsigned, Arithmetic;
+    0x66 => MinUnsigned, "umin", 2, Unsigned, Arithmetic;
+    0x67 => AbsDiffUnsigned, "uadiff", 2, Unsigned, Arithmetic;
+    0x68 => IncSaturating, "incs", 1, Unsigned, Arithmetic;
+    0x69 => DecSaturating, "decs", 1, Unsigned, Arithmetic;
+    0x6a => ISqrt, "isqrt", 1, Unsigned, Arithmetic;
+    0x70 => Rand, "rand", 1, Unsigned, Other;
+    0x71 => RSeed, "rseed", 1, Unsigned, Other;
+    0x72 => ItoF, "itf", 1, Signed, Float;
+    0x73 => FtoI, "fti", 1, Float, Float;
+    0x74 => UtoF, "utf", 1, Unsigned, Float;
+    0x75 => FtoU, "ftu", 1, Float, Float;
+    0x79 => FloatToFixed, "ftx", 2, Float, Float;
+    0x7a => FixedToFloat, "xtf", 2, Signed, Float;
+    0x80 => OrderSigned, "ocmp", 2, Signed, Other;
+    0x81 => OrderFloat, "focmp", 2, Float, Float;
+    0x82 => OrderUnsigned, "uocmp", 2, Unsigned, Other;
+    0x83 => CompareSignedInto, "cmpto", 2, Signed, Other;
+    0x84 => CompareFloatInto, "fcmpto", 2, Float, Float;
+    0x85 => CompareUnsignedInto, "ucmpto", 2, Unsigned, Other;
+    0x90 => LoadAddress, "lea", 2, Unsigned, Other;
+    0x91 => PushMulti, "pushm", 1, Unsigned, Other;
+    0x92 => PopMulti, "popm", 1, Unsigned, Other;
+    0x93 => GetPc, "getpc", 1, Unsigned, Other;
+    0x94 => Enter, "enter", 1, Unsigned, Other;
+    0x95 => Leave, "leave", 0, Unsigned, Other;
+    0x96 => Spill, "spill", 2, Unsigned, Other;
+    0x97 => Reload, "reload", 2, Unsigned, Other;
+    0x98 => HaltEqual, "hlteq", 0, Unsigned, ControlFlow;
+    0x99 => HaltNotEqual, "hltne", 0, Unsigned, ControlFlow;
+    0x9a => HaltGreaterThan, "hltgt", 0, Unsigned, ControlFlow;
+    0x9b => HaltGreaterEqual, "hltge", 0, Unsigned, ControlFlow;
+    0x9c => HaltLessThan, "hltlt", 0, Unsigned, ControlFlow;
+    0x9d => HaltLessEqual, "hltle", 0, Unsigned, ControlFlow;
+    0x9e => TakeR, "taker", 1, Unsigned, Other;
+    0x9f => Repeat, "rep", 2, Unsigned, ControlFlow;
+    0xa0 => FetchAdd, "xadd", 2, Unsigned, Arithmetic;
+    extended:
+    0x00 => ExtendedNop, "xnop", 0, Unsigned, Other;
 }
 
+/// Description of an opcode for tools like assemblers and disassemblers
 #[derive(Clone, Copy, Debug)]
-enum OpCode {
-    Halt,
-    Nop,
-    JumpGreaterThan,
-    JumpNotEqual,
-    JumpEqual,
-    Jump,
-    JumpGreaterEqual,
-    JumpLessThan,
-    JumpLessEqual,
-    CompareSigned,
-    CompareFloat,
-    CompareUnsigned,
-    Call,
-    Return,
-    SysCall,
-    Load,
-    LoadSigned,
-    LoadWord,
-    LoadWordSigned,
-    LoadA,
-    StoreA,
-    LoadB,
-    StoreB,
-    LoadC,
-    StoreC,
-    LoadD,
-    StoreD,
-    Zero,
-    Push,
-    Pop,
-    Inc,
-    Dec,
-    Add,
-    Sub,
-    Mul,
-    Div,
-    Mod,
-    Max,
-    Min,
-    Abs,
-    Sign,
-    And,
-    Or,
-    Not,
-    Nand,
-    Xor,
-    ShiftLogicalRight,
-    ShiftArithRight,
-    ShiftLogicLeft,
-    RotateRight,
-    RotateLeft,
-    Set,
-    Clear,
-    Toggle,
-    Test,
-    AddUnsigned,
-    SubUnsigned,
-    MulUnsigned,
-    DivUnsigned,
-    ModUnsigned,
-    MaxUnsigned,
-    MinUnsigned,
-    IncFloat,
-    DecFloat,
-    AddFloat,
-    SubFloat,
-    MulFloat,
-    DivFloat,
-    ModFloat,
-    MaxFloat,
-    MinFloat,
-    AbsFloat,
-    SignFloat,
-    Sin,
-    Cos,
-    Tan,
-    ArcSin,
-    ArcCos,
-    ArcTan,
-    ArcTan2,
-    Sqrt,
-    Pow,
-    Exp,
-    Log,
-    Log2,
-    Log10,
-    Rand,
-    RSeed,
-    ItoF,
-    FtoI,
-    UtoF,
-    FtoU,
+pub struct OpCodeInfo {
+    pub op_code: u8,
+    pub mnemonic: &'static str,
+    /// Number of parameters following the parameter mode byte
+    pub operands: u8,
+    /// How the instruction interprets its values
+    pub kind: Type,
+    pub category: Category,
+}
+
+/// Returns the metadata of a single byte opcode, `None` for reserved opcodes
+pub fn op_code_info(byte: u8) -> Option<&'static OpCodeInfo> {
+    OP_CODES.iter().find(|info| info.op_code == byte)
+}
+
+/// Returns the metadata of the opcode following the extension prefix
+pub fn extended_op_code_info(byte: u8) -> Option<&'static OpCodeInfo> {
+    EXTENDED_OP_CODES.iter().find(|info| info.op_code == byte)
+}
+
+/// Returns the metadata of the single byte opcode with the given mnemonic
+pub fn op_code_by_mnemonic(mnemonic: &str) -> Option<&'static OpCodeInfo> {
+    OP_CODES.iter().find(|info| info.mnemonic == mnemonic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::MB_4;
+
+    #[test]
+    fn op_code_table_covers_every_byte() {
+        for byte in 0..=u8::MAX {
+            let reserved = RESERVED_OP_CODES.iter().any(|range| range.contains(&byte));
+            if reserved || byte == EXTENSION_PREFIX {
+                assert!(op_code_info(byte).is_none(), "{byte:#04x} has metadata");
+                assert!(parse_op_code(byte).is_err(), "{byte:#04x} decodes");
+            } else {
+                let info = op_code_info(byte).unwrap_or_else(|| panic!("{byte:#04x} is missing"));
+                assert_eq!(info.op_code, byte);
+                assert_eq!(parse_op_code(byte).unwrap().mnemonic(), info.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn mnemonics_round_trip() {
+        for info in OP_CODES {
+            let found = op_code_by_mnemonic(info.mnemonic).unwrap();
+            assert_eq!(found.op_code, info.op_code, "{}", info.mnemonic);
+            let op_code = parse_op_code(info.op_code).unwrap();
+            assert_eq!(op_code.info().mnemonic, info.mnemonic);
+        }
+        assert!(OP_CODES
+            .windows(2)
+            .all(|pair| pair[0].op_code < pair[1].op_code));
+    }
+
+    #[test]
+    fn kinds_match_the_decoded_instructions() {
+        for info in OP_CODES {
+            // op a b, instructions with one operand ignore b
+            let mem = Memory::load_rom(&[info.op_code, 0x66, 0x00, 0x01], &[]).unwrap();
+            let Ok((instruction, _)) = parse_instruction(&Cpu::new(), &mem, MB_4 as u32) else {
+                continue;
+            };
+            let kind = match instruction {
+                Instruction::Add(_, _, _, kind)
+                | Instruction::Sub(_, _, _, kind)
+                | Instruction::Mul(_, _, _, kind)
+                | Instruction::DivMod(_, _, _, kind, _)
+                | Instruction::MaxMin(_, _, _, kind, _)
+                | Instruction::AbsDiff(_, _, _, kind)
+                | Instruction::AbsSign(_, _, kind, _)
+                | Instruction::Order(_, _, _, kind)
+                | Instruction::CompareInto(_, _, _, kind) => kind,
+                _ => continue,
+            };
+            assert_eq!(kind, info.kind, "{}", info.mnemonic);
+        }
+    }
+
+    #[test]
+    fn decodes_extended_op_codes() {
+        // xnop; nop
+        let mem = Memory::load_rom(&[EXTENSION_PREFIX, 0x00, 0x01], &[]).unwrap();
+        let raw = decode_instruction(&mem, MB_4 as u32).unwrap();
+        assert!(matches!(raw.op_code, OpCode::ExtendedNop));
+        assert_eq!(raw.len, 2);
+        let (instruction, len) = parse_instruction(&Cpu::new(), &mem, MB_4 as u32).unwrap();
+        assert!(matches!(instruction, Instruction::Nop));
+        assert_eq!(len, 2);
+        assert_eq!(
+            decode_raw_from_bytes(&[EXTENSION_PREFIX, 0x00], 0)
+                .unwrap()
+                .len,
+            2
+        );
+
+        let mem = Memory::load_rom(&[EXTENSION_PREFIX, 0x42], &[]).unwrap();
+        let error = decode_instruction(&mem, MB_4 as u32).unwrap_err();
+        assert_eq!(error, Tx8Error::InvalidExtendedOpCode(0x42));
+    }
+
+    #[test]
+    fn extended_op_code_table_matches_the_decoding() {
+        for byte in 0..=u8::MAX {
+            match extended_op_code_info(byte) {
+                Some(info) => {
+                    let op_code = parse_extended_op_code(byte).unwrap();
+                    assert_eq!(op_code.mnemonic(), info.mnemonic);
+                    assert_eq!(op_code.info().op_code, byte);
+                    assert!(op_code_by_mnemonic(info.mnemonic).is_none());
+                }
+                None => assert!(parse_extended_op_code(byte).is_err()),
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_instructions_from_bytes() {
+        // nop; ld a 5u8; add b 0x1234u16; jmp 0x400000u32; hlt
+        let code = [
+            0x01, 0x10, 0x61, 0x00, 0x05, 0x22, 0x62, 0x01, 0x34, 0x12, 0x02, 0x30, 0x00, 0x00,
+            0x40, 0x00, 0x00,
+        ];
+        let mut offset = 0;
+        let mut listing = Vec::new();
+        while offset < code.len() {
+            let (instruction, len) = decode_from_bytes(&code, offset).unwrap();
+            listing.push(instruction.to_string());
+            offset += len;
+        }
+        assert_eq!(
+            listing,
+            ["nop", "ld a 5", "add b 4660", "jmp 0x400000", "hlt"]
+        );
+    }
+
+    #[test]
+    fn decoding_from_bytes_matches_decoding_from_memory() {
+        // op a 0x01020304u32, instructions with one operand ignore the constant
+        let mut code = [0x00, 0x63, 0x00, 0x04, 0x03, 0x02, 0x01];
+        let mut mem = Memory::load_rom(&code, &[]).unwrap();
+        for byte in 0..=u8::MAX {
+            code[0] = byte;
+            mem.write_byte(MB_4 as u32, byte);
+            let from_memory = decode_instruction(&mem, MB_4 as u32);
+            let from_bytes = decode_raw_from_bytes(&code, 0);
+            match (from_memory, from_bytes) {
+                (Ok(a), Ok(b)) => assert_eq!(format!("{a:?}"), format!("{b:?}")),
+                (Err(a), Err(b)) => assert_eq!(a, b),
+                (a, b) => panic!("{byte:#04x}: {a:?} != {b:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn decoding_truncated_bytes_fails() {
+        // ld a 5u8 without the constant
+        assert_eq!(
+            decode_from_bytes(&[0x10, 0x61, 0x00], 0).unwrap_err(),
+            Tx8Error::ParseError
+        );
+        assert_eq!(
+            decode_from_bytes(&[0x01], 1).unwrap_err(),
+            Tx8Error::ParseError
+        );
+    }
+
+    #[test]
+    fn decoding_invalid_registers_fails() {
+        // ld <0x08> 5u8, ld a <0x35>, push @<0x1f>
+        for (bytes, byte) in [
+            (&[0x10, 0x61, 0x08, 0x05][..], 0x08),
+            (&[0x10, 0x66, 0x00, 0x35], 0x35),
+            (&[0x1d, 0x70, 0x1f], 0x1f),
+        ] {
+            assert_eq!(
+                decode_from_bytes(bytes, 0).unwrap_err(),
+                Tx8Error::InvalidRegister(byte)
+            );
+        }
+    }
 }
@@ -1,54 +1,310 @@
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Read},
+    str::FromStr,
+};
+
+/// Reports a status message of the interpreter, as opposed to output of the program. Messages go
+/// to the `log` crate with the `log` feature and are printed otherwise.
+macro_rules! status {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        status!(@print $level, $($arg)*);
+    }};
+    (@print warn, $($arg:tt)*) => {
+        eprintln!($($arg)*)
+    };
+    (@print $level:ident, $($arg:tt)*) => {
+        println!($($arg)*)
+    };
+}
+
+mod analysis;
+pub use analysis::{control_flow_edges, instruction_summary, EdgeKind, InstructionSummary};
+
+mod console;
+pub use console::{CONSOLE_ADDRESS, CONSOLE_SIZE};
+
+mod conformance;
+pub use conformance::{run_conformance, run_conformance_dir, Mismatch, RunResult};
+
+mod crc;
+
+#[cfg(feature = "dev")]
+mod differential;
+#[cfg(feature = "dev")]
+pub use differential::{diff_run, Divergence, StepOutcome};
+
+mod disasm;
+pub use disasm::{disassemble, disassemble_at, DisasmOptions};
+
 mod errors;
 pub use errors::Tx8Error;
 
 mod instruction;
+pub use instruction::{
+    decode_from_bytes, decode_raw_from_bytes, extended_op_code_info, op_code_by_mnemonic,
+    op_code_info, Category, Instruction, OpCode, OpCodeInfo, RawInstruction, Type,
+    EXTENDED_OP_CODES, EXTENSION_PREFIX, OP_CODES, RESERVED_OP_CODES,
+};
 
 mod hardware;
-use hardware::{Cpu, Memory};
+pub use hardware::{format_reg, AccessHook, Cpu, Memory, MmioHandler, Permissions, Register};
 
 mod execution;
-use execution::{Effect, Execution};
+pub use execution::{
+    Effect, Execution, HaltReason, RegisterCallback, StateDiff, SysCallAbi, TrapAction,
+    TrapHandler, TrapKind,
+};
 
 mod parameter;
 use parameter::*;
+pub use parameter::{register_name, Parameter};
 
 mod random;
 
-pub fn run_code(data: Vec<u8>) -> Result<(), Tx8Error> {
-    let data = parse_rom(&data)?;
-    let mut execution = Execution::new_with_rom(data)?;
-    println!("Program output:");
+#[cfg(test)]
+mod reference;
+
+mod rom;
+pub use rom::{
+    parse_header, parse_rom, parse_rom_text, parse_rom_with, DataSection, ParseOptions, Rom,
+    RomBuilder, RomHeader,
+};
+
+mod timing;
+pub use timing::CycleCosts;
+
+/// Debugging options for `run_code`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunOptions {
+    /// Error on reads of stack memory that was not written since it was allocated
+    pub poison_stack: bool,
+    /// Treat unknown system calls as no-ops instead of failing
+    pub lenient_sys_calls: bool,
+    /// Fail on integer overflow in `add`, `sub` and `mul` instead of wrapping around
+    pub trap_overflow: bool,
+    /// Store every NaN result of a float instruction as `0x7fc00000`
+    pub canonical_nan: bool,
+    /// Print the final register state as JSON after the program halted
+    #[cfg(feature = "serde")]
+    pub dump_state: bool,
+    /// Print the number of simulated cycles after the program halted
+    pub print_cycles: bool,
+    /// Stop the program after this many units of work, see `Execution::set_budget`
+    pub budget: Option<u64>,
+    /// Stop the program once it printed this many bytes, see `Execution::set_output_limit`
+    pub output_limit: Option<u64>,
+    /// Stop the program once it leaves its code, see `Execution::set_code_bounds_check`
+    pub check_code_bounds: bool,
+    /// Fail on writes into the code, see `Execution::set_code_protection`
+    pub protect_code: bool,
+    /// Where system calls read their arguments from, see `SysCallAbi`
+    pub sys_call_abi: SysCallAbi,
+    /// Fail on relative addresses outside of the memory, see `Execution::set_bounded_offsets`
+    pub bounded_offsets: bool,
+    /// Fail on accesses below this address, see `Execution::set_null_guard`
+    pub null_guard: u32,
+    /// Load the code at this address instead of the usual code address, see
+    /// `Execution::new_with_rom_at`
+    pub base: Option<u32>,
+    /// Print this many of the last instructions to stderr if the program fails
+    pub replay_log: usize,
+    /// Map the console device, see `Execution::map_console`
+    pub console: bool,
+    /// Print every instruction to stderr before executing it, colored if stderr is a terminal
+    pub trace: bool,
+    /// How the instructions are printed with `trace`
+    pub trace_format: TraceFormat,
+}
+
+/// Output format of the instruction trace of `run_code`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// The address and the disassembled instruction
+    #[default]
+    Human,
+    /// A header followed by one row per step with the step index, the program counter, the
+    /// mnemonic and the registers in decimal
+    Csv,
+    /// One JSON object per step with the same fields as `Csv`
+    JsonLines,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(TraceFormat::Human),
+            "csv" => Ok(TraceFormat::Csv),
+            "json-lines" => Ok(TraceFormat::JsonLines),
+            _ => Err(format!(
+                "unknown trace format \"{s}\", expected human, csv or json-lines"
+            )),
+        }
+    }
+}
+
+/// The column names of CSV trace entries
+fn csv_trace_header() -> String {
+    let registers = Register::ALL.map(|reg| format!("{reg:?}").to_lowercase());
+    format!("step,pc,mnemonic,{}", registers.join(","))
+}
+
+/// Formats the trace entry of the instruction at the program counter, before it is executed.
+/// Human readable entries end with the source line of the instruction if the rom has debug info.
+fn trace_line(
+    execution: &Execution,
+    format: TraceFormat,
+    colored: bool,
+    lines: &HashMap<u32, u32>,
+) -> Result<String, Tx8Error> {
+    let pc = execution.program_counter();
+    if format == TraceFormat::Human {
+        let (text, _) = disassemble_at(execution.memory(), pc, colored)?;
+        return Ok(match lines.get(&pc) {
+            Some(line) => format!("{pc:06x}  {text}  ; line {line}"),
+            None => format!("{pc:06x}  {text}"),
+        });
+    }
+    let mnemonic = instruction::decode_instruction(execution.memory(), pc)?
+        .op_code
+        .mnemonic();
+    let step = execution.instruction_count();
+    let registers = Register::ALL.map(|reg| execution.get_register(reg));
+    Ok(match format {
+        TraceFormat::Csv => {
+            let registers = registers.map(|val| val.to_string()).join(",");
+            format!("{step},{pc},{mnemonic},{registers}")
+        }
+        _ => {
+            let registers = Register::ALL
+                .iter()
+                .zip(registers)
+                .map(|(reg, val)| format!(",\"{}\":{val}", format!("{reg:?}").to_lowercase()))
+                .collect::<String>();
+            format!("{{\"step\":{step},\"pc\":{pc},\"mnemonic\":\"{mnemonic}\"{registers}}}")
+        }
+    })
+}
+
+pub fn run_code(data: Vec<u8>, options: RunOptions) -> Result<(), Tx8Error> {
+    let rom = parse_rom(&data)?;
+    status!(info, "Executing program \"{}\"", rom.name);
+    status!(info, "Description: {}", rom.description);
+    let mut input = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut input)
+        .map_err(|_| Tx8Error::NoInputGiven)?;
+    let mut execution = match options.base {
+        Some(base) => Execution::new_with_rom_at(&rom, base)?,
+        None => Execution::new_with_rom(&rom)?,
+    };
+    execution.set_input(input);
+    execution.set_output(Box::new(std::io::stdout()));
+    execution.set_error_output(Box::new(std::io::stderr()));
+    execution.set_poison_stack(options.poison_stack);
+    execution.set_lenient_sys_calls(options.lenient_sys_calls);
+    execution.set_trap_overflow(options.trap_overflow);
+    execution.set_canonical_nan(options.canonical_nan);
+    execution.set_budget(options.budget);
+    execution.set_output_limit(options.output_limit);
+    execution.set_code_bounds_check(options.check_code_bounds);
+    execution.set_code_protection(options.protect_code);
+    execution.set_bounded_offsets(options.bounded_offsets);
+    execution.set_null_guard(options.null_guard);
+    execution.set_sys_call_abi(options.sys_call_abi);
+    execution.set_replay_log(options.replay_log);
+    if options.console {
+        execution.map_console()?;
+    }
+    status!(info, "Program output:");
+    let colored = std::io::stderr().is_terminal();
+    // the debug info lists offsets into the code, the trace shows addresses
+    let code_start = options.base.unwrap_or(hardware::MB_4 as u32);
+    let lines: HashMap<u32, u32> = rom
+        .lines
+        .iter()
+        .map(|&(offset, line)| (code_start + offset, line))
+        .collect();
+    if options.trace && options.trace_format == TraceFormat::Csv {
+        eprintln!("{}", csv_trace_header());
+    }
     loop {
-        if let Effect::Halted = execution.next_step()? {
-            println!("\nProgram halted");
+        if options.trace {
+            eprintln!(
+                "{}",
+                trace_line(&execution, options.trace_format, colored, &lines)?
+            );
+        }
+        let effect = execution.next_step().inspect_err(|_| {
+            if options.replay_log > 0 {
+                eprintln!("Last executed instructions:");
+                for (pc, instruction) in execution.recent_instructions() {
+                    eprintln!("{pc:06x}  {instruction}");
+                }
+            }
+        })?;
+        if let Effect::Halted(reason) = effect {
+            // end the last line of the program output
+            #[cfg(not(feature = "log"))]
+            println!();
+            match reason {
+                HaltReason::HaltOpcode => status!(info, "Program halted"),
+                HaltReason::Exit(code) => status!(info, "Program exited with code {code}"),
+                HaltReason::RanOffEnd => status!(info, "Program ran past the end of its code"),
+            }
             break;
         }
     }
+    if options.print_cycles {
+        status!(info, "Simulated cycles: {}", execution.simulated_cycles());
+    }
+    #[cfg(feature = "serde")]
+    if options.dump_state {
+        println!("{}", execution.state_json());
+    }
     Ok(())
 }
 
-fn parse_rom(data: &Vec<u8>) -> Result<&[u8], Tx8Error> {
-    // Ensure file is at least 64 bytes long and magic bytes match
-    if data.len() < 64 || &data[0..4] != "TX8\0".as_bytes() {
-        return Err(Tx8Error::ParseError);
-    }
-    // assign length
-    let program_name_length = data[4] as usize;
-    let description_length = u16::from_le_bytes(data[5..7].try_into()?) as usize;
-    let data_length = u32::from_le_bytes(data[7..11].try_into()?) as usize;
-
-    let program_name_end = 64 + program_name_length;
-    let description_end = program_name_end + description_length;
-    let data_end = description_end + data_length;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // check length
-    if data.len() != data_end {
-        return Err(Tx8Error::ParseError);
+    #[test]
+    fn csv_traces_start_with_the_header() {
+        // ld a 5, hlt
+        let mut execution = Execution::test_harness(&[0x10, 0x61, 0x00, 0x05, 0x00], &[]).unwrap();
+        execution.set_register(Register::B, 7);
+        let line = |execution: &Execution| {
+            trace_line(execution, TraceFormat::Csv, false, &HashMap::new()).unwrap()
+        };
+        assert_eq!(csv_trace_header(), "step,pc,mnemonic,a,b,c,d,r,o,p,s");
+        assert_eq!(
+            line(&execution),
+            "0,4194304,ld,0,7,0,0,0,0,4194304,12591104"
+        );
+        execution.next_step().unwrap();
+        assert_eq!(
+            line(&execution),
+            "1,4194308,hlt,5,7,0,0,0,0,4194308,12591104"
+        );
     }
 
-    let program_name = std::str::from_utf8(&data[64..program_name_end])?;
-    let description = std::str::from_utf8(&data[program_name_end..description_end])?;
-    println!("Executing program \"{}\"", program_name);
-    println!("Description: {}", description);
-    Ok(&data[description_end..data_end])
+    #[test]
+    fn human_traces_annotate_the_source_line() {
+        // ld a 5, hlt
+        let mut execution = Execution::test_harness(&[0x10, 0x61, 0x00, 0x05, 0x00], &[]).unwrap();
+        let lines = HashMap::from([(0x400004, 12)]);
+        let line = |execution: &Execution| {
+            trace_line(execution, TraceFormat::Human, false, &lines).unwrap()
+        };
+        assert_eq!(line(&execution), "400000  ld a 5u8");
+        execution.next_step().unwrap();
+        assert_eq!(line(&execution), "400004  hlt  ; line 12");
+    }
 }
@@ -1,5 +1,6 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use std::fs::read;
+use std::io::IsTerminal;
 use std::process::exit;
 use tx8_core::*;
 
@@ -8,12 +9,245 @@ use tx8_core::*;
 #[command(author = "TecTrixer")]
 #[command(version = "0.1.0")]
 #[command(about = "This interpreter takes tx8 ROM files (.txr) and executes them.")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Execute a rom, the default if no subcommand is given
+    Run(RunArgs),
+    /// Print an assembly listing of the code of a rom
+    Disasm(DisasmArgs),
+    /// Print the header of a rom and count its instructions by category
+    Info(InfoArgs),
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    filename: String,
+}
+
+#[derive(Args)]
+struct DisasmArgs {
     filename: String,
+    /// Show the encoded bytes of every instruction
+    #[arg(long)]
+    bytes: bool,
+    /// Replace constant jump and call targets with labels
+    #[arg(long)]
+    labels: bool,
+    /// First address to list, in hexadecimal
+    #[arg(long, value_parser = parse_address)]
+    start: Option<u32>,
+    /// Address after the last one to list, in hexadecimal
+    #[arg(long, value_parser = parse_address)]
+    end: Option<u32>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[arg(required = true)]
+    filename: Option<String>,
+    /// Error on reads of uninitialized stack memory
+    #[arg(long)]
+    poison_stack: bool,
+    /// Warn about unknown system calls instead of failing
+    #[arg(long)]
+    lenient_syscalls: bool,
+    /// Fail on integer overflow instead of wrapping around
+    #[arg(long)]
+    trap_overflow: bool,
+    /// Store every NaN produced by a float instruction as 0x7fc00000
+    #[arg(long)]
+    canonical_nan: bool,
+    /// Print the final register state as JSON
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    dump_state: bool,
+    /// Print the number of simulated cycles
+    #[arg(long)]
+    cycles: bool,
+    /// Abort the program after this many instructions and bytes processed by system calls
+    #[arg(long)]
+    budget: Option<u64>,
+    /// Abort the program after it printed this many bytes
+    #[arg(long)]
+    output_limit: Option<u64>,
+    /// Stop the program when it runs past the end of its code
+    #[arg(long)]
+    check_code_bounds: bool,
+    /// Fail when the program writes into its own code
+    #[arg(long)]
+    protect_code: bool,
+    /// Pass system call arguments on the stack and return results in R
+    #[arg(long)]
+    stack_abi: bool,
+    /// Fail when a relative address plus the O register leaves the memory
+    #[arg(long)]
+    bounded_offsets: bool,
+    /// Fail on accesses below this hexadecimal address, e.g. 1000 to catch null pointers
+    #[arg(long, value_parser = parse_address, default_value = "0")]
+    null_guard: u32,
+    /// Load the code at this hexadecimal address and adjust its relocated addresses
+    #[arg(long, value_parser = parse_address)]
+    base: Option<u32>,
+    /// Print the last N executed instructions when the program fails
+    #[arg(long, default_value_t = 0)]
+    replay_log: usize,
+    /// Map the console device for cursor positioning and colors
+    #[arg(long)]
+    console: bool,
+    /// Print every instruction to stderr before executing it
+    #[arg(long)]
+    trace: bool,
+    /// Format of the trace: human, csv or json-lines
+    #[arg(long, default_value = "human")]
+    trace_format: TraceFormat,
+}
+
+fn parse_address(src: &str) -> Result<u32, String> {
+    let digits = src.trim_start_matches("0x");
+    u32::from_str_radix(digits, 16).map_err(|e| e.to_string())
 }
 
 fn main() -> Result<(), ()> {
-    let filename = Cli::parse().filename;
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Disasm(args)) => disasm(args),
+        Some(Command::Info(args)) => info(args),
+        Some(Command::Run(args)) => run(args),
+        None => run(cli.run),
+    }
+}
+
+fn disasm(args: DisasmArgs) -> Result<(), ()> {
+    let filename = args.filename;
+    let file = match read(&filename) {
+        Ok(d) => d,
+        Err(e) => {
+            println!("Failed to open \"{filename}\": {e:?}");
+            exit(1)
+        }
+    };
+    let options = DisasmOptions {
+        raw_bytes: args.bytes,
+        labels: args.labels,
+        range: match (args.start, args.end) {
+            (None, None) => None,
+            (start, end) => Some(start.unwrap_or(0)..end.unwrap_or(u32::MAX)),
+        },
+        colored: std::io::stdout().is_terminal(),
+        lines: Vec::new(),
+    };
+    let listing = parse_rom(&file).and_then(|rom| {
+        let options = DisasmOptions {
+            lines: rom.lines,
+            ..options
+        };
+        disassemble(rom.code, &options)
+    });
+    match listing {
+        Ok(listing) => {
+            print!("{listing}");
+            exit(0)
+        }
+        Err(e) => {
+            println!("{}", e);
+            exit(1)
+        }
+    }
+}
+
+fn info(args: InfoArgs) -> Result<(), ()> {
+    let filename = args.filename;
+    let file = match read(&filename) {
+        Ok(d) => d,
+        Err(e) => {
+            println!("Failed to open \"{filename}\": {e:?}");
+            exit(1)
+        }
+    };
+    match print_info(&file) {
+        Ok(()) => exit(0),
+        Err(e) => {
+            println!("{}", e);
+            exit(1)
+        }
+    }
+}
+
+fn print_info(file: &[u8]) -> Result<(), Tx8Error> {
+    let header = parse_header(file)?;
+    let rom = parse_rom(file)?;
+    let summary = instruction_summary(rom.code)?;
+    println!("Name:            {}", rom.name);
+    println!("Description:     {}", rom.description);
+    println!("File size:       {} bytes", file.len());
+    println!("Data length:     {} bytes", header.data_length);
+    println!(
+        "Header checksum: {:#04x} ({})",
+        header.checksum,
+        if header.checksum_valid {
+            "valid"
+        } else {
+            "invalid"
+        }
+    );
+    match header.crc {
+        0 => println!("CRC32:           none"),
+        crc => println!("CRC32:           {crc:#010x}"),
+    }
+    println!("Data sections:   {}", header.section_count);
+    println!("Relocations:     {}", header.relocation_count);
+    println!("Line entries:    {}", header.line_count);
+    match header.reserved.iter().position(|byte| *byte != 0) {
+        None => println!("Reserved bytes:  all zero"),
+        Some(index) => println!("Reserved bytes:  first non-zero byte at {}", 21 + index),
+    }
+    println!("Entry point:     {:#08x}", rom.entry_point());
+    println!("Instructions:");
+    println!("  arithmetic:    {}", summary.arithmetic);
+    println!("  float:         {}", summary.float);
+    println!("  control flow:  {}", summary.control_flow);
+    println!("  system calls:  {}", summary.sys_call);
+    println!("  other:         {}", summary.other);
+    println!("  invalid bytes: {}", summary.invalid_bytes);
+    Ok(())
+}
+
+fn run(cli: RunArgs) -> Result<(), ()> {
+    // clap requires the filename unless a subcommand was given
+    let filename = cli.filename.unwrap_or_default();
+    let options = RunOptions {
+        poison_stack: cli.poison_stack,
+        lenient_sys_calls: cli.lenient_syscalls,
+        trap_overflow: cli.trap_overflow,
+        canonical_nan: cli.canonical_nan,
+        #[cfg(feature = "serde")]
+        dump_state: cli.dump_state,
+        print_cycles: cli.cycles,
+        budget: cli.budget,
+        output_limit: cli.output_limit,
+        check_code_bounds: cli.check_code_bounds,
+        protect_code: cli.protect_code,
+        sys_call_abi: if cli.stack_abi {
+            SysCallAbi::Stack
+        } else {
+            SysCallAbi::Legacy
+        },
+        bounded_offsets: cli.bounded_offsets,
+        null_guard: cli.null_guard,
+        base: cli.base,
+        replay_log: cli.replay_log,
+        console: cli.console,
+        trace: cli.trace,
+        trace_format: cli.trace_format,
+    };
     println!("Reading {filename}");
     let file = match read(&filename) {
         Ok(d) => d,
@@ -22,7 +256,7 @@ fn main() -> Result<(), ()> {
             return Ok(());
         }
     };
-    match run_code(file) {
+    match run_code(file, options) {
         Ok(_) => exit(0),
         Err(e) => {
             println!("\n{}", e);
@@ -41,19 +41,192 @@ pub enum Parameter {
     RegisterAddress(u8),
 }
 
-pub fn parse_parameter(mem: &Memory, ptr: u32, par_mode: ParameterMode) -> (Parameter, u32) {
-    match par_mode {
+impl Parameter {
+    /// Renders the parameter in assembly syntax, e.g. `42u8`, `#401000`, `$-10`, `as` or `@b`
+    pub fn describe(&self) -> String {
+        match *self {
+            Parameter::Unused => String::new(),
+            Parameter::Constant8(x) => format!("{x}u8"),
+            Parameter::Constant16(x) => format!("{x}u16"),
+            Parameter::Constant32(x) => format!("{x}"),
+            Parameter::AbsoluteAddress(ptr) => format!("#{ptr:x}"),
+            // relative offsets are 24 bit two's complement
+            Parameter::RelativeAddress(ptr) if ptr & 0x800000 != 0 => {
+                format!("$-{:x}", 0x1000000 - (ptr & 0xffffff))
+            }
+            Parameter::RelativeAddress(ptr) => format!("${ptr:x}"),
+            Parameter::Register(r) => describe_register(r),
+            Parameter::RegisterAddress(r) => format!("@{}", describe_register(r)),
+        }
+    }
+
+    /// Encodes a float literal of the assembly language, e.g. `-1.5`, `1.0e10`, `inf` or `nan`,
+    /// as the 32 bit constant holding the bits of the nearest `f32`. Fails with `ParseError` if
+    /// the literal doesn't match the `float` rule of the grammar.
+    pub fn from_float_literal(literal: &str) -> Result<Parameter, Tx8Error> {
+        if literal == "nan" {
+            return Ok(Parameter::Constant32(f32::NAN.to_bits()));
+        }
+        let unsigned = literal.strip_prefix('-').unwrap_or(literal);
+        if unsigned != "inf" && !is_decimal_literal(unsigned) {
+            return Err(Tx8Error::ParseError);
+        }
+        let val: f32 = literal.parse().map_err(|_| Tx8Error::ParseError)?;
+        Ok(Parameter::Constant32(val.to_bits()))
+    }
+}
+
+/// Whether the text has digits on both sides of the decimal point and an optional exponent
+fn is_decimal_literal(text: &str) -> bool {
+    let digits = |text: &str| !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit());
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (text, None),
+    };
+    let Some((integer, fraction)) = mantissa.split_once('.') else {
+        return false;
+    };
+    digits(integer)
+        && digits(fraction)
+        && exponent
+            .is_none_or(|exponent| digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent)))
+}
+
+/// Returns the assembly name of a register encoding, including the `s` and `b` suffixes of the
+/// short (0x20) and byte (0x10) views
+pub fn register_name(byte: u8) -> Option<String> {
+    let name = match byte & 0xf {
+        0x00 => "a",
+        0x01 => "b",
+        0x02 => "c",
+        0x03 => "d",
+        0x04 => "r",
+        0x05 => "o",
+        0x06 => "p",
+        0x07 => "s",
+        _ => return None,
+    };
+    let suffix = match byte & 0xf0 {
+        0x00 => "",
+        0x10 => "b",
+        0x20 => "s",
+        _ => return None,
+    };
+    Some(format!("{name}{suffix}"))
+}
+
+fn describe_register(byte: u8) -> String {
+    register_name(byte).unwrap_or_else(|| format!("<invalid register {byte:#04x}>"))
+}
+
+/// Where instructions are decoded from, a `Memory` or the bytes of a code image
+pub trait ByteSource {
+    /// Reads `len` bytes (1 to 4) at `ptr` as a little-endian number, fails with `ParseError` if
+    /// they are not available
+    fn read_le(&self, ptr: u32, len: u32) -> Result<u32, Tx8Error>;
+}
+
+impl ByteSource for Memory {
+    fn read_le(&self, ptr: u32, len: u32) -> Result<u32, Tx8Error> {
+        Ok(match len {
+            1 => self.read_byte(ptr) as u32,
+            2 => self.read_short(ptr) as u32,
+            3 => self.read_24bit(ptr),
+            _ => self.read_int(ptr),
+        })
+    }
+}
+
+impl ByteSource for [u8] {
+    fn read_le(&self, ptr: u32, len: u32) -> Result<u32, Tx8Error> {
+        let start = ptr as usize;
+        let bytes = self
+            .get(start..start + len as usize)
+            .ok_or(Tx8Error::ParseError)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |acc, &byte| acc << 8 | byte as u32))
+    }
+}
+
+pub fn parse_parameter<S: ByteSource + ?Sized>(
+    src: &S,
+    ptr: u32,
+    par_mode: ParameterMode,
+) -> Result<(Parameter, u32), Tx8Error> {
+    Ok(match par_mode {
         ParameterMode::Unused => (Parameter::Unused, 0),
-        ParameterMode::Constant8 => (Parameter::Constant8(mem.read_byte(ptr)), 1),
-        ParameterMode::Constant16 => (Parameter::Constant16(mem.read_short(ptr)), 2),
-        ParameterMode::Constant32 => (Parameter::Constant32(mem.read_int(ptr)), 4),
-        ParameterMode::AbsoluteAddress => (Parameter::AbsoluteAddress(mem.read_24bit(ptr)), 3),
-        ParameterMode::RelativeAddress => (Parameter::RelativeAddress(mem.read_24bit(ptr)), 3),
-        ParameterMode::Register => (Parameter::Register(mem.read_byte(ptr)), 1),
-        ParameterMode::RegisterAddress => (Parameter::RegisterAddress(mem.read_byte(ptr)), 1),
+        ParameterMode::Constant8 => (Parameter::Constant8(src.read_le(ptr, 1)? as u8), 1),
+        ParameterMode::Constant16 => (Parameter::Constant16(src.read_le(ptr, 2)? as u16), 2),
+        ParameterMode::Constant32 => (Parameter::Constant32(src.read_le(ptr, 4)?), 4),
+        ParameterMode::AbsoluteAddress => (Parameter::AbsoluteAddress(src.read_le(ptr, 3)?), 3),
+        ParameterMode::RelativeAddress => (Parameter::RelativeAddress(src.read_le(ptr, 3)?), 3),
+        ParameterMode::Register => (Parameter::Register(src.read_le(ptr, 1)? as u8), 1),
+        ParameterMode::RegisterAddress => {
+            (Parameter::RegisterAddress(src.read_le(ptr, 1)? as u8), 1)
+        }
+    })
+}
+
+/// The memory parameters are resolved against, see `Value::from_par`
+pub trait ReadMemory {
+    fn read_byte(&self, ptr: u32) -> u8;
+    fn read_short(&self, ptr: u32) -> u16;
+    fn read_int(&self, ptr: u32) -> u32;
+    fn relative_address(&self, ptr: u32, offset: u32) -> Result<u32, Tx8Error>;
+}
+
+impl ReadMemory for Memory {
+    fn read_byte(&self, ptr: u32) -> u8 {
+        Memory::read_byte(self, ptr)
+    }
+    fn read_short(&self, ptr: u32) -> u16 {
+        Memory::read_short(self, ptr)
+    }
+    fn read_int(&self, ptr: u32) -> u32 {
+        Memory::read_int(self, ptr)
+    }
+    fn relative_address(&self, ptr: u32, offset: u32) -> Result<u32, Tx8Error> {
+        Memory::relative_address(self, ptr, offset)
     }
 }
 
+/// Memory without contents for decoding without a `Memory`, every read returns 0
+pub struct Unmapped;
+
+impl ReadMemory for Unmapped {
+    fn read_byte(&self, _ptr: u32) -> u8 {
+        0
+    }
+    fn read_short(&self, _ptr: u32) -> u16 {
+        0
+    }
+    fn read_int(&self, _ptr: u32) -> u32 {
+        0
+    }
+    fn relative_address(&self, ptr: u32, offset: u32) -> Result<u32, Tx8Error> {
+        Ok(ptr.wrapping_add(offset))
+    }
+}
+
+/// Resolves the address an address parameter points to without reading memory
+pub fn effective_address<M: ReadMemory + ?Sized>(
+    par: Parameter,
+    cpu: &Cpu,
+    mem: &M,
+) -> Result<u32, Tx8Error> {
+    let addr = match par {
+        Parameter::AbsoluteAddress(ptr) => ptr,
+        Parameter::RelativeAddress(ptr) => mem.relative_address(ptr, cpu.o)?,
+        Parameter::RegisterAddress(r) => {
+            Value::from_par(Parameter::Register(r), cpu, mem, Int)?.val
+        }
+        _ => return Err(Tx8Error::InstructionError),
+    };
+    Ok(addr & 0xffffff)
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Value {
     pub val: u32,
@@ -64,112 +237,68 @@ impl Value {
     pub fn new(val: u32, size: Size) -> Self {
         Value { val, size }
     }
-    pub fn from_par(
+    pub fn from_par<M: ReadMemory + ?Sized>(
         par: Parameter,
         cpu: &Cpu,
-        mem: &Memory,
+        mem: &M,
         mem_size: Size,
     ) -> Result<Self, Tx8Error> {
         match par {
             Parameter::Unused => Err(Tx8Error::InstructionError),
             Parameter::Constant8(x) => Ok(Value::new(x as u32, Byte)),
             Parameter::Constant16(x) => Ok(Value::new(x as u32, Short)),
-            Parameter::Constant32(x) => Ok(Value::new(x as u32, Int)),
+            Parameter::Constant32(x) => Ok(Value::new(x, Int)),
             Parameter::AbsoluteAddress(ptr) => match mem_size {
                 Byte => Ok(Value::new(mem.read_byte(ptr) as u32, Byte)),
                 Short => Ok(Value::new(mem.read_short(ptr) as u32, Short)),
                 Int => Ok(Value::new(mem.read_int(ptr), Int)),
             },
-            Parameter::RelativeAddress(ptr) => match mem_size {
-                Byte => Ok(Value::new(mem.read_byte(ptr + cpu.o) as u32, Byte)),
-                Short => Ok(Value::new(mem.read_short(ptr + cpu.o) as u32, Short)),
-                Int => Ok(Value::new(mem.read_int(ptr + cpu.o), Int)),
-            },
-            Parameter::Register(r) => {
-                let val = match 0xf & r {
-                    0x00 => cpu.a,
-                    0x01 => cpu.b,
-                    0x02 => cpu.c,
-                    0x03 => cpu.d,
-                    0x04 => cpu.r,
-                    0x05 => cpu.o,
-                    0x06 => cpu.p,
-                    0x07 => cpu.s,
-                    _ => return Err(Tx8Error::InvalidRegister),
-                };
-                let size = get_reg_size(r);
-                let filter = match size {
-                    Byte => 0xff,
-                    Short => 0xffff,
-                    Int => 0xffffffff,
-                };
-                Ok(Value::new(val & filter, size))
+            Parameter::RelativeAddress(ptr) => {
+                let ptr = mem.relative_address(ptr, cpu.o)?;
+                match mem_size {
+                    Byte => Ok(Value::new(mem.read_byte(ptr) as u32, Byte)),
+                    Short => Ok(Value::new(mem.read_short(ptr) as u32, Short)),
+                    Int => Ok(Value::new(mem.read_int(ptr), Int)),
+                }
             }
+            Parameter::Register(r) => Ok(Value::new(read_register(cpu, r)?, get_reg_size(r)?)),
             Parameter::RegisterAddress(r) => {
-                let val = match 0xf & r {
-                    0x00 => cpu.a,
-                    0x01 => cpu.b,
-                    0x02 => cpu.c,
-                    0x03 => cpu.d,
-                    0x04 => cpu.r,
-                    0x05 => cpu.o,
-                    0x06 => cpu.p,
-                    0x07 => cpu.s,
-                    _ => return Err(Tx8Error::InvalidRegister),
-                };
-                let filter = match get_reg_size(r) {
-                    Byte => 0xff,
-                    Short => 0xffff,
-                    Int => 0xffffffff,
-                };
+                let ptr = read_register(cpu, r)?;
                 match mem_size {
-                    Byte => Ok(Value::new(mem.read_byte(val & filter) as u32, Byte)),
-                    Short => Ok(Value::new(mem.read_short(val & filter) as u32, Short)),
-                    Int => Ok(Value::new(mem.read_int(val & filter), Int)),
+                    Byte => Ok(Value::new(mem.read_byte(ptr) as u32, Byte)),
+                    Short => Ok(Value::new(mem.read_short(ptr) as u32, Short)),
+                    Int => Ok(Value::new(mem.read_int(ptr), Int)),
                 }
             }
         }
     }
-    pub fn from_par_signed(
+    pub fn from_par_signed<M: ReadMemory + ?Sized>(
         par: Parameter,
         cpu: &Cpu,
-        mem: &Memory,
+        mem: &M,
         mem_size: Size,
     ) -> Result<Self, Tx8Error> {
         match par {
             Parameter::Unused => Err(Tx8Error::InstructionError),
             Parameter::Constant8(x) => Ok(Value::new(x as i8 as i32 as u32, Byte)),
             Parameter::Constant16(x) => Ok(Value::new(x as i16 as i32 as u32, Short)),
-            Parameter::Constant32(x) => Ok(Value::new(x as u32, Int)),
+            Parameter::Constant32(x) => Ok(Value::new(x, Int)),
             Parameter::AbsoluteAddress(ptr) => match mem_size {
                 Byte => Ok(Value::new(mem.read_byte(ptr) as i8 as i32 as u32, Byte)),
                 Short => Ok(Value::new(mem.read_short(ptr) as i16 as i32 as u32, Short)),
                 Int => Ok(Value::new(mem.read_int(ptr), Int)),
             },
-            Parameter::RelativeAddress(ptr) => match mem_size {
-                Byte => Ok(Value::new(
-                    mem.read_byte(ptr + cpu.o) as i8 as i32 as u32,
-                    Byte,
-                )),
-                Short => Ok(Value::new(
-                    mem.read_short(ptr + cpu.o) as i16 as i32 as u32,
-                    Short,
-                )),
-                Int => Ok(Value::new(mem.read_int(ptr + cpu.o), Int)),
-            },
+            Parameter::RelativeAddress(ptr) => {
+                let ptr = mem.relative_address(ptr, cpu.o)?;
+                match mem_size {
+                    Byte => Ok(Value::new(mem.read_byte(ptr) as i8 as i32 as u32, Byte)),
+                    Short => Ok(Value::new(mem.read_short(ptr) as i16 as i32 as u32, Short)),
+                    Int => Ok(Value::new(mem.read_int(ptr), Int)),
+                }
+            }
             Parameter::Register(r) => {
-                let val = match 0xf & r {
-                    0x00 => cpu.a,
-                    0x01 => cpu.b,
-                    0x02 => cpu.c,
-                    0x03 => cpu.d,
-                    0x04 => cpu.r,
-                    0x05 => cpu.o,
-                    0x06 => cpu.p,
-                    0x07 => cpu.s,
-                    _ => return Err(Tx8Error::InvalidRegister),
-                };
-                let size = get_reg_size(r);
+                let val = read_register(cpu, r)?;
+                let size = get_reg_size(r)?;
                 let val = match size {
                     Byte => (val & 0xff) as i8 as i32 as u32,
                     Short => (val & 0xffff) as i16 as i32 as u32,
@@ -178,48 +307,43 @@ impl Value {
                 Ok(Value::new(val, size))
             }
             Parameter::RegisterAddress(r) => {
-                let val = match 0xf & r {
-                    0x00 => cpu.a,
-                    0x01 => cpu.b,
-                    0x02 => cpu.c,
-                    0x03 => cpu.d,
-                    0x04 => cpu.r,
-                    0x05 => cpu.o,
-                    0x06 => cpu.p,
-                    0x07 => cpu.s,
-                    _ => return Err(Tx8Error::InvalidRegister),
-                };
-                let filter = match get_reg_size(r) {
-                    Byte => 0xff,
-                    Short => 0xffff,
-                    Int => 0xffffffff,
-                };
+                let ptr = read_register(cpu, r)?;
                 match mem_size {
-                    Byte => Ok(Value::new(
-                        mem.read_byte(val & filter) as i8 as i32 as u32,
-                        Byte,
-                    )),
-                    Short => Ok(Value::new(
-                        mem.read_short(val & filter) as i16 as i32 as u32,
-                        Short,
-                    )),
-                    Int => Ok(Value::new(mem.read_int(val & filter), Int)),
+                    Byte => Ok(Value::new(mem.read_byte(ptr) as i8 as i32 as u32, Byte)),
+                    Short => Ok(Value::new(mem.read_short(ptr) as i16 as i32 as u32, Short)),
+                    Int => Ok(Value::new(mem.read_int(ptr), Int)),
                 }
             }
         }
     }
 }
 
-fn get_reg_size(byte: u8) -> Size {
-    // TODO: maybe get more efficient, was lazy, therefore counted all possibilities
+/// Returns the size of the register view selected by the high nibble of a register encoding
+fn get_reg_size(byte: u8) -> Result<Size, Tx8Error> {
     match byte {
-        0x00 | 0x01 | 0x02 | 0x03 | 0x04 | 0x05 | 0x06 | 0x07 => Int,
-        0x20 | 0x21 | 0x22 | 0x23 | 0x24 | 0x25 | 0x26 | 0x27 => Short,
-        0x10 | 0x11 | 0x12 | 0x13 | 0x14 | 0x15 | 0x16 | 0x17 => Byte,
-        _ => Int,
+        0x00..=0x07 => Ok(Int),
+        0x20..=0x27 => Ok(Short),
+        0x10..=0x17 => Ok(Byte),
+        _ => Err(Tx8Error::InvalidRegister(byte)),
     }
 }
 
+/// Reads the register view of a register encoding, masked to the size of the view
+fn read_register(cpu: &Cpu, byte: u8) -> Result<u32, Tx8Error> {
+    let val = match byte & 0xf {
+        0x00 => cpu.a,
+        0x01 => cpu.b,
+        0x02 => cpu.c,
+        0x03 => cpu.d,
+        0x04 => cpu.r,
+        0x05 => cpu.o,
+        0x06 => cpu.p,
+        0x07 => cpu.s,
+        _ => return Err(Tx8Error::InvalidRegister(byte)),
+    };
+    Ok(val & get_reg_size(byte)?.mask())
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum Size {
     Byte,
@@ -235,6 +359,15 @@ impl Size {
             Int => 4,
         }
     }
+
+    /// Mask selecting the lowest `bytes()` bytes of a value
+    pub fn mask(&self) -> u32 {
+        match self {
+            Byte => 0xff,
+            Short => 0xffff,
+            Int => 0xffffffff,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -253,12 +386,44 @@ impl Writable {
             Parameter::Constant32(_) => Err(Tx8Error::InstructionError),
             Parameter::AbsoluteAddress(x) => Ok(Writable::AbsoluteAddress(AbsoluteAddress(x))),
             Parameter::RelativeAddress(x) => Ok(Writable::RelativeAddress(RelativeAddress(x))),
-            Parameter::Register(x) => Ok(Writable::Register(Register(x))),
-            Parameter::RegisterAddress(x) => Ok(Writable::RegisterAddress(RegisterAddress(x))),
+            Parameter::Register(x) => Ok(Writable::Register(Register {
+                byte: x,
+                size: get_reg_size(x)?,
+            })),
+            Parameter::RegisterAddress(x) => {
+                get_reg_size(x)?;
+                Ok(Writable::RegisterAddress(RegisterAddress(x)))
+            }
         }
     }
 }
 
+impl Writable {
+    /// Returns the full view of a register, e.g. to restore it from the stack
+    pub fn register(reg: crate::hardware::Register) -> Writable {
+        Writable::Register(Register {
+            byte: reg as u8,
+            size: Int,
+        })
+    }
+
+    /// Returns the parameter this writable was created from
+    pub fn to_par(self) -> Parameter {
+        match self {
+            Writable::AbsoluteAddress(x) => Parameter::AbsoluteAddress(x.0),
+            Writable::RelativeAddress(x) => Parameter::RelativeAddress(x.0),
+            Writable::Register(x) => Parameter::Register(x.byte),
+            Writable::RegisterAddress(x) => Parameter::RegisterAddress(x.0),
+        }
+    }
+}
+
+impl std::fmt::Display for Writable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_par().describe())
+    }
+}
+
 impl Write for Writable {
     fn write(self, mem: &mut Memory, cpu: &mut Cpu, val: u32) -> Result<(), Tx8Error> {
         match self {
@@ -349,7 +514,7 @@ impl Write for RelativeAddress {
         val: u32,
         size: Size,
     ) -> Result<(), Tx8Error> {
-        let ptr = self.0 + cpu.o;
+        let ptr = mem.relative_address(self.0, cpu.o)?;
         match size {
             Byte => mem.write_byte(ptr, val as u8),
             Short => mem.write_short(ptr, val as u16),
@@ -359,53 +524,46 @@ impl Write for RelativeAddress {
     }
 }
 
+/// A register view, the size is known to be valid once the encoding was decoded
 #[derive(Copy, Clone, Debug)]
-pub struct Register(pub u8);
+pub struct Register {
+    byte: u8,
+    size: Size,
+}
 
 impl Write for Register {
     fn write(self, mem: &mut Memory, cpu: &mut Cpu, val: u32) -> Result<(), Tx8Error> {
-        let size = match self.0 & 0xf0 {
-            0x00 => Int,
-            0x20 => Short,
-            0x10 => Byte,
-            _ => return Err(Tx8Error::InvalidRegister),
-        };
-        self.write_size(mem, cpu, val, size)
+        self.write_size(mem, cpu, val, self.size)
     }
 
     fn size(&self) -> Size {
-        get_reg_size(self.0)
+        self.size
     }
 
     fn write_size(
         self,
-        _: &mut Memory,
+        mem: &mut Memory,
         cpu: &mut Cpu,
         val: u32,
         _size: Size,
     ) -> Result<(), Tx8Error> {
         // NOTE: ignoring the size, works if write_size is only being called by load
-        let size = match self.0 & 0xf0 {
-            0x00 => Int,
-            0x20 => Short,
-            0x10 => Byte,
-            _ => return Err(Tx8Error::InvalidRegister),
-        };
-        let (mask, mask2) = match size {
-            Byte => (0xffffff00, 0xff),
-            Short => (0xffff0000, 0xffff),
-            Int => (0x0, 0xffffffff),
-        };
-        match self.0 & 0xf {
+        let mask2 = self.size.mask();
+        let mask = !mask2;
+        match self.byte & 0xf {
             0x00 => cpu.a = (cpu.a & mask) | (val & mask2),
             0x01 => cpu.b = (cpu.b & mask) | (val & mask2),
             0x02 => cpu.c = (cpu.c & mask) | (val & mask2),
             0x03 => cpu.d = (cpu.d & mask) | (val & mask2),
-            0x04 => cpu.r = (cpu.r & mask) | (val & mask2),
+            0x04 => cpu.set_r((cpu.r & mask) | (val & mask2)),
             0x05 => cpu.o = (cpu.o & mask) | (val & mask2),
             0x06 => cpu.p = (cpu.p & mask) | (val & mask2),
-            0x07 => cpu.s = (cpu.s & mask) | (val & mask2),
-            _ => return Err(Tx8Error::InvalidRegister),
+            0x07 => {
+                let old = cpu.s;
+                cpu.s = (cpu.s & mask) | (val & mask2);
+                mem.move_stack_pointer(old, cpu.s);
+            }
+            _ => return Err(Tx8Error::InvalidRegister(self.byte)),
         };
 
         Ok(())
@@ -430,33 +588,7 @@ impl Write for RegisterAddress {
         val: u32,
         size: Size,
     ) -> Result<(), Tx8Error> {
-        let ptr = match self.0 {
-            0x00 => cpu.a,
-            0x01 => cpu.b,
-            0x02 => cpu.c,
-            0x03 => cpu.d,
-            0x04 => cpu.r,
-            0x05 => cpu.o,
-            0x06 => cpu.p,
-            0x07 => cpu.s,
-            0x10 => cpu.a & 0xff,
-            0x11 => cpu.b & 0xff,
-            0x12 => cpu.c & 0xff,
-            0x13 => cpu.d & 0xff,
-            0x14 => cpu.r & 0xff,
-            0x15 => cpu.o & 0xff,
-            0x16 => cpu.p & 0xff,
-            0x17 => cpu.s & 0xff,
-            0x20 => cpu.a & 0xffff,
-            0x21 => cpu.b & 0xffff,
-            0x22 => cpu.c & 0xffff,
-            0x23 => cpu.d & 0xffff,
-            0x24 => cpu.r & 0xffff,
-            0x25 => cpu.o & 0xffff,
-            0x26 => cpu.p & 0xffff,
-            0x27 => cpu.s & 0xffff,
-            _ => return Err(Tx8Error::InvalidRegister),
-        };
+        let ptr = read_register(cpu, self.0)?;
         match size {
             Byte => mem.write_byte(ptr, val as u8),
             Short => mem.write_short(ptr, val as u16),
@@ -465,3 +597,100 @@ impl Write for RegisterAddress {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float_bits(literal: &str) -> u32 {
+        match Parameter::from_float_literal(literal) {
+            Ok(Parameter::Constant32(bits)) => bits,
+            other => panic!("{literal} encoded as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn float_literals_encode_their_bits() {
+        assert_eq!(float_bits("0.0"), 0x00000000);
+        assert_eq!(float_bits("-0.0"), 0x80000000);
+        assert_eq!(float_bits("-1.5"), 0xbfc00000);
+        assert_eq!(float_bits("1.0e10"), 0x501502f9);
+        assert_eq!(float_bits("2.5E-1"), 0.25f32.to_bits());
+        assert_eq!(float_bits("inf"), 0x7f800000);
+        assert_eq!(float_bits("-inf"), 0xff800000);
+        assert_eq!(float_bits("nan"), 0x7fc00000);
+    }
+
+    #[test]
+    fn float_literals_outside_the_grammar_are_rejected() {
+        for literal in [
+            "1", ".5", "1.", "1e5", "1.0e", "+1.0", "-nan", "NaN", "infinity", "",
+        ] {
+            assert!(
+                Parameter::from_float_literal(literal).is_err(),
+                "{literal} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn describes_every_parameter_mode() {
+        let cases = [
+            (Parameter::Unused, ""),
+            (Parameter::Constant8(42), "42u8"),
+            (Parameter::Constant16(1000), "1000u16"),
+            (Parameter::Constant32(70000), "70000"),
+            (Parameter::AbsoluteAddress(0x401000), "#401000"),
+            (Parameter::RelativeAddress(0x10), "$10"),
+            (Parameter::RelativeAddress(0xfffff0), "$-10"),
+            (Parameter::Register(0x00), "a"),
+            (Parameter::Register(0x17), "sb"),
+            (Parameter::Register(0x21), "bs"),
+            (Parameter::Register(0x08), "<invalid register 0x08>"),
+            (Parameter::RegisterAddress(0x01), "@b"),
+        ];
+        for (par, expected) in cases {
+            assert_eq!(par.describe(), expected, "{par:?}");
+        }
+    }
+
+    #[test]
+    fn writes_and_reads_every_register_view() {
+        let mut mem = Memory::load_rom(&[], &[]).unwrap();
+        for (index, reg) in crate::Register::ALL.into_iter().enumerate() {
+            for (view, mask) in [(0x00, 0xffffffff), (0x10, 0xff), (0x20, 0xffff)] {
+                let byte = view + index as u8;
+                let mut cpu = Cpu::new();
+                cpu.set(reg, 0x12345678);
+                let to = Writable::from_par(Parameter::Register(byte)).unwrap();
+                to.write(&mut mem, &mut cpu, 0xabcdef99).unwrap();
+                let expected = (0x12345678 & !mask) | (0xabcdef99 & mask);
+                assert_eq!(cpu.get(reg), expected, "{byte:#04x}");
+                let read = Value::from_par(Parameter::Register(byte), &cpu, &mem, Int).unwrap();
+                assert_eq!(read.val, 0xabcdef99 & mask, "{byte:#04x}");
+                assert_eq!(read.size.mask(), mask, "{byte:#04x}");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_register_encodings_fail() {
+        let cpu = Cpu::new();
+        let mem = Memory::load_rom(&[], &[]).unwrap();
+        for byte in [0x08, 0x0f, 0x18, 0x30, 0x40, 0xff] {
+            let error = Tx8Error::InvalidRegister(byte);
+            assert_eq!(
+                Writable::from_par(Parameter::Register(byte)).unwrap_err(),
+                error
+            );
+            assert_eq!(
+                Writable::from_par(Parameter::RegisterAddress(byte)).unwrap_err(),
+                error
+            );
+            assert_eq!(
+                Value::from_par(Parameter::Register(byte), &cpu, &mem, Int).unwrap_err(),
+                error
+            );
+        }
+    }
+}
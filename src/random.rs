@@ -13,10 +13,22 @@ impl Rand {
         Rand { val: SEED }
     }
     pub fn next(&mut self) -> u32 {
-        self.val = MULTIPLIER * self.val + INCREMENT;
+        self.val = MULTIPLIER.wrapping_mul(self.val).wrapping_add(INCREMENT);
         (self.val >> 16) & RANGE
     }
     pub fn set_seed(&mut self, seed: u32) {
         self.val = seed;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_generator_wraps_around() {
+        let mut rand = Rand::new();
+        let values: Vec<u32> = (0..3).map(|_| rand.next()).collect();
+        assert_eq!(values, [13289, 23247, 24381]);
+    }
+}
@@ -0,0 +1,356 @@
+//! Reference model of the integer instructions written from the spec, which the interpreter is
+//! checked against
+
+use crate::{Execution, Register, Tx8Error};
+
+/// An instruction whose result differs from the reference model
+#[derive(Clone)]
+struct ModelMismatch {
+    mnemonic: &'static str,
+    /// The operands in assembly syntax, e.g. `a #c01000`
+    operands: &'static str,
+    /// Size of the register views and constants used as operands in bits
+    bits: u32,
+    /// Initial value of the destination
+    a: u32,
+    /// Value of the source, stored at the address for address operands
+    b: u32,
+    /// The destination and `R` after the instruction, `None` if it should fail with a division by
+    /// zero
+    expected: Option<(u32, u32)>,
+    actual: Result<(u32, u32), Tx8Error>,
+}
+
+/// Printed in the assertion message when the check fails, with the operands in hex
+impl std::fmt::Debug for ModelMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ModelMismatch")
+            .field(
+                "instruction",
+                &format_args!("{} {}", self.mnemonic, self.operands),
+            )
+            .field("bits", &self.bits)
+            .field("a", &format_args!("{:#x}", self.a))
+            .field("b", &format_args!("{:#x}", self.b))
+            .field("expected", &self.expected)
+            .field("actual", &self.actual)
+            .finish()
+    }
+}
+
+const OPERATIONS: [(u8, &str); 21] = [
+    (0x09, "cmp"),
+    (0x0b, "ucmp"),
+    (0x22, "add"),
+    (0x23, "sub"),
+    (0x24, "mul"),
+    (0x25, "div"),
+    (0x26, "mod"),
+    (0x30, "and"),
+    (0x31, "or"),
+    (0x33, "nand"),
+    (0x34, "xor"),
+    (0x35, "slr"),
+    (0x36, "sar"),
+    (0x37, "sll"),
+    (0x38, "ror"),
+    (0x39, "rol"),
+    (0x60, "uadd"),
+    (0x61, "usub"),
+    (0x62, "umul"),
+    (0x63, "udiv"),
+    (0x64, "umod"),
+];
+
+// the upper bytes are set, so views which do not mask them show up as mismatches
+const OPERANDS: [u32; 15] = [
+    0, 1, 2, 3, 7, 31, 0x7f, 0x80, 0xff, 0x7fff, 0x8000, 0xabcdffff, 0x7fffffff, 0x80000000,
+    0xffffffff,
+];
+
+// the initial value of `R`, which instructions not documented to change it must keep
+const R_SENTINEL: u32 = 0x5a5a5a5a;
+
+const LOADS: [(u8, &str); 4] = [(0x10, "ld"), (0x11, "lds"), (0x12, "lw"), (0x13, "lws")];
+
+const CODE: u32 = 0x400000;
+// where address operands point to
+const SOURCE: u32 = 0xc01000;
+const DESTINATION: u32 = 0xc01100;
+// `O` for relative addresses, so that the offset 0x10 points to the source
+const OFFSET_BASE: u32 = SOURCE - 0x10;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Constant,
+    Absolute,
+    Relative,
+    Register,
+    RegisterAddress,
+}
+
+// every source mode with a register as well as a memory destination
+const LOAD_OPERANDS: [(Operand, Operand, &str); 10] = [
+    (Operand::Register, Operand::Constant, "a const"),
+    (Operand::Register, Operand::Absolute, "a #c01000"),
+    (Operand::Register, Operand::Relative, "a $10"),
+    (Operand::Register, Operand::Register, "a b"),
+    (Operand::Register, Operand::RegisterAddress, "a @b"),
+    (Operand::Absolute, Operand::Constant, "#c01100 const"),
+    (Operand::Absolute, Operand::Absolute, "#c01100 #c01000"),
+    (Operand::Absolute, Operand::Relative, "#c01100 $10"),
+    (Operand::Absolute, Operand::Register, "#c01100 b"),
+    (Operand::Absolute, Operand::RegisterAddress, "#c01100 @b"),
+];
+
+/// Executes `op a b` for every integer comparison, arithmetic, logic and shift instruction on a
+/// grid of operand values and the byte, short and int views of `A` and `B`, and compares `A` and
+/// `R` with the reference model
+fn check_operations() -> Vec<ModelMismatch> {
+    // creating a memory is slow compared to an instruction, so every case overwrites the code
+    let mut execution = Execution::test_harness(&[], &[]).unwrap();
+    let mut mismatches = Vec::new();
+    for (op_code, mnemonic) in OPERATIONS {
+        for (bits, view) in [(8, 0x10), (16, 0x20), (32, 0x00)] {
+            for a in OPERANDS {
+                for b in OPERANDS {
+                    let expected = model(mnemonic, bits, a, b);
+                    let actual = execute(&mut execution, op_code, view, a, b);
+                    let matches = match (&expected, &actual) {
+                        (Some(expected), Ok(actual)) => expected == actual,
+                        (None, Err(Tx8Error::DivisionByZero)) => true,
+                        _ => false,
+                    };
+                    if !matches {
+                        mismatches.push(ModelMismatch {
+                            mnemonic,
+                            operands: "a b",
+                            bits,
+                            a,
+                            b,
+                            expected,
+                            actual,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    mismatches
+}
+
+/// Checks the loads like `check_operations` for every source mode, with `A` and memory as
+/// destination
+fn check_loads() -> Vec<ModelMismatch> {
+    let mut execution = Execution::test_harness(&[], &[]).unwrap();
+    let mut mismatches = Vec::new();
+    for (op_code, mnemonic) in LOADS {
+        for (to, from, operands) in LOAD_OPERANDS {
+            for bits in [8, 16, 32] {
+                for a in OPERANDS {
+                    for b in OPERANDS {
+                        let expected = load_model(mnemonic, to, from, bits, a, b);
+                        let actual = execute_load(&mut execution, op_code, to, from, bits, a, b);
+                        if actual != Ok(expected) {
+                            mismatches.push(ModelMismatch {
+                                mnemonic,
+                                operands,
+                                bits,
+                                a,
+                                b,
+                                expected: Some(expected),
+                                actual,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    mismatches
+}
+
+/// Places the code at the usual code address and points `P` at it
+fn load_code(execution: &mut Execution, code: &[u8]) {
+    for (addr, byte) in (CODE..).zip(code) {
+        execution.memory_mut().write_byte(addr, *byte);
+    }
+    execution.set_register(Register::P, CODE);
+}
+
+fn execute(
+    execution: &mut Execution,
+    op_code: u8,
+    view: u8,
+    a: u32,
+    b: u32,
+) -> Result<(u32, u32), Tx8Error> {
+    // op a b with both parameters in register mode
+    load_code(execution, &[op_code, 0x66, view, view + 1]);
+    execution.set_register(Register::A, a);
+    execution.set_register(Register::B, b);
+    execution.set_register(Register::R, R_SENTINEL);
+    execution.next_step()?;
+    Ok((
+        execution.get_register(Register::A),
+        execution.get_register(Register::R),
+    ))
+}
+
+/// Encodes an operand, register views have the given size and constants the given width
+fn encode(operand: Operand, register: u8, bits: u32, val: u32) -> (u8, Vec<u8>) {
+    let view = match bits {
+        8 => 0x10,
+        16 => 0x20,
+        _ => 0x00,
+    };
+    match operand {
+        Operand::Constant => match bits {
+            8 => (0x1, vec![val as u8]),
+            16 => (0x2, (val as u16).to_le_bytes().to_vec()),
+            _ => (0x3, val.to_le_bytes().to_vec()),
+        },
+        Operand::Absolute => (0x4, val.to_le_bytes()[..3].to_vec()),
+        Operand::Relative => (0x5, vec![0x10, 0x00, 0x00]),
+        Operand::Register => (0x6, vec![view + register]),
+        // the pointer is always read from the whole register
+        Operand::RegisterAddress => (0x7, vec![register]),
+    }
+}
+
+fn execute_load(
+    execution: &mut Execution,
+    op_code: u8,
+    to: Operand,
+    from: Operand,
+    bits: u32,
+    a: u32,
+    b: u32,
+) -> Result<(u32, u32), Tx8Error> {
+    let (to_mode, to_bytes) = encode(to, 0, bits, DESTINATION);
+    let (from_mode, from_bytes) = match from {
+        Operand::Constant | Operand::Register => encode(from, 1, bits, b),
+        _ => encode(from, 1, bits, SOURCE),
+    };
+    let mut code = vec![op_code, to_mode << 4 | from_mode];
+    code.extend(to_bytes);
+    code.extend(from_bytes);
+    load_code(execution, &code);
+    execution.memory_mut().write_int(SOURCE, b);
+    execution.memory_mut().write_int(DESTINATION, a);
+    execution.set_register(Register::A, a);
+    execution.set_register(Register::B, b);
+    if from == Operand::RegisterAddress {
+        execution.set_register(Register::B, SOURCE);
+    }
+    execution.set_register(Register::O, OFFSET_BASE);
+    execution.set_register(Register::R, R_SENTINEL);
+    execution.next_step()?;
+    let result = match to {
+        Operand::Register => execution.get_register(Register::A),
+        _ => execution.memory().read_int(DESTINATION),
+    };
+    Ok((result, execution.get_register(Register::R)))
+}
+
+/// The expected destination and `R` after a load, following the rules of the spec: the value
+/// has the size of the constant or register view it comes from, `ld` and `lds` read one byte of
+/// memory and `lw` and `lws` four. `ld` and `lw` zero-extend it, `lds` and `lws` sign-extend it.
+/// A register view is written completely, memory gets as many bytes as the value has, except for
+/// `lw` and `lws` which always write four.
+fn load_model(mnemonic: &str, to: Operand, from: Operand, bits: u32, a: u32, b: u32) -> (u32, u32) {
+    let word = mnemonic.starts_with("lw");
+    let width = match from {
+        Operand::Constant | Operand::Register => bits,
+        _ if word => 32,
+        _ => 8,
+    };
+    let val = sign_extend(b as u64, width) as u32;
+    let val = match mnemonic.ends_with('s') {
+        true => val,
+        false => b & (u64::MAX >> (64 - width)) as u32,
+    };
+    let written = match to {
+        Operand::Register => bits,
+        _ if word => 32,
+        _ => width,
+    };
+    let mask = (u64::MAX >> (64 - written)) as u32;
+    ((a & !mask) | (val & mask), R_SENTINEL)
+}
+
+/// The expected `A` and `R` after `op a b` on the views with the given number of bits
+fn model(mnemonic: &str, bits: u32, a: u32, b: u32) -> Option<(u32, u32)> {
+    let mask = (u64::MAX >> (64 - bits)) as u32;
+    let (x, y) = ((a & mask) as u64, (b & mask) as u64);
+    let (sx, sy) = (sign_extend(x, bits), sign_extend(y, bits));
+    let fits_signed = |val: i64| val >= -(1 << (bits - 1)) && val < 1 << (bits - 1);
+    let flags = |carry: bool, signed: bool| carry as u32 | (signed as u32) << 1;
+    let shift = y % bits as u64;
+
+    let (res, r) = match mnemonic {
+        // comparisons only write `R`
+        "cmp" => return Some((a, sx.cmp(&sy) as i32 as u32)),
+        "ucmp" => return Some((a, x.cmp(&y) as i32 as u32)),
+        "add" | "uadd" => (x + y, flags(x + y > mask as u64, !fits_signed(sx + sy))),
+        "sub" | "usub" => (x.wrapping_sub(y), flags(x < y, !fits_signed(sx - sy))),
+        "mul" => {
+            let product = sx * sy;
+            (product as u64, (product >> 32) as u32)
+        }
+        "umul" => (x * y, ((x * y) >> 32) as u32),
+        "div" | "mod" if sy == 0 => return None,
+        "udiv" | "umod" if y == 0 => return None,
+        // the quotient of the smallest integer and -1 wraps around
+        "div" => ((sx / sy) as u64, (sx % sy) as u32),
+        "mod" => ((sx % sy) as u64, (sx / sy) as u32),
+        "udiv" => (x / y, (x % y) as u32),
+        "umod" => (x % y, (x / y) as u32),
+        "and" => (x & y, R_SENTINEL),
+        "or" => (x | y, R_SENTINEL),
+        "nand" => (!(x & y), R_SENTINEL),
+        "xor" => (x ^ y, R_SENTINEL),
+        "slr" => (x >> shift, (x & ((1 << shift) - 1)) as u32),
+        "sar" => ((sx >> shift) as u64, (x & ((1 << shift) - 1)) as u32),
+        "sll" if shift == 0 => (x, 0),
+        "sll" => (x << shift, (x >> (bits as u64 - shift)) as u32),
+        "ror" => (
+            (x >> shift) | (x << ((bits as u64 - shift) % bits as u64)),
+            R_SENTINEL,
+        ),
+        "rol" => (
+            (x << shift) | (x >> ((bits as u64 - shift) % bits as u64)),
+            R_SENTINEL,
+        ),
+        _ => unreachable!(),
+    };
+    Some(((a & !mask) | (res as u32 & mask), r))
+}
+
+fn sign_extend(val: u64, bits: u32) -> i64 {
+    ((val << (64 - bits)) as i64) >> (64 - bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_match_reference_model() {
+        assert_no_mismatches(check_operations());
+    }
+
+    #[test]
+    fn loads_match_reference_model() {
+        assert_no_mismatches(check_loads());
+    }
+
+    fn assert_no_mismatches(mismatches: Vec<ModelMismatch>) {
+        assert!(
+            mismatches.is_empty(),
+            "{} mismatches, e.g. {:#?}",
+            mismatches.len(),
+            &mismatches[..mismatches.len().min(5)]
+        );
+    }
+}
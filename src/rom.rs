@@ -0,0 +1,421 @@
+use crate::{crc::crc32, hardware::MB_4, Tx8Error};
+
+/// A parsed tx8 rom file
+#[derive(Clone, Debug, Default)]
+pub struct Rom<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub code: &'a [u8],
+    pub sections: Vec<DataSection<'a>>,
+    /// Offsets into the code of 32 bit addresses which point into the code, assuming it is loaded
+    /// at the usual code address. They are adjusted when the code is loaded somewhere else.
+    pub relocations: Vec<u32>,
+    /// Debug info mapping offsets into the code to the source line the instruction starting there
+    /// was assembled from, sorted by offset
+    pub lines: Vec<(u32, u32)>,
+}
+
+impl<'a> Rom<'a> {
+    /// Creates an unnamed rom consisting only of code
+    pub fn from_code(code: &'a [u8]) -> Self {
+        Rom {
+            name: "",
+            description: "",
+            code,
+            sections: Vec::new(),
+            relocations: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Address execution starts at, the first byte of the code loaded at the usual code address
+    pub fn entry_point(&self) -> u32 {
+        MB_4 as u32
+    }
+
+    /// Returns the source line of the instruction starting at `offset` in the code, if the rom has
+    /// debug info for it
+    pub fn line_at(&self, offset: u32) -> Option<u32> {
+        self.lines
+            .binary_search_by_key(&offset, |(offset, _)| *offset)
+            .ok()
+            .map(|index| self.lines[index].1)
+    }
+}
+
+/// Initialized data which is copied to a fixed address when the rom is loaded
+#[derive(Clone, Copy, Debug)]
+pub struct DataSection<'a> {
+    pub name: &'a str,
+    pub address: u32,
+    pub data: &'a [u8],
+}
+
+/// Options controlling how strictly `parse_rom_with` validates a rom
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    /// Reject roms without a CRC32 in the header. Otherwise a zero CRC32 field is accepted without
+    /// verifying the binary data, as written by older tools.
+    pub require_crc: bool,
+    /// Ignore bytes after the last data section, e.g. padding of block-aligned files. By default,
+    /// the file length must match the header exactly.
+    pub allow_trailing_data: bool,
+}
+
+/// The fixed 64 byte header at the start of a rom file
+#[derive(Clone, Copy, Debug)]
+pub struct RomHeader {
+    pub name_length: u8,
+    pub description_length: u16,
+    /// Length of the binary data declared in the header
+    pub data_length: u32,
+    /// XOR of all other header bytes
+    pub checksum: u8,
+    /// Whether `checksum` matches the other header bytes
+    pub checksum_valid: bool,
+    pub section_count: u8,
+    /// CRC32 of everything after the header up to the end of the line table, 0 if the rom has
+    /// none
+    pub crc: u32,
+    pub relocation_count: u16,
+    pub line_count: u16,
+    /// Bytes 21 to 63, all zero in roms written by current tools
+    pub reserved: [u8; 43],
+}
+
+/// Reads the header without looking at the rest of the file
+pub fn parse_header(data: &[u8]) -> Result<RomHeader, Tx8Error> {
+    // Ensure file is at least 64 bytes long and magic bytes match
+    if data.len() < 64 || &data[0..4] != "TX8\0".as_bytes() {
+        return Err(Tx8Error::ParseError);
+    }
+    Ok(RomHeader {
+        name_length: data[4],
+        description_length: u16::from_le_bytes(data[5..7].try_into()?),
+        data_length: u32::from_le_bytes(data[7..11].try_into()?),
+        checksum: data[11],
+        checksum_valid: data[..64].iter().fold(0, |acc, byte| acc ^ byte) == 0,
+        section_count: data[12],
+        crc: u32::from_le_bytes(data[13..17].try_into()?),
+        relocation_count: u16::from_le_bytes(data[17..19].try_into()?),
+        line_count: u16::from_le_bytes(data[19..21].try_into()?),
+        reserved: data[21..64].try_into()?,
+    })
+}
+
+pub fn parse_rom(data: &[u8]) -> Result<Rom<'_>, Tx8Error> {
+    parse_rom_with(data, ParseOptions::default())
+}
+
+pub fn parse_rom_with(data: &[u8], options: ParseOptions) -> Result<Rom<'_>, Tx8Error> {
+    let header = parse_header(data)?;
+    // assign length
+    let program_name_length = header.name_length as usize;
+    let description_length = header.description_length as usize;
+    let data_length = header.data_length as usize;
+    let section_count = header.section_count;
+    let crc = header.crc;
+    let relocation_count = header.relocation_count as usize;
+    let line_count = header.line_count as usize;
+
+    let program_name_end = 64 + program_name_length;
+    let description_end = program_name_end + description_length;
+    let data_end = description_end + data_length;
+
+    if data.len() < data_end {
+        return Err(Tx8Error::ParseError);
+    }
+
+    let name = std::str::from_utf8(&data[64..program_name_end])?;
+    let description = std::str::from_utf8(&data[program_name_end..description_end])?;
+    let code = &data[description_end..data_end];
+
+    let mut sections = Vec::new();
+    let mut ptr = data_end;
+    for _ in 0..section_count {
+        let (section, len) = parse_section(&data[ptr..])?;
+        sections.push(section);
+        ptr += len;
+    }
+
+    let table = data
+        .get(ptr..ptr + relocation_count * 4)
+        .ok_or(Tx8Error::ParseError)?;
+    let relocations = table
+        .chunks(4)
+        .map(|offset| u32::from_le_bytes(offset.try_into().unwrap()))
+        .collect::<Vec<u32>>();
+    if relocations
+        .iter()
+        .any(|&offset| offset as usize + 4 > code.len())
+    {
+        return Err(Tx8Error::ParseError);
+    }
+    ptr += table.len();
+
+    let table = data
+        .get(ptr..ptr + line_count * 8)
+        .ok_or(Tx8Error::ParseError)?;
+    let lines = table
+        .chunks(8)
+        .map(|entry| {
+            let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let line = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+            (offset, line)
+        })
+        .collect::<Vec<(u32, u32)>>();
+    if lines
+        .iter()
+        .any(|&(offset, _)| offset as usize >= code.len())
+        || lines.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+    {
+        return Err(Tx8Error::ParseError);
+    }
+    ptr += table.len();
+
+    // everything was read, so only trailing bytes can be left
+    if data.len() != ptr && !options.allow_trailing_data {
+        return Err(Tx8Error::TrailingData);
+    }
+    if (crc != 0 || options.require_crc) && crc != crc32(&data[64..ptr]) {
+        return Err(Tx8Error::ChecksumMismatch);
+    }
+
+    Ok(Rom {
+        name,
+        description,
+        code,
+        sections,
+        relocations,
+        lines,
+    })
+}
+
+/// Assembles the binary rom file format from code and data sections
+#[derive(Clone, Debug, Default)]
+pub struct RomBuilder<'a> {
+    rom: Rom<'a>,
+}
+
+impl<'a> RomBuilder<'a> {
+    pub fn new(code: &'a [u8]) -> Self {
+        RomBuilder {
+            rom: Rom::from_code(code),
+        }
+    }
+
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.rom.name = name;
+        self
+    }
+
+    pub fn description(mut self, description: &'a str) -> Self {
+        self.rom.description = description;
+        self
+    }
+
+    pub fn section(mut self, name: &'a str, address: u32, data: &'a [u8]) -> Self {
+        self.rom.sections.push(DataSection {
+            name,
+            address,
+            data,
+        });
+        self
+    }
+
+    /// Marks the 32 bit address at `offset` in the code as pointing into the code
+    pub fn relocation(mut self, offset: u32) -> Self {
+        self.rom.relocations.push(offset);
+        self
+    }
+
+    /// Records that the instruction at `offset` in the code was assembled from source line `line`
+    pub fn line(mut self, offset: u32, line: u32) -> Self {
+        self.rom.lines.push((offset, line));
+        self
+    }
+
+    /// Writes the header including the CRC32 of everything following it, followed by the contents
+    pub fn build(&self) -> Result<Vec<u8>, Tx8Error> {
+        let rom = &self.rom;
+        let name_length: u8 = rom.name.len().try_into()?;
+        let description_length: u16 = rom.description.len().try_into()?;
+        let data_length: u32 = rom.code.len().try_into()?;
+        let section_count: u8 = rom.sections.len().try_into()?;
+        let relocation_count: u16 = rom.relocations.len().try_into()?;
+        let line_count: u16 = rom.lines.len().try_into()?;
+        let mut lines = rom.lines.clone();
+        lines.sort_unstable_by_key(|(offset, _)| *offset);
+
+        let mut bytes = vec![0; 64];
+        bytes[0..4].copy_from_slice(b"TX8\0");
+        bytes[4] = name_length;
+        bytes[5..7].copy_from_slice(&description_length.to_le_bytes());
+        bytes[7..11].copy_from_slice(&data_length.to_le_bytes());
+        bytes[12] = section_count;
+        bytes[17..19].copy_from_slice(&relocation_count.to_le_bytes());
+        bytes[19..21].copy_from_slice(&line_count.to_le_bytes());
+
+        bytes.extend_from_slice(rom.name.as_bytes());
+        bytes.extend_from_slice(rom.description.as_bytes());
+        bytes.extend_from_slice(rom.code);
+        for section in &rom.sections {
+            let section_name_length: u8 = section.name.len().try_into()?;
+            let section_length: u32 = section.data.len().try_into()?;
+            bytes.push(section_name_length);
+            bytes.extend_from_slice(section.name.as_bytes());
+            bytes.extend_from_slice(&section.address.to_le_bytes());
+            bytes.extend_from_slice(&section_length.to_le_bytes());
+            bytes.extend_from_slice(section.data);
+        }
+        for offset in &rom.relocations {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        for (offset, line) in lines {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&line.to_le_bytes());
+        }
+
+        // the header checksum covers the CRC32, so it is computed last
+        let crc = crc32(&bytes[64..]);
+        bytes[13..17].copy_from_slice(&crc.to_le_bytes());
+        bytes[11] = bytes[..64].iter().fold(0, |acc, byte| acc ^ byte);
+        Ok(bytes)
+    }
+}
+
+/// Converts a hex listing into a binary rom. Everything after a `;` up to the end of the line is
+/// a comment, whitespace is ignored and the remaining hex digits are read in pairs.
+pub fn parse_rom_text(src: &str) -> Result<Vec<u8>, Tx8Error> {
+    let digits = src
+        .lines()
+        .flat_map(|line| line.split(';').next().unwrap_or("").chars())
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8).ok_or(Tx8Error::ParseError))
+        .collect::<Result<Vec<u8>, Tx8Error>>()?;
+    if digits.len() % 2 != 0 {
+        return Err(Tx8Error::ParseError);
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect())
+}
+
+fn parse_section(data: &[u8]) -> Result<(DataSection<'_>, usize), Tx8Error> {
+    let name_length = *data.first().ok_or(Tx8Error::ParseError)? as usize;
+    let name_end = 1 + name_length;
+    let name = std::str::from_utf8(data.get(1..name_end).ok_or(Tx8Error::ParseError)?)?;
+    let header = data
+        .get(name_end..name_end + 8)
+        .ok_or(Tx8Error::ParseError)?;
+    let address = u32::from_le_bytes(header[0..4].try_into()?);
+    let length = u32::from_le_bytes(header[4..8].try_into()?) as usize;
+    let data_start = name_end + 8;
+    let data = data
+        .get(data_start..data_start + length)
+        .ok_or(Tx8Error::ParseError)?;
+    Ok((
+        DataSection {
+            name,
+            address,
+            data,
+        },
+        data_start + length,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build() -> Vec<u8> {
+        RomBuilder::new(&[0x01, 0x00])
+            .name("crc")
+            .section("data", 0xc01000, b"abc")
+            .line(0, 1)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn correct_roms_pass_the_crc_check() {
+        let rom = build();
+        assert!(parse_header(&rom).unwrap().checksum_valid);
+        let options = ParseOptions {
+            require_crc: true,
+            ..Default::default()
+        };
+        assert_eq!(parse_rom_with(&rom, options).unwrap().code, [0x01, 0x00]);
+    }
+
+    #[test]
+    fn flipped_bytes_after_the_header_fail_the_crc_check() {
+        let rom = build();
+        // the name, the code, the section data and the line table
+        for index in [64, 67, rom.len() - 11, rom.len() - 1] {
+            let mut corrupted = rom.clone();
+            corrupted[index] ^= 0x01;
+            assert_eq!(
+                parse_rom(&corrupted).unwrap_err(),
+                Tx8Error::ChecksumMismatch,
+                "{index}"
+            );
+        }
+    }
+
+    #[test]
+    fn roms_without_a_crc_pass_unless_it_is_required() {
+        let mut rom = build();
+        rom[13..17].fill(0);
+        rom[11] = 0;
+        rom[11] = rom[..64].iter().fold(0, |acc, byte| acc ^ byte);
+        assert!(parse_rom(&rom).is_ok());
+        let options = ParseOptions {
+            require_crc: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_rom_with(&rom, options).unwrap_err(),
+            Tx8Error::ChecksumMismatch
+        );
+    }
+
+    #[test]
+    fn parses_annotated_hex_listings() {
+        let listing = "\
+            ; header magic\n\
+            54 58 38 00\n\
+            0e30 ; sys\n\
+            DEAD beef ; mixed case\n";
+        assert_eq!(
+            parse_rom_text(listing).unwrap(),
+            [0x54, 0x58, 0x38, 0x00, 0x0e, 0x30, 0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(parse_rom_text("0e 3"), Err(Tx8Error::ParseError));
+        assert_eq!(parse_rom_text("0g"), Err(Tx8Error::ParseError));
+    }
+
+    #[test]
+    fn trailing_data_is_rejected_unless_allowed() {
+        let rom = build();
+        let lenient = ParseOptions {
+            allow_trailing_data: true,
+            ..Default::default()
+        };
+        assert!(parse_rom(&rom).is_ok());
+        assert!(parse_rom_with(&rom, lenient).is_ok());
+
+        let mut padded = rom.clone();
+        padded.extend([0; 16]);
+        assert_eq!(parse_rom(&padded).unwrap_err(), Tx8Error::TrailingData);
+        assert_eq!(parse_rom_with(&padded, lenient).unwrap().code, [0x01, 0x00]);
+
+        let truncated = &rom[..rom.len() - 1];
+        assert_eq!(parse_rom(truncated).unwrap_err(), Tx8Error::ParseError);
+        assert_eq!(
+            parse_rom_with(truncated, lenient).unwrap_err(),
+            Tx8Error::ParseError
+        );
+    }
+}
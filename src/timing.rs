@@ -0,0 +1,49 @@
+use crate::instruction::Instruction;
+
+/// Approximate cost in cycles of the different kinds of instructions
+#[derive(Clone, Copy, Debug)]
+pub struct CycleCosts {
+    /// Cost of every instruction not covered by one of the other categories
+    pub base: u64,
+    pub mul: u64,
+    pub div: u64,
+    /// Trigonometric, exponential, logarithmic, power and square root operations
+    pub transcendental: u64,
+    pub sys_call: u64,
+}
+
+impl Default for CycleCosts {
+    fn default() -> Self {
+        CycleCosts {
+            base: 1,
+            mul: 3,
+            div: 20,
+            transcendental: 40,
+            sys_call: 10,
+        }
+    }
+}
+
+impl CycleCosts {
+    pub fn cost(&self, instr: &Instruction) -> u64 {
+        match instr {
+            Instruction::Mul(..) | Instruction::Fma(..) => self.mul,
+            Instruction::DivMod(..) => self.div,
+            Instruction::Sin(..)
+            | Instruction::Cos(..)
+            | Instruction::Tan(..)
+            | Instruction::ArcSin(..)
+            | Instruction::ArcCos(..)
+            | Instruction::ArcTan(..)
+            | Instruction::ArcTan2(..)
+            | Instruction::Sqrt(..)
+            | Instruction::Pow(..)
+            | Instruction::Exp(..)
+            | Instruction::Log(..)
+            | Instruction::Log2(..)
+            | Instruction::Log10(..) => self.transcendental,
+            Instruction::SysCall(_) => self.sys_call,
+            _ => self.base,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+use tx8_core::RomBuilder;
+
+/// Runs the disasm subcommand on a small rom with the given options and returns the listing
+fn disasm(options: &[&str]) -> String {
+    // add a 5u8; jmp 0x400000u32; hlt
+    let code = [
+        0x22, 0x61, 0x00, 0x05, 0x02, 0x30, 0x00, 0x00, 0x40, 0x00, 0x00,
+    ];
+    let rom = RomBuilder::new(&code)
+        .name("disasm test")
+        .line(0, 3)
+        .build()
+        .unwrap();
+    let name = format!("tx8-disasm-{}-{}.tx8", std::process::id(), options.join(""));
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, &rom).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tx8-core"))
+        .arg("disasm")
+        .args(options)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn disasm_lists_the_code() {
+    assert_eq!(
+        disasm(&[]),
+        "400000  add a 5u8  ; line 3\n400004  jmp 4194304\n40000a  hlt\n"
+    );
+}
+
+#[test]
+fn disasm_shows_labels_and_bytes() {
+    assert_eq!(
+        disasm(&["--labels"]),
+        ":l400000\n400000  add a 5u8  ; line 3\n400004  jmp :l400000\n40000a  hlt\n"
+    );
+    let listing = disasm(&["--bytes"]);
+    assert!(
+        listing.contains("400004  02 30 00 00 40 00             jmp 4194304\n"),
+        "{listing}"
+    );
+}
+
+#[test]
+fn disasm_lists_only_the_given_range() {
+    assert_eq!(
+        disasm(&["--start", "400004", "--end", "40000a"]),
+        "400004  jmp 4194304\n"
+    );
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+use tx8_core::RomBuilder;
+
+#[test]
+fn info_prints_the_header_and_the_instruction_counts() {
+    // hlteq; add a 1u8; hlt
+    let code = [0x98, 0x22, 0x61, 0x00, 0x01, 0x00];
+    let rom = RomBuilder::new(&code)
+        .name("info test")
+        .description("counts three instructions")
+        .section("data", 0xc01000, b"abc")
+        .build()
+        .unwrap();
+    let path = std::env::temp_dir().join(format!("tx8-info-{}.tx8", std::process::id()));
+    std::fs::write(&path, &rom).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tx8-core"))
+        .arg("info")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for line in [
+        "Name:            info test",
+        "Description:     counts three instructions",
+        &format!("File size:       {} bytes", rom.len()),
+        "Header checksum: ",
+        "Data sections:   1",
+        "Entry point:     0x400000",
+        "  arithmetic:    1",
+        "  control flow:  2",
+        "  other:         0",
+        "  invalid bytes: 0",
+    ] {
+        assert!(stdout.contains(line), "{line:?} missing in\n{stdout}");
+    }
+}